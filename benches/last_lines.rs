@@ -0,0 +1,57 @@
+//! Times printing the last 10 lines of a large file: `BackwardsReader`, which
+//! `tail -n NUM` uses, against reading every line forward and keeping the last
+//! ones. Run with `cargo bench --bench last_lines`, setting `TAIL_BENCH_MIB`
+//! for a file other than 1024 MiB.
+
+extern crate tail;
+
+use std::collections::VecDeque;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::time::Instant;
+use tail::BackwardsReader;
+
+fn main() {
+    let mib: u64 = env::var("TAIL_BENCH_MIB").ok().and_then(|mib| mib.parse().ok()).unwrap_or(1024);
+    let path = env::temp_dir().join(format!("tail-bench-last-{}.log", std::process::id()));
+    write_log(&path, mib << 20).unwrap();
+    // Both read it from the page cache, not the first from the disk
+    io::copy(&mut File::open(&path).unwrap(), &mut io::sink()).unwrap();
+
+    let start = Instant::now();
+    let mut last = VecDeque::with_capacity(10);
+    for line in BufReader::new(File::open(&path).unwrap()).lines() {
+        if last.len() == 10 {
+            last.pop_front();
+        }
+        last.push_back(line.unwrap());
+    }
+    let mut forward = Vec::new();
+    for line in last {
+        writeln!(forward, "{}", line).unwrap();
+    }
+    println!("forward lines():  {:>8.2?}", start.elapsed());
+
+    let start = Instant::now();
+    let mut fd = BufReader::new(File::open(&path).unwrap());
+    let mut backward = Vec::new();
+    BackwardsReader::new(10, &mut fd, b'\n').unwrap().read_all(&mut backward).unwrap();
+    println!("BackwardsReader:  {:>8.2?}", start.elapsed());
+
+    assert_eq!(forward, backward);
+    fs::remove_file(&path).unwrap();
+}
+
+/// Fills `path` with numbered log lines up to `size` bytes
+fn write_log(path: &std::path::Path, size: u64) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    let (mut written, mut lines) = (0, 0);
+    while written < size {
+        let line = format!("2018-02-11T10:00:00 INFO request {} served in 12ms\n", lines);
+        out.write_all(line.as_bytes())?;
+        written += line.len() as u64;
+        lines += 1;
+    }
+    out.flush()
+}
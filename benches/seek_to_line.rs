@@ -0,0 +1,52 @@
+//! Times skipping to `-n +NUM` on a large file: `seek_to_line` against the
+//! `lines().skip()` it replaced. Run with `cargo bench --bench seek_to_line`,
+//! setting `TAIL_BENCH_MIB` for a file other than 1024 MiB.
+
+extern crate tail;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::time::Instant;
+use tail::seek_to_line;
+
+fn main() {
+    let mib: u64 = env::var("TAIL_BENCH_MIB").ok().and_then(|mib| mib.parse().ok()).unwrap_or(1024);
+    let path = env::temp_dir().join(format!("tail-bench-seek-{}.log", std::process::id()));
+    let lines = write_log(&path, mib << 20).unwrap();
+    // Start near the end, so nearly all of the file is skipped
+    let n = lines - 10;
+    // Both read it from the page cache, not the first from the disk
+    io::copy(&mut File::open(&path).unwrap(), &mut io::sink()).unwrap();
+
+    let start = Instant::now();
+    let mut rest = BufReader::new(File::open(&path).unwrap()).lines().skip(n as usize);
+    let mut skipped = 0;
+    for line in &mut rest {
+        skipped += line.unwrap().len() + 1;
+    }
+    println!("lines().skip({}):  {:>8.2?}  {} bytes after", n, start.elapsed(), skipped);
+
+    let start = Instant::now();
+    let mut fd = File::open(&path).unwrap();
+    seek_to_line(&mut fd, n as usize, b'\n').unwrap();
+    let copied = io::copy(&mut fd, &mut io::sink()).unwrap();
+    println!("seek_to_line({}): {:>8.2?}  {} bytes after", n, start.elapsed(), copied);
+
+    assert_eq!(skipped as u64, copied);
+    fs::remove_file(&path).unwrap();
+}
+
+/// Fills `path` with numbered log lines up to `size` bytes, returning how many
+fn write_log(path: &std::path::Path, size: u64) -> io::Result<u64> {
+    let mut out = BufWriter::new(File::create(path)?);
+    let (mut written, mut lines) = (0, 0);
+    while written < size {
+        let line = format!("2018-02-11T10:00:00 INFO request {} served in 12ms\n", lines);
+        out.write_all(line.as_bytes())?;
+        written += line.len() as u64;
+        lines += 1;
+    }
+    out.flush()?;
+    Ok(lines)
+}
@@ -0,0 +1,64 @@
+//! `--checksum`: a digest of the bytes written for each file.
+
+use std::io::{self, Write};
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Passes writes through to `inner`, keeping a 64-bit FNV-1a hash of every
+/// byte that was accepted
+pub struct ChecksumWriter<W: Write> {
+    inner: W,
+    hash: u64,
+}
+
+impl<W: Write> ChecksumWriter<W> {
+    pub fn new(inner: W) -> Self {
+        ChecksumWriter { inner, hash: FNV_OFFSET_BASIS }
+    }
+
+    pub fn digest(&self) -> String {
+        format!("fnv1a64:{:016x}", self.hash)
+    }
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        for byte in &buf[..written] {
+            self.hash ^= u64::from(*byte);
+            self.hash = self.hash.wrapping_mul(FNV_PRIME);
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn digest(writes: &[&[u8]]) -> String {
+        let mut writer = ChecksumWriter::new(Vec::new());
+        for data in writes {
+            writer.write_all(data).unwrap();
+        }
+        assert_eq!(writer.inner, writes.concat());
+        writer.digest()
+    }
+
+    #[test]
+    fn matches_the_fnv1a_test_vectors() {
+        assert_eq!(digest(&[]), "fnv1a64:cbf29ce484222325");
+        assert_eq!(digest(&[b"a"]), "fnv1a64:af63dc4c8601ec8c");
+        assert_eq!(digest(&[b"foobar"]), "fnv1a64:85944171f73967e8");
+    }
+
+    #[test]
+    fn is_the_same_however_the_bytes_are_split() {
+        assert_eq!(digest(&[b"foo", b"", b"bar"]), digest(&[b"foobar"]));
+    }
+}
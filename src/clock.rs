@@ -0,0 +1,70 @@
+//! Time source for the time-based parts of following, so they can be driven
+//! without really waiting.
+
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+pub trait Clock {
+    fn now(&self) -> Instant;
+    fn sleep(&self, duration: Duration);
+}
+
+/// The real clock, used by default
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        ::std::thread::sleep(duration);
+    }
+}
+
+/// A clock that only moves when told to. Sleeping advances it immediately.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate tail;
+/// # use std::time::Duration;
+/// # use tail::clock::{Clock, MockClock};
+/// let clock = MockClock::new();
+/// let start = clock.now();
+/// clock.sleep(Duration::from_secs(30));
+/// clock.advance(Duration::from_secs(5));
+/// assert_eq!(clock.now() - start, Duration::from_secs(35));
+/// ```
+#[derive(Debug)]
+pub struct MockClock {
+    start: Instant,
+    elapsed: Cell<Duration>,
+}
+
+impl MockClock {
+    pub fn new() -> Self {
+        MockClock { start: Instant::now(), elapsed: Cell::new(Duration::from_secs(0)) }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        self.elapsed.set(self.elapsed.get() + duration);
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        MockClock::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed.get()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+}
@@ -0,0 +1,187 @@
+//! Parsing for `--config` files.
+//!
+//! A config file is a list of `key = value` lines. Keys before the first
+//! `[PATH]` section apply to every file; each section adds PATH to the files
+//! to tail, and any keys under it override the defaults for that file only.
+//! Blank lines and lines starting with `#` are ignored.
+//!
+//! ```text
+//! follow = true
+//! lines = 20
+//!
+//! [/var/log/app.log]
+//! lines = 100
+//!
+//! [/var/log/db.log]
+//! filter = ERROR|WARN
+//! prefix = [db]
+//! ```
+//!
+//! Options given on the command line take precedence over the config file,
+//! and `--no-follow` turns off its `follow`.
+
+use std::fs::File;
+use std::io::Read;
+use tail::io_error_message;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FileOptions {
+    /// Same as `-n`
+    pub lines: Option<String>,
+    /// Same as `--head-tail`
+    pub head_tail: Option<String>,
+    /// Same as `--filter`
+    pub filter: Option<String>,
+    /// Same as `--prefix`
+    pub prefix: Option<String>,
+}
+
+impl FileOptions {
+    /// Each option as set by the first of `layers` that sets it, e.g. the
+    /// command line's, then the file's section's, then the defaults
+    pub fn merged(layers: &[&FileOptions]) -> FileOptions {
+        let first = |option: fn(&FileOptions) -> &Option<String>| {
+            layers.iter().filter_map(|layer| option(layer).clone()).next()
+        };
+        FileOptions {
+            lines: first(|o| &o.lines),
+            head_tail: first(|o| &o.head_tail),
+            filter: first(|o| &o.filter),
+            prefix: first(|o| &o.prefix),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct FileConfig {
+    pub path: String,
+    pub options: FileOptions,
+}
+
+#[derive(Debug, Default)]
+pub struct Config {
+    /// Same as `-f`
+    pub follow: bool,
+    pub defaults: FileOptions,
+    pub files: Vec<FileConfig>,
+}
+
+impl Config {
+    /// Whether to follow, given whether `-f` or `-F` and `--no-follow` are on
+    /// the command line
+    pub fn follows(&self, follow: bool, no_follow: bool) -> bool {
+        follow || (self.follow && !no_follow)
+    }
+}
+
+pub fn load(path: &str) -> Result<Config, String> {
+    let mut contents = String::new();
+    File::open(path)
+        .and_then(|mut fd| fd.read_to_string(&mut contents))
+        .map_err(|e| format!("cannot read config file '{}': {}", path, io_error_message(&e)))?;
+    parse(&contents).map_err(|e| format!("{}:{}", path, e))
+}
+
+fn parse(contents: &str) -> Result<Config, String> {
+    let mut config = Config::default();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('[') && line.ends_with(']') {
+            let path = line[1..line.len() - 1].trim();
+            if path.is_empty() {
+                return Err(format!("{}: empty file name in section header", i + 1));
+            }
+            config.files.push(FileConfig { path: path.to_string(), options: FileOptions::default() });
+            continue;
+        }
+
+        let mut parts = line.splitn(2, '=');
+        let key = parts.next().unwrap().trim();
+        let value = match parts.next() {
+            Some(value) => value.trim().to_string(),
+            None => return Err(format!("{}: expected 'key = value', found '{}'", i + 1, line)),
+        };
+        if key == "follow" {
+            if !config.files.is_empty() {
+                return Err(format!("{}: follow can't be set per file", i + 1));
+            }
+            config.follow = match value.as_str() {
+                "true" => true,
+                "false" => false,
+                _ => return Err(format!("{}: follow must be true or false, found '{}'", i + 1, value)),
+            };
+            continue;
+        }
+
+        let options = match config.files.last_mut() {
+            Some(file) => &mut file.options,
+            None => &mut config.defaults,
+        };
+        match key {
+            "lines" => options.lines = Some(value),
+            "head-tail" => options.head_tail = Some(value),
+            "filter" => options.filter = Some(value),
+            "prefix" => options.prefix = Some(value),
+            _ => return Err(format!("{}: unknown key '{}'", i + 1, key)),
+        }
+    }
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(lines: Option<&str>, filter: Option<&str>, prefix: Option<&str>) -> FileOptions {
+        FileOptions {
+            lines: lines.map(String::from),
+            head_tail: None,
+            filter: filter.map(String::from),
+            prefix: prefix.map(String::from),
+        }
+    }
+
+    #[test]
+    fn sections_override_the_defaults() {
+        let config = parse("follow = true\nlines = 20\nprefix = > \n\n# the app\n[app.log]\nlines = 100\nfilter = ERROR\n\n[db.log]\nprefix = [db]\n").unwrap();
+        assert!(config.follow);
+        assert_eq!(config.defaults, options(Some("20"), None, Some(">")));
+        assert_eq!(config.files.len(), 2);
+        assert_eq!(config.files[0].path, "app.log");
+        assert_eq!(config.files[0].options, options(Some("100"), Some("ERROR"), None));
+        assert_eq!(config.files[1].path, "db.log");
+        assert_eq!(config.files[1].options, options(None, None, Some("[db]")));
+    }
+
+    #[test]
+    fn the_command_line_wins() {
+        let cli = options(None, Some("WARN"), None);
+        let section = options(Some("100"), Some("ERROR"), None);
+        let defaults = options(Some("20"), None, Some("> "));
+        assert_eq!(FileOptions::merged(&[&cli, &section, &defaults]),
+                   options(Some("100"), Some("WARN"), Some("> ")));
+        assert_eq!(FileOptions::merged(&[&cli, &defaults]), options(Some("20"), Some("WARN"), Some("> ")));
+    }
+
+    #[test]
+    fn no_follow_turns_off_the_config_files_follow() {
+        let config = parse("follow = true\n").unwrap();
+        assert!(config.follows(false, false));
+        assert!(!config.follows(false, true));
+        assert!(config.follows(true, true));
+        let config = parse("follow = false\n").unwrap();
+        assert!(!config.follows(false, false));
+        assert!(config.follows(true, false));
+    }
+
+    #[test]
+    fn reports_the_line_of_a_mistake() {
+        assert_eq!(parse("lines = 5\nbogus = 1\n").unwrap_err(), "2: unknown key 'bogus'");
+        assert_eq!(parse("[a.log]\nfollow = true\n").unwrap_err(), "2: follow can't be set per file");
+        assert_eq!(parse("[ ]\n").unwrap_err(), "1: empty file name in section header");
+        assert_eq!(parse("lines\n").unwrap_err(), "1: expected 'key = value', found 'lines'");
+    }
+}
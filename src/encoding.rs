@@ -0,0 +1,184 @@
+//! Decoding of non-UTF-8 text for `--encoding`.
+//!
+//! `Encoding::sniff` guesses an encoding from the start of a file using, in
+//! order:
+//!
+//! 1. a byte order mark (UTF-8, UTF-16LE or UTF-16BE),
+//! 2. every other byte being NUL, which is how mostly-ASCII UTF-16 without a
+//!    BOM looks,
+//! 3. whether the sample is valid UTF-8,
+//!
+//! and otherwise falls back to latin-1, which can decode any byte sequence.
+//!
+//! `Utf8Decoder` is for text that arrives in pieces, e.g. lines appended to a
+//! followed file, where a character may be split between two reads.
+
+pub const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// How many bytes from the start of a file `Encoding::sniff` should be given
+pub const SNIFF_LEN: usize = 4096;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+}
+
+impl Encoding {
+    /// Parses the names accepted by `--encoding`
+    pub fn from_name(name: &str) -> Option<Encoding> {
+        match name.to_lowercase().as_str() {
+            "utf-8" | "utf8" => Some(Encoding::Utf8),
+            "utf-16le" | "utf16le" => Some(Encoding::Utf16Le),
+            "utf-16be" | "utf16be" => Some(Encoding::Utf16Be),
+            "latin-1" | "latin1" | "iso-8859-1" => Some(Encoding::Latin1),
+            _ => None,
+        }
+    }
+
+    pub fn sniff(sample: &[u8]) -> Encoding {
+        if sample.starts_with(UTF8_BOM) {
+            return Encoding::Utf8;
+        }
+        if sample.starts_with(b"\xFF\xFE") {
+            return Encoding::Utf16Le;
+        }
+        if sample.starts_with(b"\xFE\xFF") {
+            return Encoding::Utf16Be;
+        }
+
+        let pairs = sample.len() / 2;
+        if pairs > 0 {
+            let zero_at = |parity: usize| sample.chunks(2).filter(|p| p.len() == 2 && p[parity] == 0).count();
+            // Allow a few non-ASCII characters before giving up on UTF-16
+            if zero_at(1) * 10 >= pairs * 9 {
+                return Encoding::Utf16Le;
+            }
+            if zero_at(0) * 10 >= pairs * 9 {
+                return Encoding::Utf16Be;
+            }
+        }
+
+        match ::std::str::from_utf8(sample) {
+            Ok(_) => Encoding::Utf8,
+            // The sample may have cut a character in half
+            Err(e) if e.error_len().is_none() => Encoding::Utf8,
+            Err(_) => Encoding::Latin1,
+        }
+    }
+
+    /// Decodes `bytes`, dropping a leading byte order mark. Invalid sequences
+    /// are replaced with U+FFFD.
+    pub fn decode(self, bytes: &[u8]) -> String {
+        match self {
+            Encoding::Utf8 => {
+                let bytes = bytes.strip_prefix(UTF8_BOM).unwrap_or(bytes);
+                String::from_utf8_lossy(bytes).into_owned()
+            },
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let units = bytes.chunks(2).map(|pair| {
+                    // A dangling odd byte is decoded as an unpaired unit
+                    let (first, second) = (pair[0], *pair.get(1).unwrap_or(&0));
+                    if self == Encoding::Utf16Le {
+                        u16::from_le_bytes([first, second])
+                    } else {
+                        u16::from_be_bytes([first, second])
+                    }
+                });
+                let decoded: String = ::std::char::decode_utf16(units)
+                    .map(|c| c.unwrap_or(::std::char::REPLACEMENT_CHARACTER))
+                    .collect();
+                match decoded.strip_prefix('\u{FEFF}') {
+                    Some(rest) => rest.to_string(),
+                    None => decoded,
+                }
+            },
+            Encoding::Latin1 => bytes.iter().map(|b| *b as char).collect(),
+        }
+    }
+}
+
+/// Decodes UTF-8 that arrives in chunks. An incomplete character at the end of
+/// a chunk is held back and prepended to the next one instead of decoding to
+/// U+FFFD, so only bytes that are actually invalid get replaced.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate tail;
+/// # use tail::encoding::Utf8Decoder;
+/// let mut decoder = Utf8Decoder::default();
+/// // "é" is 0xC3 0xA9
+/// assert_eq!(decoder.decode(b"caf\xC3"), "caf");
+/// assert_eq!(decoder.decode(b"\xA9\n"), "\u{e9}\n");
+/// ```
+#[derive(Debug, Default)]
+pub struct Utf8Decoder {
+    pending: Vec<u8>,
+}
+
+impl Utf8Decoder {
+    pub fn decode(&mut self, chunk: &[u8]) -> String {
+        let mut bytes = ::std::mem::take(&mut self.pending);
+        bytes.extend_from_slice(chunk);
+        let complete = match ::std::str::from_utf8(&bytes) {
+            Ok(_) => bytes.len(),
+            // The chunk ends part way through a character
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(_) => {
+                // Only the tail can be incomplete; everything before it is
+                // decoded, replacing whatever is invalid
+                let incomplete_at = |i: &usize| match ::std::str::from_utf8(&bytes[*i..]) {
+                    Err(e) => e.valid_up_to() == 0 && e.error_len().is_none(),
+                    Ok(_) => false,
+                };
+                (bytes.len().saturating_sub(3)..bytes.len()).find(incomplete_at).unwrap_or(bytes.len())
+            },
+        };
+        self.pending = bytes.split_off(complete);
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sniffs_boms_utf16_and_utf8_before_falling_back_to_latin1() {
+        assert_eq!(Encoding::sniff(b"\xEF\xBB\xBFcaf\xC3\xA9"), Encoding::Utf8);
+        assert_eq!(Encoding::sniff(b"\xFF\xFEc\x00a\x00"), Encoding::Utf16Le);
+        assert_eq!(Encoding::sniff(b"\xFE\xFF\x00c\x00a"), Encoding::Utf16Be);
+        assert_eq!(Encoding::sniff(b"c\x00a\x00f\x00e\x00\n\x00"), Encoding::Utf16Le);
+        assert_eq!(Encoding::sniff(b"\x00c\x00a\x00f\x00e\x00\n"), Encoding::Utf16Be);
+        assert_eq!(Encoding::sniff(b"caf\xC3\xA9\n"), Encoding::Utf8);
+        // Cut off part way through the "é"
+        assert_eq!(Encoding::sniff(b"caf\xC3"), Encoding::Utf8);
+        assert_eq!(Encoding::sniff(b"caf\xE9\n"), Encoding::Latin1);
+        assert_eq!(Encoding::sniff(b""), Encoding::Utf8);
+    }
+
+    #[test]
+    fn decodes_each_encoding_without_its_bom() {
+        assert_eq!(Encoding::Utf8.decode(b"\xEF\xBB\xBFcaf\xC3\xA9"), "caf\u{e9}");
+        assert_eq!(Encoding::Utf8.decode(b"caf\xE9"), "caf\u{fffd}");
+        assert_eq!(Encoding::Utf16Le.decode(b"\xFF\xFEc\x00a\x00f\x00\xE9\x00"), "caf\u{e9}");
+        assert_eq!(Encoding::Utf16Be.decode(b"\xFE\xFF\x00c\x00a\x00f\x00\xE9"), "caf\u{e9}");
+        assert_eq!(Encoding::Latin1.decode(b"caf\xE9"), "caf\u{e9}");
+    }
+
+    #[test]
+    fn names_are_case_insensitive() {
+        assert_eq!(Encoding::from_name("UTF-16LE"), Some(Encoding::Utf16Le));
+        assert_eq!(Encoding::from_name("ISO-8859-1"), Some(Encoding::Latin1));
+        assert_eq!(Encoding::from_name("ebcdic"), None);
+    }
+    #[test]
+    fn utf8_decoder_replaces_only_invalid_bytes() {
+        let mut decoder = Utf8Decoder::default();
+        assert_eq!(decoder.decode(b"a\xFFb\xE2\x82"), "a\u{fffd}b");
+        assert_eq!(decoder.decode(b"\xAC\n"), "\u{20ac}\n");
+    }
+}
@@ -0,0 +1,186 @@
+//! `--filter` and `--prefix`: which lines of a file are output, and what goes
+//! in front of them. Both can be set per file in a `--config` file.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+use pattern::Regex;
+
+#[derive(Debug, Clone, Default)]
+pub struct LineFilter {
+    /// `--filter`: only lines matching it are output
+    regex: Option<Regex>,
+    /// `--prefix`
+    prefix: Vec<u8>,
+}
+
+impl LineFilter {
+    pub fn new(regex: Option<&str>, prefix: Option<&str>) -> Result<LineFilter, String> {
+        Ok(LineFilter {
+            regex: regex.map(Regex::new).transpose()?,
+            prefix: prefix.map_or(Vec::new(), |prefix| prefix.as_bytes().to_vec()),
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.regex.is_none() && self.prefix.is_empty()
+    }
+
+    /// Whether `line`, as read from the file, is output
+    pub fn keeps(&self, line: &[u8]) -> bool {
+        self.regex.as_ref().is_none_or(|regex| regex.is_match(&String::from_utf8_lossy(line)))
+    }
+
+    /// What goes in front of each line that's output, which may be nothing
+    pub fn prefix(&self) -> &[u8] {
+        &self.prefix
+    }
+}
+
+/// The filter for every file: those named in a `--config` section have their
+/// own, and the rest share the default
+#[derive(Debug, Default, Clone)]
+pub struct LineFilters {
+    default: LineFilter,
+    files: HashMap<String, LineFilter>,
+}
+
+impl LineFilters {
+    pub fn new(default: LineFilter) -> LineFilters {
+        LineFilters { default, files: HashMap::new() }
+    }
+
+    pub fn insert(&mut self, file_name: &str, filter: LineFilter) {
+        self.files.insert(file_name.to_string(), filter);
+    }
+
+    /// `file_name`'s filter, if it has one that does anything
+    pub fn get(&self, file_name: &str) -> Option<&LineFilter> {
+        Some(self.files.get(file_name).unwrap_or(&self.default)).filter(|filter| !filter.is_empty())
+    }
+}
+
+/// Passes through the lines `filter` keeps, with its prefix in front, and
+/// drops the rest. With `numbered` set, lines start with a `--line-numbers`
+/// number, which the filter doesn't see and the prefix goes in front of.
+pub struct FilteredWriter<W: Write> {
+    inner: W,
+    filter: Option<LineFilter>,
+    delimiter: u8,
+    numbered: bool,
+    /// The start of a line whose delimiter hasn't been written yet
+    partial: Vec<u8>,
+}
+
+impl<W: Write> FilteredWriter<W> {
+    pub fn new(inner: W, filter: Option<&LineFilter>, delimiter: u8, numbered: bool) -> Self {
+        FilteredWriter { inner, filter: filter.cloned(), delimiter, numbered, partial: Vec::new() }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Writes a last line that had no delimiter, if it's kept
+    pub fn finish(&mut self) -> io::Result<()> {
+        if !self.partial.is_empty() {
+            let line = std::mem::take(&mut self.partial);
+            self.write_line(&line)?;
+        }
+        Ok(())
+    }
+
+    fn write_line(&mut self, line: &[u8]) -> io::Result<()> {
+        let filter = match self.filter {
+            Some(ref filter) => filter,
+            None => return self.inner.write_all(line),
+        };
+        let text = line.strip_suffix(&[self.delimiter]).unwrap_or(line);
+        let text = match text.iter().position(|b| *b == b'\t') {
+            Some(tab) if self.numbered => &text[tab + 1..],
+            _ => text,
+        };
+        if filter.keeps(text) {
+            self.inner.write_all(filter.prefix())?;
+            self.inner.write_all(line)?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for FilteredWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.filter.is_none() {
+            return self.inner.write(buf);
+        }
+        let delimiter = self.delimiter;
+        for piece in buf.split_inclusive(|b| *b == delimiter) {
+            self.partial.extend_from_slice(piece);
+            if piece.last() == Some(&delimiter) {
+                let line = std::mem::take(&mut self.partial);
+                self.write_line(&line)?;
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filtered(filter: &LineFilter, numbered: bool, writes: &[&[u8]]) -> Vec<u8> {
+        let mut out = Vec::new();
+        {
+            let mut writer = FilteredWriter::new(&mut out, Some(filter), b'\n', numbered);
+            for data in writes {
+                writer.write_all(data).unwrap();
+            }
+            writer.finish().unwrap();
+        }
+        out
+    }
+
+    #[test]
+    fn keeps_matching_lines_across_writes() {
+        let filter = LineFilter::new(Some("^ERROR"), None).unwrap();
+        let out = filtered(&filter, false, &[b"ERROR one\ninfo\nERR", b"OR two\nERROR three"]);
+        assert_eq!(out, b"ERROR one\nERROR two\nERROR three");
+    }
+
+    #[test]
+    fn matches_a_character_split_between_writes() {
+        let filter = LineFilter::new(Some("^caf\u{e9}$"), None).unwrap();
+        // "é" is 0xC3 0xA9
+        let out = filtered(&filter, false, &[b"cafe\ncaf\xC3", b"\xA9\n"]);
+        assert_eq!(out, b"caf\xC3\xA9\n");
+    }
+
+    #[test]
+    fn prefixes_kept_lines_in_front_of_their_number() {
+        let filter = LineFilter::new(Some("^b"), Some("[app] ")).unwrap();
+        let out = filtered(&filter, true, &[b"     1\ta\n     2\tb\n"]);
+        assert_eq!(out, b"[app]      2\tb\n");
+    }
+
+    #[test]
+    fn files_without_their_own_filter_share_the_default() {
+        let mut filters = LineFilters::new(LineFilter::new(None, Some("> ")).unwrap());
+        filters.insert("quiet.log", LineFilter::default());
+        assert_eq!(filters.get("other.log").unwrap().prefix(), b"> ");
+        assert!(filters.get("quiet.log").is_none());
+        assert!(LineFilters::default().get("other.log").is_none());
+    }
+
+    #[test]
+    fn rejects_a_bad_regex() {
+        assert!(LineFilter::new(Some("(a"), None).is_err());
+    }
+}
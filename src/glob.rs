@@ -0,0 +1,145 @@
+//! Wildcards in file arguments, for `--glob`.
+//!
+//! `*` matches any run of characters, `?` any one character, and `[...]` one
+//! of a set, e.g. `[0-9]` or `[!.]`, as in the shell. As there, a name
+//! starting with `.` is only matched by a pattern that starts with one.
+//! Wildcards are only expanded in the file name; the directory part of the
+//! pattern has to be given as it is.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Debug, Clone)]
+enum Token {
+    Literal(char),
+    Any,
+    Star,
+    /// The ranges, and whether they're negated
+    Set(Vec<(char, char)>, bool),
+}
+
+#[derive(Debug, Clone)]
+pub struct Glob {
+    /// The pattern up to the file name, including the last `/`
+    prefix: String,
+    name: Vec<Token>,
+}
+
+/// Whether `s` has any wildcards in it
+pub fn has_wildcards(s: &str) -> bool {
+    s.contains(['*', '?', '['])
+}
+
+impl Glob {
+    pub fn new(pattern: &str) -> Result<Glob, String> {
+        let split = pattern.rfind('/').map_or(0, |i| i + 1);
+        let (prefix, name) = pattern.split_at(split);
+        if has_wildcards(prefix) {
+            return Err(format!("'{}': wildcards are only supported in the file name", pattern));
+        }
+        let mut tokens = Vec::new();
+        let mut chars = name.chars();
+        while let Some(c) = chars.next() {
+            tokens.push(match c {
+                '*' => Token::Star,
+                '?' => Token::Any,
+                '[' => parse_set(&mut chars).ok_or_else(|| format!("'{}': unclosed '['", pattern))?,
+                c => Token::Literal(c),
+            });
+        }
+        Ok(Glob { prefix: prefix.to_string(), name: tokens })
+    }
+
+    /// The directory the matching files are in
+    pub fn dir(&self) -> &Path {
+        if self.prefix.is_empty() { Path::new(".") } else { Path::new(&self.prefix) }
+    }
+
+    pub fn matches_name(&self, name: &str) -> bool {
+        if name.starts_with('.') && !matches!(self.name.first(), Some(&Token::Literal('.'))) {
+            return false;
+        }
+        let name: Vec<char> = name.chars().collect();
+        match_tokens(&self.name, &name)
+    }
+
+    /// The paths of the files in `dir()` whose names match, sorted. Names
+    /// that aren't UTF-8 are left out.
+    pub fn expand(&self) -> io::Result<Vec<String>> {
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(self.dir())? {
+            let entry = entry?;
+            if let Some(name) = entry.file_name().to_str() {
+                if self.matches_name(name) && !entry.file_type()?.is_dir() {
+                    paths.push(format!("{}{}", self.prefix, name));
+                }
+            }
+        }
+        paths.sort();
+        Ok(paths)
+    }
+}
+
+/// Parses the rest of a `[...]` set, after the `[`
+fn parse_set<I: Iterator<Item = char>>(chars: &mut I) -> Option<Token> {
+    let mut ranges = Vec::new();
+    let mut negated = false;
+    let mut first = true;
+    let mut pending: Option<char> = None;
+    let mut in_range = false;
+    loop {
+        let c = chars.next()?;
+        if first && (c == '!' || c == '^') {
+            negated = true;
+            continue;
+        }
+        // A `]` straight after the `[` is part of the set
+        if c == ']' && !first {
+            break;
+        }
+        first = false;
+        if c == '-' && pending.is_some() && !in_range {
+            in_range = true;
+            continue;
+        }
+        match pending {
+            Some(lo) if in_range => {
+                ranges.push((lo, c));
+                pending = None;
+                in_range = false;
+            },
+            Some(lo) => {
+                ranges.push((lo, lo));
+                pending = Some(c);
+            },
+            None => pending = Some(c),
+        }
+    }
+    if let Some(lo) = pending {
+        ranges.push((lo, lo));
+        if in_range {
+            ranges.push(('-', '-'));
+        }
+    }
+    Some(Token::Set(ranges, negated))
+}
+
+fn match_tokens(tokens: &[Token], name: &[char]) -> bool {
+    match tokens.split_first() {
+        None => name.is_empty(),
+        Some((&Token::Star, rest)) => (0..=name.len()).any(|skip| match_tokens(rest, &name[skip..])),
+        Some((token, rest)) => match name.split_first() {
+            Some((&c, name)) => {
+                let matched = match *token {
+                    Token::Literal(literal) => c == literal,
+                    Token::Any => true,
+                    Token::Set(ref ranges, negated) => ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != negated,
+                    Token::Star => unreachable!(),
+                };
+                matched && match_tokens(rest, name)
+            },
+            None => false,
+        },
+    }
+}
@@ -0,0 +1,388 @@
+//! Gzip decoding, for following logs that are written as a series of gzip
+//! members appended to the same file.
+//!
+//! Each member is decoded on its own, so a member that's still being written
+//! can be left for later and decoded once the rest of it arrives. The inflate
+//! implementation follows RFC 1951 directly and favours simplicity over speed.
+
+/// The first bytes of every gzip member
+const MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+const FLAG_HCRC: u8 = 0x02;
+const FLAG_EXTRA: u8 = 0x04;
+const FLAG_NAME: u8 = 0x08;
+const FLAG_COMMENT: u8 = 0x10;
+
+#[derive(Debug)]
+enum Error {
+    /// The data ends part way through a member
+    Incomplete,
+    Invalid(String),
+}
+
+type Result<T> = ::std::result::Result<T, Error>;
+
+pub fn is_gzip(sample: &[u8]) -> bool {
+    sample.starts_with(&MAGIC)
+}
+
+/// Decodes the complete gzip members at the start of `data`, stopping at one
+/// that hasn't been fully written yet. Returns the decompressed bytes and how
+/// many bytes of `data` the complete members took up.
+pub fn decode_members(data: &[u8]) -> ::std::result::Result<(Vec<u8>, usize), String> {
+    let mut output = Vec::new();
+    let mut consumed = 0;
+    while consumed < data.len() {
+        let decoded = output.len();
+        match decode_member(&data[consumed..], &mut output) {
+            Ok(len) => consumed += len,
+            // What there is of it comes out once it's whole
+            Err(Error::Incomplete) => {
+                output.truncate(decoded);
+                break;
+            },
+            Err(Error::Invalid(e)) => return Err(format!("invalid gzip data at byte {}: {}", consumed, e)),
+        }
+    }
+    Ok((output, consumed))
+}
+
+/// Appends one member's data to `output`, returning the member's length
+fn decode_member(data: &[u8], output: &mut Vec<u8>) -> Result<usize> {
+    let mut pos = member_header_len(data)?;
+    let start = output.len();
+    let mut bits = BitReader { data, pos, bit: 0 };
+    inflate(&mut bits, output)?;
+    pos = bits.pos + if bits.bit > 0 { 1 } else { 0 };
+
+    let trailer = data.get(pos..pos + 8).ok_or(Error::Incomplete)?;
+    let crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+    let size = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+    if crc32(&output[start..]) != crc {
+        return Err(Error::Invalid(String::from("CRC mismatch")));
+    }
+    if (output.len() - start) as u32 != size {
+        return Err(Error::Invalid(String::from("length mismatch")));
+    }
+    Ok(pos + 8)
+}
+
+fn member_header_len(data: &[u8]) -> Result<usize> {
+    let header = data.get(..10).ok_or(Error::Incomplete)?;
+    if !is_gzip(header) {
+        return Err(Error::Invalid(String::from("not a gzip member")));
+    }
+    if header[2] != 8 {
+        return Err(Error::Invalid(format!("unsupported compression method {}", header[2])));
+    }
+    let flags = header[3];
+    let mut pos = 10;
+    if flags & FLAG_EXTRA != 0 {
+        let len = data.get(pos..pos + 2).ok_or(Error::Incomplete)?;
+        pos += 2 + u16::from_le_bytes([len[0], len[1]]) as usize;
+    }
+    for flag in &[FLAG_NAME, FLAG_COMMENT] {
+        if flags & flag != 0 {
+            let end = data.get(pos..).and_then(|rest| rest.iter().position(|b| *b == 0))
+                .ok_or(Error::Incomplete)?;
+            pos += end + 1;
+        }
+    }
+    if flags & FLAG_HCRC != 0 {
+        pos += 2;
+    }
+    if pos > data.len() {
+        return Err(Error::Incomplete);
+    }
+    Ok(pos)
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bit: u8,
+}
+
+impl<'a> BitReader<'a> {
+    fn bits(&mut self, count: u8) -> Result<u32> {
+        let mut value = 0;
+        for i in 0..count {
+            let byte = *self.data.get(self.pos).ok_or(Error::Incomplete)?;
+            value |= (((byte >> self.bit) & 1) as u32) << i;
+            self.bit += 1;
+            if self.bit == 8 {
+                self.bit = 0;
+                self.pos += 1;
+            }
+        }
+        Ok(value)
+    }
+
+    /// Skips to the next byte boundary, as stored blocks start on one
+    fn align(&mut self) {
+        if self.bit > 0 {
+            self.bit = 0;
+            self.pos += 1;
+        }
+    }
+}
+
+/// A canonical Huffman code, as the number of codes of each length and the
+/// symbols in code order
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0u16; 16];
+        for len in lengths {
+            counts[*len as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets = [0u16; 16];
+        for len in 1..16 {
+            offsets[len] = offsets[len - 1] + counts[len - 1];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, len) in lengths.iter().enumerate().filter(|&(_, len)| *len != 0) {
+            symbols[offsets[*len as usize] as usize] = symbol as u16;
+            offsets[*len as usize] += 1;
+        }
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, bits: &mut BitReader) -> Result<u16> {
+        // Codes of each length are consecutive, starting where the previous
+        // length's codes end (shifted left a bit)
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..16 {
+            code |= bits.bits(1)? as i32;
+            let count = self.counts[len] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(Error::Invalid(String::from("invalid Huffman code")))
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31,
+    35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2,
+    3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193,
+    257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6,
+    7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+/// The order code length code lengths are stored in for dynamic blocks
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+fn inflate(bits: &mut BitReader, output: &mut Vec<u8>) -> Result<()> {
+    let start = output.len();
+    loop {
+        let last = bits.bits(1)? == 1;
+        match bits.bits(2)? {
+            0 => stored_block(bits, output)?,
+            1 => {
+                let (literals, distances) = fixed_codes();
+                compressed_block(bits, output, start, &literals, &distances)?
+            },
+            2 => {
+                let (literals, distances) = dynamic_codes(bits)?;
+                compressed_block(bits, output, start, &literals, &distances)?
+            },
+            _ => return Err(Error::Invalid(String::from("invalid block type"))),
+        }
+        if last {
+            return Ok(());
+        }
+    }
+}
+
+fn stored_block(bits: &mut BitReader, output: &mut Vec<u8>) -> Result<()> {
+    bits.align();
+    let header = bits.data.get(bits.pos..bits.pos + 4).ok_or(Error::Incomplete)?;
+    let len = u16::from_le_bytes([header[0], header[1]]);
+    if len != !u16::from_le_bytes([header[2], header[3]]) {
+        return Err(Error::Invalid(String::from("stored block length mismatch")));
+    }
+    let start = bits.pos + 4;
+    let block = bits.data.get(start..start + len as usize).ok_or(Error::Incomplete)?;
+    output.extend_from_slice(block);
+    bits.pos = start + len as usize;
+    Ok(())
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    for (symbol, len) in lengths.iter_mut().enumerate() {
+        *len = match symbol {
+            0..=143 => 8,
+            144..=255 => 9,
+            256..=279 => 7,
+            _ => 8,
+        };
+    }
+    (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+}
+
+fn dynamic_codes(bits: &mut BitReader) -> Result<(Huffman, Huffman)> {
+    let literal_count = bits.bits(5)? as usize + 257;
+    let distance_count = bits.bits(5)? as usize + 1;
+    let code_length_count = bits.bits(4)? as usize + 4;
+
+    let mut code_lengths = [0u8; 19];
+    for index in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[*index] = bits.bits(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&code_lengths);
+
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let (value, repeat) = match code_length_code.decode(bits)? {
+            len @ 0..=15 => (len as u8, 1),
+            16 => {
+                let previous = *lengths.last()
+                    .ok_or_else(|| Error::Invalid(String::from("repeated code length with no previous length")))?;
+                (previous, 3 + bits.bits(2)?)
+            },
+            17 => (0, 3 + bits.bits(3)?),
+            _ => (0, 11 + bits.bits(7)?),
+        };
+        for _ in 0..repeat {
+            lengths.push(value);
+        }
+    }
+    if lengths.len() > literal_count + distance_count {
+        return Err(Error::Invalid(String::from("too many code lengths")));
+    }
+    Ok((Huffman::new(&lengths[..literal_count]), Huffman::new(&lengths[literal_count..])))
+}
+
+/// `start` is where this member's output begins, which distances can't reach
+/// back past
+fn compressed_block(bits: &mut BitReader, output: &mut Vec<u8>, start: usize,
+                    literals: &Huffman, distances: &Huffman) -> Result<()> {
+    loop {
+        let symbol = literals.decode(bits)? as usize;
+        if symbol < 256 {
+            output.push(symbol as u8);
+            continue;
+        }
+        if symbol == 256 {
+            return Ok(());
+        }
+        let symbol = symbol - 257;
+        if symbol >= LENGTH_BASE.len() {
+            return Err(Error::Invalid(String::from("invalid length code")));
+        }
+        let len = LENGTH_BASE[symbol] as usize + bits.bits(LENGTH_EXTRA[symbol])? as usize;
+        let symbol = distances.decode(bits)? as usize;
+        if symbol >= DIST_BASE.len() {
+            return Err(Error::Invalid(String::from("invalid distance code")));
+        }
+        let distance = DIST_BASE[symbol] as usize + bits.bits(DIST_EXTRA[symbol])? as usize;
+        if distance > output.len() - start {
+            return Err(Error::Invalid(String::from("distance too far back")));
+        }
+        // Copied a byte at a time, as the copy may overlap what it produces
+        let from = output.len() - distance;
+        for i in 0..len {
+            let byte = output[from + i];
+            output.push(byte);
+        }
+    }
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    /// `data` compressed by gzip(1), as a log rotator would leave it
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut child = Command::new("gzip").args(["-c", "-n", "-9"])
+            .stdin(Stdio::piped()).stdout(Stdio::piped()).spawn().unwrap();
+        child.stdin.take().unwrap().write_all(data).unwrap();
+        child.wait_with_output().unwrap().stdout
+    }
+
+    /// A member holding `data` in a stored block, with a file name and
+    /// comment in its header
+    fn stored_member(data: &[u8]) -> Vec<u8> {
+        let mut member = vec![0x1f, 0x8b, 8, FLAG_NAME | FLAG_COMMENT, 0, 0, 0, 0, 0, 3];
+        member.extend_from_slice(b"f.log\0a comment\0");
+        let len = data.len() as u16;
+        member.push(1);
+        member.extend_from_slice(&len.to_le_bytes());
+        member.extend_from_slice(&(!len).to_le_bytes());
+        member.extend_from_slice(data);
+        member.extend_from_slice(&crc32(data).to_le_bytes());
+        member.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        member
+    }
+
+    fn lines(count: usize) -> Vec<u8> {
+        let words = ["error", "request", "served", "in", "ms", "user", "login", "failed"];
+        (0..count).flat_map(|i| format!("{} {} {}\n", words[i % 8], i, words[i * 7 % 8]).into_bytes()).collect()
+    }
+
+    #[test]
+    fn decodes_fixed_dynamic_and_stored_blocks() {
+        // gzip(1) uses the fixed codes for short inputs and dynamic ones for
+        // longer
+        for data in &[&b""[..], b"one\ntwo\n", &lines(500)] {
+            assert_eq!(decode_members(&gzip(data)).unwrap().0, *data);
+        }
+        let member = stored_member(b"stored\n");
+        assert_eq!(decode_members(&member).unwrap(), (b"stored\n".to_vec(), member.len()));
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn stops_at_a_member_still_being_written() {
+        let first = [gzip(b"one\n"), stored_member(b"two\n")].concat();
+        let second = gzip(&lines(100));
+        for cut in &[0, 5, 10, second.len() / 2, second.len() - 1] {
+            let data = [&first[..], &second[..*cut]].concat();
+            assert_eq!(decode_members(&data).unwrap(), (b"one\ntwo\n".to_vec(), first.len()), "cut at {}", cut);
+        }
+        let data = [&first[..], &second[..]].concat();
+        assert_eq!(decode_members(&data).unwrap(), ([&b"one\ntwo\n"[..], &lines(100)].concat(), data.len()));
+    }
+
+    #[test]
+    fn rejects_corrupt_members() {
+        let mut member = gzip(b"one\n");
+        let crc_at = member.len() - 8;
+        member[crc_at] ^= 1;
+        let data = [gzip(b"ok\n"), member].concat();
+        assert!(decode_members(&data).unwrap_err().starts_with("invalid gzip data at byte 23: CRC mismatch"));
+        assert_eq!(decode_members(b"not gzip data").unwrap_err(), "invalid gzip data at byte 0: not a gzip member");
+    }
+}
@@ -0,0 +1,62 @@
+//! `--json-complete`: holding back JSON that hasn't been fully written yet.
+//!
+//! This isn't a parser. It only tracks how deeply nested in objects and arrays
+//! the text is and whether it's inside a string, which is enough to tell where
+//! a value written by a JSON-lines producer ends.
+
+/// Returns the length of the longest prefix of `bytes` that ends with a
+/// newline outside of any string, object or array, i.e. one made of whole
+/// lines of complete values. A stray closing bracket is ignored, but a stray
+/// quote holds back everything after it, as it looks like an unfinished
+/// string.
+pub fn complete_len(bytes: &[u8]) -> usize {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut complete = 0;
+    for (i, byte) in bytes.iter().enumerate() {
+        if in_string {
+            match *byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {},
+            }
+            continue;
+        }
+        match *byte {
+            b'"' => in_string = true,
+            b'{' | b'[' => depth += 1,
+            b'}' | b']' => depth = depth.saturating_sub(1),
+            b'\n' if depth == 0 => complete = i + 1,
+            _ => {},
+        }
+    }
+    complete
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn holds_back_values_that_are_still_open() {
+        assert_eq!(complete_len(b""), 0);
+        assert_eq!(complete_len(b"{\"a\": 1}\n"), 9);
+        assert_eq!(complete_len(b"{\"a\": 1}\n{\"b\": "), 9);
+        assert_eq!(complete_len(b"{\"a\": 1}\n{\"b\": 2}"), 9);
+        // A value pretty-printed over several lines is complete at its end
+        let pretty = b"{\n  \"a\": [\n    1\n  ]\n}\n";
+        assert_eq!(complete_len(pretty), pretty.len());
+        assert_eq!(complete_len(b"{\n  \"a\": [\n    1\n"), 0);
+    }
+
+    #[test]
+    fn brackets_and_newlines_in_strings_do_not_count() {
+        assert_eq!(complete_len(b"{\"a\": \"}\\n{\"}\n"), 14);
+        assert_eq!(complete_len(b"{\"a\": \"\\\"}\"}\n"), 13);
+        assert_eq!(complete_len(b"{\"a\": \"x\n"), 0);
+        // Stray closing brackets are ignored
+        assert_eq!(complete_len(b"}\n{}\n"), 5);
+    }
+}
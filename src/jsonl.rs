@@ -0,0 +1,137 @@
+//! `--output=json`: each line as a JSON object on a line of its own, for log
+//! pipelines, e.g.
+//!
+//! ```text
+//! {"file":"app.log","line":"started","ts":"2024-01-01T12:00:00Z"}
+//! ```
+//!
+//! `ts` is when tail read the line. Bytes that aren't UTF-8 are replaced with
+//! U+FFFD rather than dropped.
+
+use std::io::{self, Write};
+use std::time::SystemTime;
+use timestamp;
+
+/// Writes `line`, without its delimiter, as the record for a line of `file`
+pub fn write_record<W: Write>(writer: &mut W, file: &str, line: &[u8], delimiter: u8) -> io::Result<()> {
+    let line = line.strip_suffix(&[delimiter]).unwrap_or(line);
+    let mut record = String::from("{\"file\":");
+    push_string(&mut record, file);
+    record.push_str(",\"line\":");
+    push_string(&mut record, &String::from_utf8_lossy(line));
+    record.push_str(",\"ts\":\"");
+    record.push_str(&timestamp::iso8601(SystemTime::now()));
+    record.push_str("\"}\n");
+    writer.write_all(record.as_bytes())
+}
+
+/// Appends `s` to `out` as a JSON string
+fn push_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Passes writes through to `inner` as they are, or as records of `file`'s
+/// lines if there is a file
+pub struct RecordWriter<W: Write> {
+    inner: W,
+    file: Option<String>,
+    delimiter: u8,
+    /// The start of a line whose delimiter hasn't been written yet
+    partial: Vec<u8>,
+}
+
+impl<W: Write> RecordWriter<W> {
+    pub fn new(inner: W, file: Option<&str>, delimiter: u8) -> Self {
+        RecordWriter { inner, file: file.map(String::from), delimiter, partial: Vec::new() }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Writes the record for a last line that had no delimiter
+    pub fn finish(&mut self) -> io::Result<()> {
+        if let Some(ref file) = self.file {
+            if !self.partial.is_empty() {
+                write_record(&mut self.inner, file, &self.partial, self.delimiter)?;
+                self.partial.clear();
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for RecordWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let file = match self.file {
+            Some(ref file) => file,
+            None => return self.inner.write(buf),
+        };
+        let delimiter = self.delimiter;
+        for piece in buf.split_inclusive(|b| *b == delimiter) {
+            self.partial.extend_from_slice(piece);
+            if piece.last() == Some(&delimiter) {
+                write_record(&mut self.inner, file, &self.partial, delimiter)?;
+                self.partial.clear();
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The records written, with their `ts` fields cut off
+    fn records(writes: &[&[u8]]) -> Vec<String> {
+        let mut out = Vec::new();
+        {
+            let mut writer = RecordWriter::new(&mut out, Some("app.log"), b'\n');
+            for data in writes {
+                writer.write_all(data).unwrap();
+            }
+            writer.finish().unwrap();
+        }
+        String::from_utf8(out).unwrap().lines()
+            .map(|record| record[..record.find(",\"ts\":").unwrap()].to_string())
+            .collect()
+    }
+
+    #[test]
+    fn writes_a_record_per_line_across_writes() {
+        assert_eq!(records(&[b"one\ntw", b"o\nthree"]), [
+            "{\"file\":\"app.log\",\"line\":\"one\"",
+            "{\"file\":\"app.log\",\"line\":\"two\"",
+            "{\"file\":\"app.log\",\"line\":\"three\"",
+        ]);
+    }
+
+    #[test]
+    fn escapes_quotes_and_control_characters() {
+        assert_eq!(records(&[b"say \"hi\"\t\\ \x01\n"]), ["{\"file\":\"app.log\",\"line\":\"say \\\"hi\\\"\\t\\\\ \\u0001\""]);
+    }
+
+    #[test]
+    fn passes_writes_through_without_a_file() {
+        let mut out = Vec::new();
+        RecordWriter::new(&mut out, None, b'\n').write_all(b"a\nb").unwrap();
+        assert_eq!(out, b"a\nb");
+    }
+}
@@ -1,145 +1,865 @@
-use std::fs::{File, Metadata};
-use std::io::{Seek, BufReader, SeekFrom, Read, BufWriter, Write};
+use std::fs::{self, File, Metadata};
+use std::io::{self, Seek, BufRead, BufReader, SeekFrom, Read, Write};
 use std::collections::{VecDeque};
+use std::fmt;
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+pub mod clock;
+pub mod encoding;
 
 const BUFFER_SIZE: u64 = 4096;
+/// What `seek_to_line` reads at a time. It only ever reads forward, so it can
+/// take bigger chunks than the readers that work back from the end.
+const SKIP_CHUNK_SIZE: usize = 64 * 1024;
+const DEFAULT_LINES: usize = 10;
+const DEFAULT_SLEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What went wrong while tailing a file
+///
+/// ```
+/// # extern crate tail;
+/// # use tail::{TailBuilder, TailError};
+/// match TailBuilder::new("/nonexistent/app.log").run(Vec::new()) {
+///     Err(TailError::Read { ref path, ref source }) => {
+///         assert_eq!(path.to_str(), Some("/nonexistent/app.log"));
+///         assert_eq!(source.kind(), std::io::ErrorKind::NotFound);
+///     },
+///     other => panic!("unexpected result: {:?}", other),
+/// }
+/// ```
+#[derive(Debug)]
+pub enum TailError {
+    /// Reading, seeking or writing failed
+    Io(io::Error),
+    /// Reading, seeking or writing failed while tailing the file at `path`
+    Read { path: PathBuf, source: io::Error },
+}
+
+impl TailError {
+    /// Notes that the error happened while tailing the file at `path`
+    pub fn at_path<P: AsRef<Path>>(self, path: P) -> TailError {
+        match self {
+            TailError::Io(source) => TailError::Read { path: path.as_ref().to_path_buf(), source },
+            e => e,
+        }
+    }
+
+    /// The underlying error
+    pub fn io_error(&self) -> &io::Error {
+        match *self {
+            TailError::Io(ref e) | TailError::Read { source: ref e, .. } => e,
+        }
+    }
+}
+
+impl fmt::Display for TailError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TailError::Io(ref e) => f.write_str(&io_error_message(e)),
+            TailError::Read { ref path, ref source } => write!(f, "{}: {}", path.display(), io_error_message(source)),
+        }
+    }
+}
+
+/// What went wrong, without the " (os error N)" std adds to OS errors,
+/// so messages read like those of the other coreutils
+pub fn io_error_message(e: &io::Error) -> String {
+    let message = e.to_string();
+    match message.rfind(" (os error ") {
+        Some(i) if e.raw_os_error().is_some() => message[..i].to_string(),
+        _ => message,
+    }
+}
+
+impl std::error::Error for TailError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.io_error())
+    }
+}
+
+impl From<io::Error> for TailError {
+    fn from(e: io::Error) -> Self {
+        TailError::Io(e)
+    }
+}
+
+/// Drops the path, for callers that already know which file it was
+impl From<TailError> for io::Error {
+    fn from(e: TailError) -> Self {
+        match e {
+            TailError::Io(e) | TailError::Read { source: e, .. } => e,
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, TailError>;
 
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum ModificationType {
     Added,
     Removed,
     NoChange,
 }
 
-#[allow(dead_code)]
-pub enum Input {
-    File(File),
-    Stdin(std::io::Stdin),
-}
-
 /// Reads file backwards to efficiently retrieve the last N lines
 ///
+/// Any `Read + Seek` source works, e.g. a `Cursor` over bytes already in
+/// memory, though a file is normally read through a `BufReader`.
+///
+/// This assumes the end of the file is the newest data. Circular logs that
+/// wrap around and overwrite themselves from the start break that, and the
+/// lines returned may not be the most recent ones. If the file shrinks while
+/// it's being scanned, only the lines read before that are returned.
+///
 /// # Examples
 ///
-/// ```
+/// ```no_run
+/// # extern crate tail;
+/// # use tail::BackwardsReader;
+/// # use std::fs::File;
+/// # use std::io::{BufReader, BufWriter, Write};
+/// let mut fd = BufReader::new(File::open("/var/log/syslog").unwrap());
 /// let mut writer = BufWriter::new(std::io::stdout());
-/// let mut reader = BackwardsReader::new(10, &mut writer);
-/// reader.read_all(&mut writer);
+/// let mut reader = BackwardsReader::new(10, &mut fd, b'\n').unwrap();
+/// reader.read_all(&mut writer).unwrap();
 /// writer.flush().unwrap();
 /// ```
-pub struct BackwardsReader<'a> {
-    pieces: VecDeque<VecDeque<Vec<u8>>>,
+///
+/// ```
+/// # extern crate tail;
+/// # use tail::BackwardsReader;
+/// # use std::io::Cursor;
+/// let mut source = Cursor::new(b"one\ntwo\nthree\n".to_vec());
+/// let mut lines = Vec::new();
+/// BackwardsReader::new(1, &mut source, b'\n').unwrap().read_all(&mut lines).unwrap();
+/// assert_eq!(lines, b"three\n");
+/// ```
+pub struct BackwardsReader<'a, R = BufReader<File>> {
+    /// The chunks read so far, in file order
+    pieces: VecDeque<Vec<u8>>,
     num_of_lines: usize,
-    fd: &'a mut BufReader<File>,
+    /// With `new_bytes`, how many bytes are wanted instead of lines
+    num_of_bytes: Option<u64>,
+    fd: &'a mut R,
+    /// Delimiters in `pieces`, not counting the one ending the last line
     total_newlines: usize,
-    first_read: bool,
-    last_offset: u64
-}
-
-impl<'a> BackwardsReader<'a> {
-    pub fn new(num_of_lines: usize, fd: &'a mut BufReader<File>) -> Self {
-        let last_offset = fd.seek(SeekFrom::End(0))
-                                .unwrap_or_else(|_| { panic!("Failed to seek to end of file") });
-        BackwardsReader {
-            pieces: VecDeque::with_capacity(num_of_lines),
-            num_of_lines: num_of_lines,
-            fd: fd,
+    /// What lines end with, normally `b'\n'`
+    delimiter: u8,
+    /// The length of the file when the reader was created
+    end: u64,
+    /// Where the next chunk back ends
+    last_offset: u64,
+    /// The scan never reads before this offset
+    scan_limit: u64,
+    /// How much is read back at a time
+    buffer_size: u64,
+    /// Written between lines instead of `delimiter`, if set
+    output_delimiter: Option<Vec<u8>>,
+}
+
+impl<'a, R: Read + Seek> BackwardsReader<'a, R> {
+    /// Lines end with `delimiter`, which is normally `b'\n'`, or `b'\0'` for
+    /// NUL terminated records.
+    pub fn new(num_of_lines: usize, fd: &'a mut R, delimiter: u8) -> Result<Self> {
+        let end = fd.seek(SeekFrom::End(0))?;
+        Ok(BackwardsReader {
+            pieces: VecDeque::new(),
+            num_of_lines,
+            num_of_bytes: None,
+            fd,
             total_newlines: 0,
-            first_read: true,
-            last_offset: last_offset
-        }
+            delimiter,
+            end,
+            last_offset: end,
+            scan_limit: 0,
+            buffer_size: BUFFER_SIZE,
+            output_delimiter: None,
+        })
     }
 
-    fn handle_partial_read(&mut self) {
-        if self.last_offset > 0 {
-            self.fd.seek(SeekFrom::Start(0)).unwrap();
-            let mut buff = vec![0; (self.last_offset) as usize];
-            self.fd.read_exact(buff.as_mut_slice())
-                .unwrap_or_else(|_| { panic!("Incorrectly handled unexpected EOF. Probably an off by one error") });
-            if self.first_read && buff[buff.len() - 1] != b'\n' {
-                self.total_newlines += 1;
-                self.first_read = false;
-                buff.push(b'\n');
-            }
-            let buff: VecDeque<Vec<u8>> = buff.split(|elm: &u8| {*elm == b'\n'}).map(|elm: &[u8]| elm.to_vec()).collect();
-            self.total_newlines += buff.len() - 1;
-            self.pieces.push_front(buff);
+    /// Reads the last `num_of_bytes` bytes instead of lines, like `tail -c`.
+    /// Lines are still taken to end with `b'\n'` for `output_delimiter`.
+    ///
+    /// ```
+    /// # extern crate tail;
+    /// # use tail::BackwardsReader;
+    /// # use std::io::Cursor;
+    /// let mut bytes = Vec::new();
+    /// BackwardsReader::new_bytes(6, &mut Cursor::new(b"one\ntwo\n".to_vec())).unwrap().read_all(&mut bytes).unwrap();
+    /// assert_eq!(bytes, b"e\ntwo\n");
+    ///
+    /// bytes.clear();
+    /// BackwardsReader::new_bytes(100, &mut Cursor::new(b"one\n".to_vec())).unwrap().read_all(&mut bytes).unwrap();
+    /// assert_eq!(bytes, b"one\n");
+    ///
+    /// bytes.clear();
+    /// BackwardsReader::new_bytes(5, &mut Cursor::new(b"one\ntwo\n".to_vec())).unwrap()
+    ///     .buffer_size(2).read_all(&mut bytes).unwrap();
+    /// assert_eq!(bytes, b"\ntwo\n");
+    /// ```
+    pub fn new_bytes(num_of_bytes: u64, fd: &'a mut R) -> Result<Self> {
+        let mut reader = BackwardsReader::new(0, fd, b'\n')?;
+        reader.num_of_bytes = Some(num_of_bytes);
+        Ok(reader)
+    }
+
+    /// Treats the file as ending at `end`, if it's that long, e.g. at its
+    /// length when it was stat'ed, so what's appended while it's being read is
+    /// left for whatever follows it. Set this before `max_scan_bytes`, which
+    /// counts back from the end.
+    ///
+    /// ```
+    /// # extern crate tail;
+    /// # use tail::BackwardsReader;
+    /// # use std::io::{Cursor, Seek};
+    /// let mut source = Cursor::new(b"one\ntwo\nappended\n".to_vec());
+    /// let mut lines = Vec::new();
+    /// BackwardsReader::new(1, &mut source, b'\n').unwrap().ending_at(8).read_all(&mut lines).unwrap();
+    /// assert_eq!(lines, b"two\n");
+    /// assert_eq!(source.stream_position().unwrap(), 8);
+    /// ```
+    pub fn ending_at(mut self, end: u64) -> Self {
+        self.end = self.end.min(end);
+        self.last_offset = self.end;
+        self.scan_limit = self.scan_limit.min(self.end);
+        self
+    }
+
+    /// Reads at most `max_bytes` back from the end of the file. If the lines
+    /// wanted start further back than that, only the lines in that window are
+    /// returned, the first of which may be cut off, as if the window started
+    /// the file.
+    pub fn max_scan_bytes(mut self, max_bytes: u64) -> Self {
+        self.scan_limit = self.scan_limit.max(self.end.saturating_sub(max_bytes));
+        self
+    }
+
+    /// Reads back `buffer_size` bytes at a time instead of 4096. Larger
+    /// chunks suit files with long lines or slow seeks. The output is the
+    /// same whatever the size.
+    ///
+    /// # Panics
+    ///
+    /// If `buffer_size` is 0.
+    ///
+    /// ```
+    /// # extern crate tail;
+    /// # use tail::BackwardsReader;
+    /// # use std::fs::{self, File};
+    /// # use std::io::BufReader;
+    /// let path = std::env::temp_dir().join("tail-buffer-size-example.log");
+    /// fs::write(&path, "one\ntwo\nthree\n").unwrap();
+    /// let mut fd = BufReader::new(File::open(&path).unwrap());
+    ///
+    /// let mut lines = Vec::new();
+    /// BackwardsReader::new(2, &mut fd, b'\n').unwrap().buffer_size(1).read_all(&mut lines).unwrap();
+    /// assert_eq!(lines, b"two\nthree\n");
+    /// # fs::remove_file(&path).unwrap();
+    /// ```
+    ///
+    /// A file that's exactly a whole number of chunks keeps its first line:
+    ///
+    /// ```
+    /// # extern crate tail;
+    /// # use tail::BackwardsReader;
+    /// # use std::io::Cursor;
+    /// for &size in &[4096, 8192] {
+    ///     // 16 byte lines
+    ///     let contents: String = (0..size / 16).map(|i| format!("{:015}\n", i)).collect();
+    ///     assert_eq!(contents.len(), size);
+    ///     let mut lines = Vec::new();
+    ///     BackwardsReader::new(size, &mut Cursor::new(contents.as_bytes()), b'\n').unwrap()
+    ///         .read_all(&mut lines).unwrap();
+    ///     assert_eq!(lines, contents.as_bytes());
+    /// }
+    /// ```
+    pub fn buffer_size(mut self, buffer_size: u64) -> Self {
+        assert!(buffer_size > 0, "BackwardsReader buffer size must be greater than 0");
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    /// Leaves out a UTF-8 byte order mark at the start of the file, as if the
+    /// file started after it
+    pub fn skip_bom(mut self) -> Self {
+        let mut start = [0; 3];
+        let has_bom = self.fd.seek(SeekFrom::Start(0)).is_ok()
+            && self.fd.read_exact(&mut start).is_ok()
+            && start == encoding::UTF8_BOM;
+        if has_bom {
+            self.scan_limit = self.scan_limit.max(encoding::UTF8_BOM.len() as u64);
         }
+        self
     }
 
-    fn read(&mut self) -> bool {
-        let seek_offset = if (self.last_offset as i64) - (BUFFER_SIZE as i64) >= 0 {
-            self.last_offset - BUFFER_SIZE
-        } else {
-            self.handle_partial_read();
-            return false;
-        };
-        match self.fd.seek(SeekFrom::Start(seek_offset)) {
-            Ok(new_offset) => {
-                self.last_offset = new_offset;
+    /// Writes `separator` between the lines instead of the delimiter, like
+    /// `paste -s -d`. The last line keeps its delimiter, if it has one, and an
+    /// empty separator runs the lines together.
+    ///
+    /// ```
+    /// # extern crate tail;
+    /// # use tail::BackwardsReader;
+    /// # use std::fs::{self, File};
+    /// # use std::io::BufReader;
+    /// let path = std::env::temp_dir().join("tail-output-delimiter-example.log");
+    /// fs::write(&path, "one\ntwo\nthree\n").unwrap();
+    /// let mut fd = BufReader::new(File::open(&path).unwrap());
+    ///
+    /// let mut lines = Vec::new();
+    /// BackwardsReader::new(2, &mut fd, b'\n').unwrap().output_delimiter(b", ").read_all(&mut lines).unwrap();
+    /// assert_eq!(lines, b"two, three\n");
+    /// # fs::remove_file(&path).unwrap();
+    /// ```
+    pub fn output_delimiter(mut self, separator: &[u8]) -> Self {
+        self.output_delimiter = Some(separator.to_vec());
+        self
+    }
+
+    /// Has `write` write to `writer` through a `JoinWriter`, if there's an
+    /// output delimiter
+    fn joined<W: Write, F>(&mut self, writer: &mut W, write: F) -> Result<()>
+        where F: FnOnce(&mut Self, &mut dyn Write) -> Result<()>
+    {
+        match self.output_delimiter.clone() {
+            Some(separator) => {
+                let mut joined = JoinWriter::new(&mut *writer, self.delimiter, &separator);
+                write(self, &mut joined)?;
+                Ok(joined.finish()?)
             },
-            Err(_) => {
-                self.handle_partial_read();
-                return false;
-            }
+            None => write(self, writer),
         }
+    }
 
-        let mut buff = vec![0; BUFFER_SIZE as usize];
-        self.fd.read_exact(buff.as_mut_slice())
-            .unwrap_or_else(|_| { panic!("Failed to read from end of file in BackwardsReader") });
-        if self.first_read && buff[buff.len() - 1] != b'\n' {
-            self.total_newlines += 1;
-            self.first_read = false;
-            buff.push(b'\n');
+    /// Reads the next chunk back, returning whether more are needed
+    fn read(&mut self) -> Result<bool> {
+        let enough = match self.num_of_bytes {
+            Some(n) => self.end - self.last_offset >= n,
+            None => self.total_newlines >= self.num_of_lines,
+        };
+        if enough || self.last_offset <= self.scan_limit {
+            return Ok(false);
         }
-        let buff: VecDeque<Vec<u8>> = buff.split(|elm: &u8| {*elm == b'\n'}).map(|elm: &[u8]| elm.to_vec()).collect();
-        self.total_newlines += buff.len() - 1;
+        let chunk_start = self.last_offset.saturating_sub(self.buffer_size).max(self.scan_limit)
+            .max(self.num_of_bytes.map_or(0, |n| self.end.saturating_sub(n)));
+        self.fd.seek(SeekFrom::Start(chunk_start))?;
+        let mut buff = vec![0; (self.last_offset - chunk_start) as usize];
+        if !fill(self.fd, buff.as_mut_slice())? {
+            // The file shrank under us, e.g. it was truncated or a circular log
+            // wrapped around. Stop here with the lines found so far.
+            return Ok(false);
+        }
+        let mut newlines = buff.iter().filter(|b| **b == self.delimiter).count();
+        // The newline ending the last line doesn't start another one
+        if self.last_offset == self.end && buff[buff.len() - 1] == self.delimiter {
+            newlines -= 1;
+        }
+        self.total_newlines += newlines;
+        self.last_offset = chunk_start;
         self.pieces.push_front(buff);
-        
-        if self.first_read {
-            self.first_read = false;
+        Ok(true)
+    }
+
+    /// Writes the last `num_of_lines` lines to `writer`, leaving the file
+    /// positioned at the end of them so it can be read on from there.
+    ///
+    /// The output is the same as GNU `tail -n`: a file with fewer lines than
+    /// asked for is written whole, and a last line without a newline is
+    /// written without one.
+    ///
+    /// `writer` can be any `Write`, e.g. a `Vec<u8>` to capture the lines.
+    /// They're written a chunk at a time, so wrap unbuffered writers such as
+    /// stdout or a socket in a `BufWriter`.
+    ///
+    /// ```
+    /// # extern crate tail;
+    /// # use tail::BackwardsReader;
+    /// # use std::fs::{self, File};
+    /// # use std::io::BufReader;
+    /// let path = std::env::temp_dir().join("tail-read-all-example.log");
+    /// fs::write(&path, "one\ntwo\nthree\n").unwrap();
+    /// let mut fd = BufReader::new(File::open(&path).unwrap());
+    ///
+    /// let mut lines = Vec::new();
+    /// BackwardsReader::new(2, &mut fd, b'\n').unwrap().read_all(&mut lines).unwrap();
+    /// assert_eq!(lines, b"two\nthree\n");
+    ///
+    /// fs::write(&path, "one\ntwo").unwrap();
+    /// let mut fd = BufReader::new(File::open(&path).unwrap());
+    /// let mut lines = Vec::new();
+    /// BackwardsReader::new(5, &mut fd, b'\n').unwrap().read_all(&mut lines).unwrap();
+    /// assert_eq!(lines, b"one\ntwo");
+    /// # fs::remove_file(&path).unwrap();
+    /// ```
+    ///
+    /// An empty file has no lines to write, and a file that's only a newline
+    /// has one empty line. Asking for 0 lines writes nothing, whatever the
+    /// file holds.
+    ///
+    /// ```
+    /// # extern crate tail;
+    /// # use tail::BackwardsReader;
+    /// # use std::io::Cursor;
+    /// let mut lines = Vec::new();
+    /// BackwardsReader::new(10, &mut Cursor::new(Vec::new()), b'\n').unwrap().read_all(&mut lines).unwrap();
+    /// assert!(lines.is_empty());
+    ///
+    /// BackwardsReader::new(10, &mut Cursor::new(b"\n".to_vec()), b'\n').unwrap().read_all(&mut lines).unwrap();
+    /// assert_eq!(lines, b"\n");
+    ///
+    /// lines.clear();
+    /// BackwardsReader::new(0, &mut Cursor::new(b"one\ntwo\n".to_vec()), b'\n').unwrap().read_all(&mut lines).unwrap();
+    /// assert!(lines.is_empty());
+    /// ```
+    pub fn read_all<W: Write>(&mut self, writer: &mut W) -> Result<()> {
+        self.joined(writer, |reader, writer| reader.write_lines(writer))?;
+        self.fd.seek(SeekFrom::Start(self.end))?;
+        Ok(())
+    }
+
+    fn write_lines(&mut self, writer: &mut dyn Write) -> Result<()> {
+        if self.num_of_lines == 0 && self.num_of_bytes.is_none() {
+            return Ok(());
+        }
+        while self.read()? {}
+
+        // Any delimiters beyond the ones wanted are all in the first chunk, as
+        // the scan stops at the chunk where the count is reached. The wanted
+        // lines start after the last of those extra ones.
+        let mut start = 0;
+        if self.num_of_bytes.is_none() && self.total_newlines >= self.num_of_lines {
+            let extra = self.total_newlines - self.num_of_lines + 1;
+            if let Some(first) = self.pieces.front() {
+                start = first.iter().enumerate()
+                    .filter(|&(_, b)| *b == self.delimiter)
+                    .nth(extra - 1)
+                    .map_or(0, |(i, _)| i + 1);
+            }
+        }
+        for (i, piece) in self.pieces.drain(..).enumerate() {
+            writer.write_all(if i == 0 { &piece[start..] } else { &piece })?;
         }
-        self.total_newlines < self.num_of_lines
+        Ok(())
     }
 
-    pub fn read_all<T: Write>(&mut self, writer: &mut BufWriter<T>) {
-        while self.read() {}
+    /// Writes the same output as `read_all`, without holding the selected lines
+    /// in memory.
+    ///
+    /// The backward scan only counts newlines to find where the first wanted
+    /// line starts, then that region is copied forward to `writer`, so memory
+    /// use stays at one buffer no matter how long the lines are. Use this
+    /// instead of `read_all`, not after it.
+    pub fn read_all_streaming<W: Write>(&mut self, writer: &mut W) -> Result<()> {
+        let end = self.last_offset;
+        let start = self.find_start_of_tail(end)?;
+        self.fd.seek(SeekFrom::Start(start))?;
+        self.joined(writer, |reader, writer| {
+            io::copy(&mut reader.fd.by_ref().take(end - start), writer)?;
+            Ok(())
+        })
+    }
+
+    /// Yields the lines `read_all` would write one at a time instead, using
+    /// the reader's delimiter and limits
+    pub fn lines(mut self) -> Result<LineIterator<&'a mut R>> where R: BufRead {
+        let end = self.end;
+        let start = self.find_start_of_tail(end)?;
+        self.fd.seek(SeekFrom::Start(start))?;
+        Ok(LineIterator { source: self.fd.take(end - start), delimiter: self.delimiter })
+    }
+
+    /// Returns the offset of the first of the last `num_of_lines` lines in the
+    /// first `end` bytes of the file
+    fn find_start_of_tail(&mut self, end: u64) -> Result<u64> {
+        if let Some(n) = self.num_of_bytes {
+            return Ok(end.saturating_sub(n).max(self.scan_limit).min(end));
+        }
+        let scan = Scan {
+            num_of_lines: self.num_of_lines,
+            delimiter: self.delimiter,
+            scan_limit: self.scan_limit,
+            buffer_size: self.buffer_size,
+        };
+        Ok(scan.start_of_tail(self.fd, end)?)
+    }
+}
+
+/// How to look back through a file for where its last lines start
+struct Scan {
+    num_of_lines: usize,
+    delimiter: u8,
+    scan_limit: u64,
+    buffer_size: u64,
+}
 
-        // If we hit the top of the file early, there's no guarantee
-        // that total_newlines will be greater than num_of_lines due
-        // to the way failed backward seeks are handled in read()
-        if self.total_newlines > self.num_of_lines {
-            let mut first_chunk = self.pieces.pop_front().unwrap();
-            let pieces_to_discard = self.total_newlines - self.num_of_lines as usize;
-            if pieces_to_discard > 0 {
-                for _ in 0..pieces_to_discard {
-                    first_chunk.pop_front().unwrap();
+impl Scan {
+    /// Returns the offset of the first of the last `num_of_lines` lines in the
+    /// first `end` bytes of `fd`
+    fn start_of_tail<R: Read + Seek>(&self, fd: &mut R, end: u64) -> io::Result<u64> {
+        if self.num_of_lines == 0 || end <= self.scan_limit {
+            return Ok(end);
+        }
+        let mut newlines = 0;
+        let mut buff = vec![0; self.buffer_size as usize];
+        let mut chunk_end = end;
+        while chunk_end > self.scan_limit {
+            let chunk_start = chunk_end.saturating_sub(self.buffer_size).max(self.scan_limit);
+            let chunk = &mut buff[..(chunk_end - chunk_start) as usize];
+            fd.seek(SeekFrom::Start(chunk_start))?;
+            if !fill(fd, chunk)? {
+                // The file shrank mid-scan, so there's nothing sensible before here
+                return Ok(chunk_end);
+            }
+            for (i, byte) in chunk.iter().enumerate().rev() {
+                let offset = chunk_start + i as u64;
+                // The newline ending the last line doesn't start another one
+                if *byte == self.delimiter && offset + 1 != end {
+                    newlines += 1;
+                    if newlines == self.num_of_lines {
+                        return Ok(offset + 1);
+                    }
                 }
-                self.total_newlines -= pieces_to_discard;
             }
-            self.pieces.push_front(first_chunk);
-        }
-
-        if self.pieces.is_empty() { return; }
-
-        let mut line: Vec<u8> = Vec::new();
-        while let Some(mut piece) = self.pieces.pop_front() {
-            if piece.len() == 1 {
-                line.append(piece.pop_front().unwrap().as_mut());
-            } else if piece.len() > 1 {
-                let mut last_chunk = piece.pop_back().unwrap();
-                for mut chunk in piece {
-                    line.append(&mut chunk);
-                    line.push(b'\n');
-                    writer.write(&line).unwrap();
-                    line.clear();
+            chunk_end = chunk_start;
+        }
+        Ok(self.scan_limit)
+    }
+}
+
+/// Returns the last `n` lines of `reader`, oldest first, each without its
+/// newline. Only the end of `reader` is read, however long it is.
+///
+/// ```
+/// # extern crate tail;
+/// # use std::io::Cursor;
+/// let lines = tail::tail_lines(Cursor::new("one\ntwo\nthree"), 2).unwrap();
+/// assert_eq!(lines, vec![b"two".to_vec(), b"three".to_vec()]);
+/// ```
+pub fn tail_lines<R: Read + Seek>(reader: R, n: usize) -> io::Result<Vec<Vec<u8>>> {
+    LineIterator::new(reader, n)?.collect()
+}
+
+/// Yields the last lines of a file one at a time, oldest first, each without
+/// its delimiter, so they can be processed without holding them all.
+///
+/// At most `n` lines come out, fewer if the file is shorter. A last line with
+/// no delimiter is still a line.
+///
+/// ```
+/// # extern crate tail;
+/// # use std::io::Cursor;
+/// # use tail::LineIterator;
+/// let mut lines = LineIterator::new(Cursor::new("one\ntwo\nthree"), 5).unwrap();
+/// assert_eq!(lines.next().unwrap().unwrap(), b"one");
+/// assert_eq!(lines.count(), 2);
+/// ```
+pub struct LineIterator<R> {
+    source: io::Take<R>,
+    delimiter: u8,
+}
+
+impl<R: Read + Seek> LineIterator<BufReader<R>> {
+    /// Finds where the last `n` newline terminated lines of `reader` start.
+    /// They're read from there as the iterator goes.
+    pub fn new(mut reader: R, n: usize) -> io::Result<Self> {
+        let end = reader.seek(SeekFrom::End(0))?;
+        let scan = Scan { num_of_lines: n, delimiter: b'\n', scan_limit: 0, buffer_size: BUFFER_SIZE };
+        let start = scan.start_of_tail(&mut reader, end)?;
+        reader.seek(SeekFrom::Start(start))?;
+        Ok(LineIterator { source: BufReader::new(reader).take(end - start), delimiter: b'\n' })
+    }
+}
+
+impl<R: BufRead> Iterator for LineIterator<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut line = Vec::new();
+        match self.source.read_until(self.delimiter, &mut line) {
+            Ok(0) => None,
+            Ok(_) => {
+                if line.last() == Some(&self.delimiter) {
+                    line.pop();
                 }
-                line.append(&mut last_chunk);
+                Some(Ok(line))
+            },
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// Keeps the last `capacity` items pushed into it, e.g. the last lines of
+/// input that can't be seeked. With a capacity of 0 nothing is kept.
+///
+/// ```
+/// # extern crate tail;
+/// # use tail::RingBuffer;
+/// let mut last = RingBuffer::new(2);
+/// for line in &["one", "two", "three"] {
+///     last.push(*line);
+/// }
+/// assert_eq!(last.len(), 2);
+/// assert_eq!(last.iter().collect::<Vec<_>>(), vec![&"two", &"three"]);
+/// assert_eq!(last.drain().collect::<Vec<_>>(), vec!["two", "three"]);
+/// assert!(last.is_empty());
+/// ```
+#[derive(Debug, Clone)]
+pub struct RingBuffer<T> {
+    items: VecDeque<T>,
+    capacity: usize,
+}
+
+impl<T> RingBuffer<T> {
+    pub fn new(capacity: usize) -> RingBuffer<T> {
+        RingBuffer { items: VecDeque::with_capacity(capacity), capacity }
+    }
+
+    /// Adds `item`, dropping the oldest item if the buffer is full
+    pub fn push(&mut self, item: T) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.items.len() == self.capacity {
+            self.items.pop_front();
+        }
+        self.items.push_back(item);
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The items, oldest first
+    pub fn iter(&self) -> std::collections::vec_deque::Iter<'_, T> {
+        self.items.iter()
+    }
+
+    /// Removes the items, oldest first
+    pub fn drain(&mut self) -> std::collections::vec_deque::Drain<'_, T> {
+        self.items.drain(..)
+    }
+}
+
+impl<T> IntoIterator for RingBuffer<T> {
+    type Item = T;
+    type IntoIter = std::collections::vec_deque::IntoIter<T>;
+
+    /// The items, oldest first
+    fn into_iter(self) -> Self::IntoIter {
+        self.items.into_iter()
+    }
+}
+
+/// The last `n` items of `iter`, in order, or all of them if there are fewer.
+/// Only `n` items are held at a time.
+///
+/// ```
+/// # extern crate tail;
+/// # use tail::last_n;
+/// assert_eq!(last_n(1..=10, 3).collect::<Vec<_>>(), vec![8, 9, 10]);
+/// assert_eq!(last_n(1..=2, 5).collect::<Vec<_>>(), vec![1, 2]);
+/// assert_eq!(last_n(1..=2, 0).count(), 0);
+/// assert_eq!(last_n(std::iter::empty::<u8>(), 3).count(), 0);
+///
+/// let log = "one\ntwo\nthree\n";
+/// assert_eq!(last_n(log.lines(), 2).collect::<Vec<_>>(), vec!["two", "three"]);
+/// ```
+pub fn last_n<I: Iterator>(iter: I, n: usize) -> impl Iterator<Item = I::Item> {
+    let mut last = RingBuffer::new(n);
+    for item in iter {
+        last.push(item);
+    }
+    last.into_iter()
+}
+
+/// Writes lines through to `inner` with `separator` between them instead of
+/// their delimiter. The delimiter ending the last line is held back until
+/// another line follows, or `finish` writes it, so the output ends the way the
+/// input did.
+///
+/// ```
+/// # extern crate tail;
+/// # use tail::JoinWriter;
+/// # use std::io::Write;
+/// let mut out = Vec::new();
+/// let mut joined = JoinWriter::new(&mut out, b'\n', b",");
+/// joined.write_all(b"one\ntwo\n").unwrap();
+/// joined.write_all(b"three\n").unwrap();
+/// joined.finish().unwrap();
+/// assert_eq!(out, b"one,two,three\n");
+/// ```
+pub struct JoinWriter<W: Write> {
+    inner: W,
+    delimiter: u8,
+    separator: Vec<u8>,
+    /// Whether the last line written ended with a delimiter that hasn't been
+    /// replaced yet
+    held: bool,
+}
+
+impl<W: Write> JoinWriter<W> {
+    pub fn new(inner: W, delimiter: u8, separator: &[u8]) -> Self {
+        JoinWriter { inner, delimiter, separator: separator.to_vec(), held: false }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Writes the delimiter held back from the last line, if any
+    pub fn finish(&mut self) -> io::Result<()> {
+        if self.held {
+            self.held = false;
+            self.inner.write_all(&[self.delimiter])?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for JoinWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Joining with the delimiter itself changes nothing, so there's no
+        // need to hold a line's end back until the next line comes
+        if self.separator == [self.delimiter] {
+            return self.inner.write(buf);
+        }
+        let delimiter = self.delimiter;
+        for line in buf.split_inclusive(|b| *b == delimiter) {
+            if self.held {
+                self.held = false;
+                self.inner.write_all(&self.separator)?;
+            }
+            match line.split_last() {
+                Some((&last, text)) if last == delimiter => {
+                    self.inner.write_all(text)?;
+                    self.held = true;
+                },
+                _ => self.inner.write_all(line)?,
             }
         }
-        if !line.is_empty() {
-            writer.write(&line).unwrap();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Fills `buff` from `fd`, returning false if the file ended first
+fn fill<R: Read>(fd: &mut R, buff: &mut [u8]) -> io::Result<bool> {
+    match fd.read_exact(buff) {
+        Ok(()) => Ok(true),
+        Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Skips forward past the next `n` lines of `fd`, leaving it positioned at the
+/// start of the following line (or at EOF if the file has fewer lines). Lines
+/// end with `delimiter`, which is normally `b'\n'`.
+///
+/// Delimiters are counted over raw chunks, so nothing is decoded or allocated
+/// per line. Returns the new offset.
+pub fn seek_to_line<R: Read + Seek>(fd: &mut R, n: usize, delimiter: u8) -> io::Result<u64> {
+    let mut offset = fd.stream_position()?;
+    if n == 0 {
+        return Ok(offset);
+    }
+    let mut remaining = n;
+    let mut buff = vec![0; SKIP_CHUNK_SIZE];
+    loop {
+        let bytes_read = match fd.read(&mut buff) {
+            Ok(0) => return Ok(offset),
+            Ok(bytes_read) => bytes_read,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        // Most chunks are skipped whole, and counting them is quicker than
+        // finding each delimiter
+        let lines = buff[..bytes_read].iter().filter(|b| **b == delimiter).count();
+        if lines < remaining {
+            remaining -= lines;
+            offset += bytes_read as u64;
+            continue;
+        }
+        for (i, _) in buff[..bytes_read].iter().enumerate().filter(|&(_, b)| *b == delimiter) {
+            remaining -= 1;
+            if remaining == 0 {
+                return fd.seek(SeekFrom::Start(offset + i as u64 + 1));
+            }
         }
+        offset += bytes_read as u64;
+    }
+}
+
+/// Counts the `delimiter` terminated lines in `fd` from the start of the file,
+/// including a final line that isn't terminated. The read position is restored
+/// afterwards.
+pub fn count_lines<R: Read + Seek>(fd: &mut R, delimiter: u8) -> io::Result<usize> {
+    let original_offset = fd.stream_position()?;
+    fd.seek(SeekFrom::Start(0))?;
+    let (lines, _) = count_remaining_lines(fd, delimiter)?;
+    fd.seek(SeekFrom::Start(original_offset))?;
+    Ok(lines)
+}
+
+/// Counts the lines from `fd`'s position to its end, as `count_lines` does,
+/// and tells whether the last of them ended with `delimiter`, which it does
+/// if there are none
+///
+/// ```
+/// # extern crate tail;
+/// # use tail::count_remaining_lines;
+/// assert_eq!(count_remaining_lines(&mut &b"one\ntwo"[..], b'\n').unwrap(), (2, false));
+/// assert_eq!(count_remaining_lines(&mut &b"one\n"[..], b'\n').unwrap(), (1, true));
+/// assert_eq!(count_remaining_lines(&mut &b""[..], b'\n').unwrap(), (0, true));
+/// ```
+pub fn count_remaining_lines<R: Read>(fd: &mut R, delimiter: u8) -> io::Result<(usize, bool)> {
+    let mut lines = 0;
+    let mut last_byte = delimiter;
+    let mut buff = vec![0; BUFFER_SIZE as usize];
+    loop {
+        let bytes_read = match fd.read(&mut buff) {
+            Ok(0) => break,
+            Ok(bytes_read) => bytes_read,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        lines += buff[..bytes_read].iter().filter(|b| **b == delimiter).count();
+        last_byte = buff[bytes_read - 1];
+    }
+    let ends_with_delimiter = last_byte == delimiter;
+    if !ends_with_delimiter {
+        lines += 1;
     }
+    Ok((lines, ends_with_delimiter))
+}
+
+/// The inode of the file `metadata` is for, which tells files apart where a
+/// name can move on to another file. Always 0 where files have no inodes.
+#[cfg(unix)]
+pub fn inode_of(metadata: &Metadata) -> u64 {
+    metadata.ino()
+}
+
+#[cfg(not(unix))]
+pub fn inode_of(_metadata: &Metadata) -> u64 {
+    0
 }
 
 #[derive(Debug)]
@@ -147,46 +867,610 @@ pub struct StatefulFile {
     pub fd: BufReader<File>,
     pub old_metadata: Metadata,
     file_name: String,
-    cursor: SeekFrom,
+    /// The inode of the file that was opened, to tell when the name has moved
+    /// on to another file. Always 0 where files have no inodes.
+    inode: u64,
+    cursor: u64,
+    size_warned: bool,
+    /// The start of a line whose end hasn't been written yet
+    partial: Vec<u8>,
 }
 
 impl StatefulFile {
-    pub fn new(fd: File, file_name: String) -> Self {
-        StatefulFile {
-            old_metadata: fd.metadata()
-                .unwrap_or_else(|_| { panic!("Could not retrieve metadata for file: {}", &file_name) }),
+    /// Fails if the file's metadata can't be read
+    pub fn new(fd: File, file_name: String) -> io::Result<Self> {
+        let old_metadata = fd.metadata()?;
+        Ok(StatefulFile {
+            inode: inode_of(&old_metadata),
+            old_metadata,
             fd: BufReader::new(fd),
-            file_name: file_name,
-            cursor: SeekFrom::Start(0),
+            file_name,
+            cursor: 0,
+            size_warned: false,
+            partial: Vec::new(),
+        })
+    }
+
+    pub fn file_name(&self) -> &str {
+        &self.file_name
+    }
+
+    pub fn inode(&self) -> u64 {
+        self.inode
+    }
+
+    /// Whether the file's name now leads to a different file than the one
+    /// that was opened, as after a rotation that renames the file and creates
+    /// a new one in its place. A name that leads nowhere isn't counted, as
+    /// there's nothing to reopen yet. A rotation that copies the file and
+    /// truncates it keeps the inode, and shows up as a truncation instead.
+    #[cfg(unix)]
+    pub fn was_rotated(&self) -> bool {
+        match fs::metadata(&self.file_name) {
+            Ok(metadata) => metadata.ino() != self.inode || metadata.dev() != self.old_metadata.dev(),
+            Err(_) => false,
         }
     }
 
-    pub fn update_metadata(&mut self) {
-        self.old_metadata = self.fd.get_ref().metadata()
-            .unwrap_or_else(|_| { panic!("Could not retrieve metadata for file: {}", self.file_name) });
+    /// Without inodes, the name leads to another file if that file's length
+    /// or modification time differs from the open one's.
+    #[cfg(not(unix))]
+    pub fn was_rotated(&self) -> bool {
+        match (fs::metadata(&self.file_name), self.fd.get_ref().metadata()) {
+            (Ok(named), Ok(open)) => named.len() != open.len() || named.modified().ok() != open.modified().ok(),
+            _ => false,
+        }
+    }
+
+    /// Fails if the metadata can't be read, leaving the old metadata as it
+    /// was, e.g. so the file can be checked again later
+    pub fn update_metadata(&mut self) -> io::Result<()> {
+        self.old_metadata = self.fd.get_ref().metadata()?;
+        Ok(())
     }
 
-    pub fn modification_type(&self) -> ModificationType {
-        let new_metadata = self.fd.get_ref().metadata()
-            .unwrap_or_else(|_| { panic!("Could not retrieve metadata for file: {}", self.file_name) });
-        if new_metadata.len() > self.old_metadata.len() {
+    /// How the file's size has changed since the metadata was last updated
+    pub fn modification_type(&self) -> io::Result<ModificationType> {
+        let new_metadata = self.fd.get_ref().metadata()?;
+        Ok(if new_metadata.len() > self.old_metadata.len() {
             ModificationType::Added
         } else if new_metadata.len() < self.old_metadata.len() {
             ModificationType::Removed
         } else {
             ModificationType::NoChange
-        }
+        })
+    }
+
+    /// Counts the `delimiter` terminated lines in the file as it was when its
+    /// metadata was last updated, including a final line that isn't
+    /// terminated. The read position is restored afterwards, and the cursor
+    /// is left alone.
+    ///
+    /// ```
+    /// # extern crate tail;
+    /// # use tail::StatefulFile;
+    /// # use std::fs::{self, File};
+    /// let path = std::env::temp_dir().join("tail-count-lines-example.log");
+    /// fs::write(&path, "one\ntwo\nthree").unwrap();
+    /// let mut sf = StatefulFile::new(File::open(&path).unwrap(), String::from("example.log")).unwrap();
+    /// assert_eq!(sf.count_lines(b'\n').unwrap(), 3);
+    /// # fs::remove_file(&path).unwrap();
+    /// ```
+    pub fn count_lines(&mut self, delimiter: u8) -> io::Result<usize> {
+        let original_offset = self.fd.stream_position()?;
+        self.fd.seek(SeekFrom::Start(0))?;
+        let counted = count_remaining_lines(&mut self.fd.by_ref().take(self.old_metadata.len()), delimiter);
+        self.fd.seek(SeekFrom::Start(original_offset))?;
+        counted.map(|(lines, _)| lines)
+    }
+
+    pub fn seek_to_cursor(&mut self) -> io::Result<()> {
+        self.fd.seek(SeekFrom::Start(self.cursor))?;
+        Ok(())
     }
 
-    pub fn seek_to_cursor(&mut self) {
-        self.fd.seek(self.cursor).unwrap();
+    /// Moves the cursor to the read position. On error the cursor is left
+    /// where it was.
+    pub fn update_cursor(&mut self) -> io::Result<()> {
+        self.cursor = self.fd.stream_position()?;
+        Ok(())
     }
 
-    pub fn update_cursor(&mut self) {
-        self.cursor = SeekFrom::Start(self.fd.seek(SeekFrom::Current(0)).unwrap());
+    /// The offset following will resume from
+    pub fn cursor(&self) -> u64 {
+        self.cursor
     }
 
     pub fn reset_cursor(&mut self) {
-        self.cursor = SeekFrom::Start(0);
+        self.cursor = 0;
+    }
+
+    pub fn set_cursor(&mut self, offset: u64) {
+        self.cursor = offset;
+    }
+
+    /// Takes the start of a line that was read without its delimiter, for
+    /// the rest to be appended to once it's written
+    pub fn take_partial(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.partial)
+    }
+
+    /// Keeps the start of a line that was read without its delimiter until
+    /// the rest is read. See `take_partial`.
+    pub fn keep_partial(&mut self, partial: Vec<u8>) {
+        self.partial = partial;
+    }
+
+    /// Opens `file_name` again, e.g. after the file was replaced, and resets the
+    /// cursor to the start of the new file.
+    ///
+    /// On error the old descriptor is kept. Errors are returned as is, so a
+    /// path that has become a symlink loop shows up as `ELOOP` for the caller to
+    /// report, rather than aborting.
+    pub fn reopen(&mut self) -> io::Result<()> {
+        let fd = File::open(&self.file_name)?;
+        self.old_metadata = fd.metadata()?;
+        self.inode = inode_of(&self.old_metadata);
+        self.fd = BufReader::new(fd);
+        self.reset_cursor();
+        Ok(())
+    }
+
+    /// Returns true the first time the last seen file length reaches `threshold`.
+    /// Shrinking back below the threshold (e.g. a truncation) re-arms the check.
+    pub fn check_size_threshold(&mut self, threshold: u64) -> bool {
+        if self.old_metadata.len() < threshold {
+            self.size_warned = false;
+            return false;
+        }
+        if self.size_warned {
+            return false;
+        }
+        self.size_warned = true;
+        true
+    }
+}
+
+/// How much of the end of the file `TailBuilder` prints
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Amount {
+    Lines(usize),
+    Bytes(u64),
+}
+
+/// Prints the end of a file, and optionally what's appended to it after, the
+/// way the `tail` command does
+///
+/// # Examples
+///
+/// ```
+/// # extern crate tail;
+/// # use tail::TailBuilder;
+/// # use std::fs;
+/// let path = std::env::temp_dir().join("tail-builder-example.log");
+/// fs::write(&path, "one\ntwo\nthree\n").unwrap();
+///
+/// let mut out = Vec::new();
+/// TailBuilder::new(&path).lines(2).run(&mut out).unwrap();
+/// assert_eq!(out, b"two\nthree\n");
+///
+/// out.clear();
+/// TailBuilder::new(&path).bytes(3).run(&mut out).unwrap();
+/// assert_eq!(out, b"ee\n");
+/// # fs::remove_file(&path).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct TailBuilder {
+    path: PathBuf,
+    amount: Amount,
+    delimiter: u8,
+    follow: bool,
+    sleep_interval: Duration,
+    max_scan_bytes: Option<u64>,
+    skip_bom: bool,
+}
+
+impl TailBuilder {
+    /// The last 10 lines of `path`, without following
+    pub fn new<P: AsRef<Path>>(path: P) -> TailBuilder {
+        TailBuilder {
+            path: path.as_ref().to_path_buf(),
+            amount: Amount::Lines(DEFAULT_LINES),
+            delimiter: b'\n',
+            follow: false,
+            sleep_interval: DEFAULT_SLEEP_INTERVAL,
+            max_scan_bytes: None,
+            skip_bom: false,
+        }
+    }
+
+    /// Prints the last `n` lines
+    pub fn lines(mut self, n: usize) -> Self {
+        self.amount = Amount::Lines(n);
+        self
+    }
+
+    /// Prints the last `n` bytes instead of lines
+    pub fn bytes(mut self, n: u64) -> Self {
+        self.amount = Amount::Bytes(n);
+        self
+    }
+
+    /// What lines end with, `b'\n'` unless set
+    pub fn delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Keeps printing what's appended to the file after the initial output
+    pub fn follow(mut self, follow: bool) -> Self {
+        self.follow = follow;
+        self
+    }
+
+    /// How often a followed file is checked for new data, every second unless
+    /// set
+    pub fn sleep_interval(mut self, interval: Duration) -> Self {
+        self.sleep_interval = interval;
+        self
+    }
+
+    /// See `BackwardsReader::max_scan_bytes`
+    pub fn max_scan_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_scan_bytes = Some(max_bytes);
+        self
+    }
+
+    /// See `BackwardsReader::skip_bom`
+    pub fn skip_bom(mut self) -> Self {
+        self.skip_bom = true;
+        self
+    }
+
+    /// Opens the file and prints its end to `writer`. When following, this
+    /// then polls the file for as long as it can be read, so it only returns
+    /// on an error, which is a `TailError::Read` naming the file. A truncated
+    /// file is carried on with from its new end.
+    pub fn run<W: Write>(self, writer: W) -> Result<()> {
+        let path = self.path.clone();
+        self.tail_file(writer).map_err(|e| e.at_path(path))
+    }
+
+    fn tail_file<W: Write>(self, mut writer: W) -> Result<()> {
+        let fd = File::open(&self.path)?;
+        let mut sf = StatefulFile::new(fd, self.path.to_string_lossy().into_owned())?;
+        self.write_tail(&mut sf, &mut writer)?;
+        writer.flush()?;
+        if !self.follow {
+            return Ok(());
+        }
+        sf.update_cursor()?;
+        loop {
+            thread::sleep(self.sleep_interval);
+            let modification = sf.modification_type()?;
+            sf.update_metadata()?;
+            if let ModificationType::Removed = modification {
+                let len = sf.old_metadata.len();
+                sf.set_cursor(len);
+            }
+            sf.seek_to_cursor()?;
+            io::copy(&mut sf.fd, &mut writer)?;
+            sf.update_cursor()?;
+            writer.flush()?;
+        }
+    }
+
+    /// Prints the end of `sf`, which is already open, to `writer`, leaving
+    /// `sf` at the end of the file as it was when its metadata was last
+    /// updated. That's where following should carry on from, as whatever was
+    /// appended since hasn't been printed. Following is up to the caller.
+    ///
+    /// Errors are a `TailError::Read` naming `sf`.
+    pub fn write_tail<W: Write>(&self, sf: &mut StatefulFile, writer: &mut W) -> Result<()> {
+        let written = match self.amount {
+            Amount::Lines(n) => {
+                let len = sf.old_metadata.len();
+                BackwardsReader::new(n, &mut sf.fd, self.delimiter).and_then(|reader| {
+                    let mut reader = reader.ending_at(len);
+                    if let Some(max_bytes) = self.max_scan_bytes {
+                        reader = reader.max_scan_bytes(max_bytes);
+                    }
+                    if self.skip_bom {
+                        reader = reader.skip_bom();
+                    }
+                    reader.read_all(writer)
+                })
+            },
+            Amount::Bytes(n) => {
+                let len = sf.old_metadata.len();
+                BackwardsReader::new_bytes(n, &mut sf.fd)
+                    .and_then(|reader| reader.ending_at(len).read_all_streaming(writer))
+            },
+        };
+        written.map_err(|e| e.at_path(sf.file_name()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    /// A file of its own for each test, so they can run at the same time
+    fn temp_file(name: &str, contents: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("tail-lib-{}-{}", std::process::id(), name));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn open(path: &Path) -> StatefulFile {
+        StatefulFile::new(File::open(path).unwrap(), path.to_str().unwrap().to_string()).unwrap()
+    }
+
+    /// The last `n` lines of `data` the slow way, for the readers to match
+    fn expected_tail(data: &[u8], n: usize, delimiter: u8) -> Vec<u8> {
+        let lines: Vec<&[u8]> = data.split_inclusive(|b| *b == delimiter).collect();
+        lines[lines.len().saturating_sub(n)..].concat()
+    }
+
+    fn backwards(data: &[u8], n: usize, buffer_size: u64, delimiter: u8, streaming: bool) -> Vec<u8> {
+        let mut fd = io::Cursor::new(data.to_vec());
+        let mut reader = BackwardsReader::new(n, &mut fd, delimiter).unwrap().buffer_size(buffer_size);
+        let mut out = Vec::new();
+        if streaming {
+            reader.read_all_streaming(&mut out).unwrap();
+        } else {
+            reader.read_all(&mut out).unwrap();
+        }
+        out
+    }
+
+    #[test]
+    fn backwards_reader_reads_the_last_lines() {
+        let mut fd = io::Cursor::new(b"one\ntwo\nthree\n".to_vec());
+        let mut out = Vec::new();
+        BackwardsReader::new(2, &mut fd, b'\n').unwrap().read_all(&mut out).unwrap();
+        assert_eq!(out, b"two\nthree\n");
+    }
+
+    /// Fails every write, as stdout does once the disk is full
+    struct FullWriter;
+
+    impl Write for FullWriter {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::StorageFull, "no space left"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn write_errors_are_returned_not_panicked_on() {
+        for streaming in [false, true] {
+            let mut fd = io::Cursor::new(b"one\ntwo\nthree\n".to_vec());
+            let mut reader = BackwardsReader::new(2, &mut fd, b'\n').unwrap();
+            let result = if streaming {
+                reader.read_all_streaming(&mut FullWriter)
+            } else {
+                reader.read_all(&mut FullWriter)
+            };
+            match result {
+                Err(e) => assert_eq!(e.io_error().kind(), io::ErrorKind::StorageFull, "streaming: {}", streaming),
+                Ok(()) => panic!("the write error was lost, streaming: {}", streaming),
+            }
+        }
+    }
+
+    #[test]
+    fn max_scan_bytes_stops_short_of_lines_beyond_it() {
+        let lines: Vec<u8> = (0..20).flat_map(|i| format!("line {:02}\n", i).into_bytes()).collect();
+        // The window starts part way through "line 17"
+        for streaming in [false, true] {
+            let mut fd = io::Cursor::new(lines.clone());
+            let mut out = Vec::new();
+            let mut reader = BackwardsReader::new(10, &mut fd, b'\n').unwrap().max_scan_bytes(20);
+            if streaming {
+                reader.read_all_streaming(&mut out).unwrap();
+            } else {
+                reader.read_all(&mut out).unwrap();
+            }
+            assert_eq!(out, b" 17\nline 18\nline 19\n", "streaming: {}", streaming);
+        }
+    }
+
+    #[test]
+    fn one_byte_buffer_joins_lines_across_every_read() {
+        let data: Vec<u8> = (0..200).flat_map(|i| format!("line {} {}\n", i, "x".repeat(i % 13)).into_bytes()).collect();
+        for &n in &[1, 50, 200, 300] {
+            for &streaming in &[false, true] {
+                assert_eq!(backwards(&data, n, 1, b'\n', streaming), expected_tail(&data, n, b'\n'),
+                           "n {}, streaming {}", n, streaming);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "greater than 0")]
+    fn buffer_size_must_be_positive() {
+        let mut fd = io::Cursor::new(b"a\n".to_vec());
+        let _ = BackwardsReader::new(1, &mut fd, b'\n').unwrap().buffer_size(0);
+    }
+
+    #[test]
+    fn ending_at_leaves_out_what_comes_after() {
+        let samples: &[&[u8]] = &[b"", b"\n", b"\n\n\n", b"one", b"one\ntwo\nthree", b"one\ntwo\nthree\n"];
+        for data in samples {
+            for end in 0..data.len() + 2 {
+                let opened = &data[..end.min(data.len())];
+                for n in 0..4 {
+                    for &streaming in &[false, true] {
+                        let mut fd = io::Cursor::new(data.to_vec());
+                        let mut reader = BackwardsReader::new(n, &mut fd, b'\n').unwrap().buffer_size(2).ending_at(end as u64);
+                        let mut out = Vec::new();
+                        if streaming {
+                            reader.read_all_streaming(&mut out).unwrap();
+                        } else {
+                            reader.read_all(&mut out).unwrap();
+                        }
+                        assert_eq!(out, expected_tail(opened, n, b'\n'), "{:?} ending at {}, n {}, streaming {}",
+                                   String::from_utf8_lossy(data), end, n, streaming);
+                        assert_eq!(fd.position(), opened.len() as u64, "following carries on from the end");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn backwards_reader_splits_on_the_delimiter_given() {
+        let data = b"one\0two\nstill two\0three\0";
+        assert_eq!(backwards(data, 2, 3, b'\0', false), b"two\nstill two\0three\0");
+        assert_eq!(backwards(data, 2, 3, b'\0', true), b"two\nstill two\0three\0");
+    }
+
+    #[test]
+    fn skip_bom_starts_the_file_after_the_mark() {
+        let with_bom: &[u8] = b"\xEF\xBB\xBFone\ntwo\n";
+        // Only at the start of the file
+        let later: &[u8] = b"a\n\xEF\xBB\xBFb\n";
+        let cases: &[(&[u8], usize, &[u8])] = &[
+            (with_bom, 1, b"two\n"),
+            (with_bom, 2, b"one\ntwo\n"),
+            (with_bom, 5, b"one\ntwo\n"),
+            (later, 2, b"a\n\xEF\xBB\xBFb\n"),
+        ];
+        for &(data, n, expected) in cases {
+            for streaming in [false, true] {
+                let mut fd = io::Cursor::new(data.to_vec());
+                let mut out = Vec::new();
+                let mut reader = BackwardsReader::new(n, &mut fd, b'\n').unwrap().skip_bom();
+                if streaming {
+                    reader.read_all_streaming(&mut out).unwrap();
+                } else {
+                    reader.read_all(&mut out).unwrap();
+                }
+                assert_eq!(out, expected, "{:?}, n {}, streaming: {}", String::from_utf8_lossy(data), n, streaming);
+            }
+        }
+    }
+
+    #[test]
+    fn seek_to_line_lands_where_skipping_lines_does() {
+        // Lines that straddle the chunks it counts over, as well as short ones
+        let long: Vec<u8> = (0..3 * SKIP_CHUNK_SIZE).map(|i| if i % 4000 == 3999 { b'\n' } else { b'x' }).collect();
+        let samples: &[&[u8]] = &[b"", b"\n", b"\n\n\n", b"one", b"one\ntwo\nthree", b"one\ntwo\nthree\n", &long];
+        for data in samples {
+            let lines = data.split_inclusive(|b| *b == b'\n').count();
+            for n in 0..(lines + 2) {
+                let skipped: Vec<u8> = data.split_inclusive(|b| *b == b'\n').skip(n).flatten().cloned().collect();
+                let mut fd = io::Cursor::new(data.to_vec());
+                let offset = seek_to_line(&mut fd, n, b'\n').unwrap();
+                assert_eq!(offset, (data.len() - skipped.len()) as u64, "{} lines in", n);
+                let mut rest = Vec::new();
+                fd.read_to_end(&mut rest).unwrap();
+                assert_eq!(rest, skipped, "{:?}, {} lines in", String::from_utf8_lossy(data), n);
+            }
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn reopening_a_symlink_loop_keeps_the_old_file() {
+        let path = temp_file("looped.log", b"old\n");
+        let mut sf = open(&path);
+        fs::remove_file(&path).unwrap();
+        std::os::unix::fs::symlink(&path, &path).unwrap();
+        let err = sf.reopen().unwrap_err();
+        // ELOOP
+        assert_eq!(err.raw_os_error(), Some(40), "{}", err);
+        assert_eq!(sf.old_metadata.len(), 4);
+        let mut rest = String::new();
+        sf.fd.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "old\n");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn skips_and_counts_by_the_delimiter_given() {
+        let data = b"a\0b\nstill b\0c";
+        let mut fd = io::Cursor::new(data.to_vec());
+        assert_eq!(count_lines(&mut fd, b'\0').unwrap(), 3);
+        assert_eq!(count_lines(&mut fd, b'\n').unwrap(), 2);
+        assert_eq!(seek_to_line(&mut fd, 2, b'\0').unwrap(), 12);
+        let mut rest = Vec::new();
+        fd.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"c");
+    }
+
+    #[test]
+    fn ring_buffer_drops_the_oldest_once_it_wraps() {
+        for capacity in 1..5 {
+            let mut last = RingBuffer::new(capacity);
+            for i in 0..capacity + 2 {
+                last.push(i);
+            }
+            assert_eq!(last.len(), capacity);
+            assert_eq!(last.drain().collect::<Vec<_>>(), (2..capacity + 2).collect::<Vec<_>>());
+            // Still wraps after being emptied from the front
+            for i in 0..capacity + 2 {
+                last.push(i);
+            }
+            assert_eq!(last.iter().next(), Some(&2));
+        }
+        let mut none = RingBuffer::new(0);
+        none.push(1);
+        assert!(none.is_empty());
+    }
+
+    #[test]
+    fn rotation_is_a_new_file_under_the_name() {
+        let path = temp_file("rotated.log", b"old\n");
+        let sf = open(&path);
+        assert!(!sf.was_rotated());
+        fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(b"more\n").unwrap();
+        assert!(!sf.was_rotated());
+
+        let moved = path.with_extension("log.1");
+        fs::rename(&path, &moved).unwrap();
+        assert!(!sf.was_rotated(), "a name leading nowhere isn't a rotation");
+        fs::write(&path, b"new\n").unwrap();
+        assert!(sf.was_rotated());
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&moved).unwrap();
     }
-}
\ No newline at end of file
+
+    #[cfg(unix)]
+    #[test]
+    fn copy_truncate_keeps_the_file() {
+        let path = temp_file("copied.log", b"old\nolder\n");
+        let sf = open(&path);
+        let copy = path.with_extension("log.1");
+        fs::copy(&path, &copy).unwrap();
+        fs::OpenOptions::new().write(true).open(&path).unwrap().set_len(0).unwrap();
+        assert!(!sf.was_rotated());
+        fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(b"new\n").unwrap();
+        assert!(!sf.was_rotated());
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&copy).unwrap();
+    }
+
+    #[test]
+    fn reopening_follows_the_name() {
+        let path = temp_file("reopened.log", b"old\n");
+        let mut sf = open(&path);
+        sf.fd.seek(SeekFrom::End(0)).unwrap();
+        sf.update_cursor().unwrap();
+        let moved = path.with_extension("log.1");
+        fs::rename(&path, &moved).unwrap();
+        fs::write(&path, b"newer\n").unwrap();
+        sf.reopen().unwrap();
+        assert!(!sf.was_rotated());
+        assert_eq!(sf.cursor(), 0);
+        assert_eq!(sf.old_metadata.len(), 6);
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&moved).unwrap();
+    }
+}
@@ -1,67 +1,507 @@
+#[cfg(target_os = "linux")]
 extern crate inotify;
 extern crate getopts;
+extern crate libc;
 extern crate tail;
 
+mod checksum;
+mod config;
+mod filter;
+mod glob;
+mod gzip;
+mod json;
+mod jsonl;
+mod numbering;
+mod pattern;
+mod pid;
+mod pool;
+mod report;
+mod route;
+mod signals;
+mod state;
+mod timers;
+mod timestamp;
+mod watch;
+
 use std::path::Path;
 use std::iter::Iterator;
-use std::io::{Read, BufRead, Write, BufWriter};
-use std::fs::File;
-use std::collections::HashMap;
-use inotify::{Inotify, WatchMask, EventMask};
+use std::io::{self, Read, BufRead, Write, BufWriter, Cursor, Seek, SeekFrom, IsTerminal};
+use std::fs::{File, Metadata, OpenOptions};
+#[cfg(unix)]
+use std::os::unix::fs::{FileTypeExt, MetadataExt, OpenOptionsExt};
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, SystemTime};
+use std::sync::atomic::{AtomicU8, Ordering};
 use getopts::Options;
-use tail::{StatefulFile, ModificationType, BackwardsReader};
+use filter::{FilteredWriter, LineFilter, LineFilters};
+use tail::{StatefulFile, ModificationType, TailBuilder, JoinWriter, RingBuffer, seek_to_line, count_remaining_lines,
+           io_error_message};
+use tail::clock::SystemClock;
+use tail::encoding::{self, Encoding, Utf8Decoder};
+use checksum::ChecksumWriter;
+use glob::Glob;
+use numbering::{LineNumbers, NumberedWriter};
+use jsonl::RecordWriter;
+use report::{ErrorReport, Problem};
+use pid::{PidMode, PidWatch};
+use pool::OrderedPool;
+use route::Router;
+use state::StateFile;
+use timers::Timers;
+use watch::{EventKind, FileWatcher, RawFd, WaitResult, WatchId};
 
 #[allow(dead_code)]
-static USAGE: &'static str = r#"Usage: tail [OPTION]... [FILE]...
+static USAGE: &str = r#"Usage: tail [OPTION]... [FILE]...
 Print the last 10 lines of each FILE to standard output.
 With more than one FILE, precede each with a header giving the file name.
 
-With no FILE, or when FILE is -, read standard input.
+With no FILE, or when FILE is -, read standard input. Arguments after --
+are all FILEs, even those starting with -, e.g. tail -- -file.log.
 
 Mandatory arguments to long options are mandatory for short options too.
   -c, --bytes=[+]NUM      output the last NUM bytes; or use -c +NUM to
-                             output starting with byte NUM of each file
-  -f, --follow            output appended data as the file grows;
+                             output starting with byte NUM of each file, or
+                             -c START-END for the same as --byte-range
+      --byte-range=START-END
+                           output bytes START up to (not including) END,
+                             counting from 0; END is clamped to the file size
+  -f, --follow[={name|descriptor}]
+                           output appended data as the file grows;
+                             an absent option argument means 'descriptor'
   -F                       same as --follow=name --retry
+  -s, --sleep-interval=SECS
+                           with --follow, check every SECS (default 1.0)
+                             whether the --pid processes are alive, if the
+                             kernel can't report when they exit, whether
+                             --retry files can be opened, whether polled
+                             files have changed and, with --follow=name,
+                             whether a name has moved on
+      --poll               with --follow, check the files for changes every
+                             --sleep-interval instead of relying on inotify or
+                             kqueue, which don't see changes made on another
+                             machine to a network file system; tail also polls
+                             files they refuse to watch
+      --max-unchanged-stats=N
+                           with --follow=name, reopen a FILE whose size hasn't
+                             changed for N checks (default 5000) to make sure
+                             its name still leads to the file being read; this
+                             catches a replacement that reused the old file's
+                             inode, which comparing inodes alone can't
+      --retry              keep trying to open a file if it is inaccessible
+      --glob               expand the wildcards *, ? and [...] in the file
+                             names of FILE arguments, e.g. 'app-*.log';
+                             with --follow and --retry, files created later
+                             that match are followed too, and a pattern
+                             nothing matches yet waits for a match instead of
+                             failing
   -n, --lines=[+]NUM       output the last NUM lines, instead of the last 10;
-                             or use -n +NUM to output starting with line NUM
+                             or use -n +NUM to output starting with line NUM;
+                             -n -NUM is the same as -n NUM, and -NUM as the
+                             first argument is short for -n NUM
+      --max-scan-bytes=BYTES
+                           read at most BYTES back from the end of a file to
+                             find the last NUM lines; if they start further
+                             back, output the lines within those BYTES
+      --head-tail=K:N      output the first K and the last N lines, noting how
+                             many lines were left out in between
+      --encoding=ENC       decode the initial lines from ENC (utf-8, utf-16le,
+                             utf-16be or latin-1) before printing them as
+                             UTF-8; 'auto' guesses ENC from a BOM, NUL bytes
+                             and UTF-8 validity, falling back to latin-1
+      --strip-bom          leave out a UTF-8 byte order mark at the start of
+                             a file when the output includes it
+      --binary             print files that look binary even when the output
+                             is a terminal, instead of skipping them
+      --line-numbers       prefix each line with its number in its file,
+                             also while following; byte counts aren't
+                             numbered
   -q, --quiet              never output headers giving file names
   -v, --verbose            always output headers giving file names
+      --global-number      with --follow, prefix each appended line with a
+                             number that counts up across all files
+      --timestamps         with --follow, prefix each appended line with the
+                             UTC time it was read, e.g. 2024-01-01T12:00:00Z;
+                             the initial lines aren't timestamped
+      --json-complete      with --follow, hold back appended JSON until the
+                             object or array being written is complete, so
+                             partial writes aren't output half way through
+      --header-once        with --follow, print each file's header only the
+                             first time output switches to it
+      --reverse-files      output the files in reverse command-line order
+      --threads=N          read the initial output of up to N files at once
+                             (default 1), which helps with many files on slow
+                             storage; each file's output is still written
+                             whole and in order; following still reads the
+                             files one at a time
+      --quiet-initial      with --follow, skip the initial output and only
+                             output what's appended from now on
+      --exit-on-idle=SECS  with --follow, exit with status 124, as timeout(1)
+                             does, once no file has grown for SECS
+      --timeout=SECS       with --follow, exit after SECS no matter what
+      --pid=PID[,PID]...   with --follow, exit once the processes PID have
+                             exited, as decided by --pid-mode
+      --pid-mode=MODE      with --pid, exit when 'any' of the processes has
+                             exited, or when 'all' of them have (the default)
+      --warn-size=BYTES    with --follow, warn on stderr once a file grows
+                             to BYTES; re-armed if the file is truncated
+      --no-follow          don't follow, even if the --config file says to;
+                             -f still does
+      --filter=REGEX       output only the lines that match REGEX, also while
+                             following
+      --prefix=STR         put STR in front of every line that's output
+      --error-report[=PATH]
+                           on exit, summarize the files that had errors, were
+                             truncated or were skipped, on stderr or in PATH
+      --end-marker[=FORMAT]
+                           print a line once each file's output is over,
+                             which with --follow is when tail stops following
+                             it; '{file}' in FORMAT is replaced by the file
+                             name (default '#eof file={file}')
+      --checksum           after each file's initial output, print a checksum
+                             of the bytes written for it to stderr
+      --route=REGEX:PATH   with --follow, append lines matching REGEX to PATH
+                             instead of printing them; the first matching
+                             route wins and it may be given more than once
+      --route-default=PATH
+                           with --route, append lines that match no route to
+                             PATH instead of printing them
+      --state-file=PATH    save how far each file has been read in PATH, and
+                             on the next run print only what was appended
+                             since, if the file is the same one; a state file
+                             that can't be read is ignored and replaced
+      --config=PATH        read files and per-file options from PATH; options
+                             on the command line take precedence
+  -z, --zero-terminated    line delimiter is NUL, not newline
+      --output-delimiter=STR
+                           write STR between lines instead of the line
+                             delimiter, like paste -d; the last line keeps its
+                             delimiter, and an empty STR runs lines together
+      --output=FORMAT      'text' (the default), or 'json' to write each line
+                             as {"file":...,"line":...,"ts":...} on a line of
+                             its own, with ts the UTC time tail read it; there
+                             are no headers with 'json'
   -h, --help     display this help and exit
   -V, --version  output version information and exit
 
-NUM may have a multiplier suffix:
+NUM and BYTES may have a multiplier suffix:
 b 512, kB 1000, K 1024, MB 1000*1000, M 1024*1024,
 GB 1000*1000*1000, G 1024*1024*1024, and so on for T, P, E, Z, Y.
 
+Files starting with the gzip magic bytes are decompressed before the lines
+are picked. With --follow, gzip members appended to such a file are printed
+once each member has been written in full.
+
+With --follow, a named pipe is printed as data is written to it, by however
+many writers in turn, rather than from its last lines, as it has no end to
+look back from.
+
+When the output is a terminal, a file with a NUL byte near its start is taken
+to be binary and skipped with a warning, as printing it would garble the
+terminal. That's not done with -z or --encoding, where NUL bytes are expected.
+
+A file given more than once, under the same name or through a link, has its
+last lines printed for each name but is only followed under the first.
+
+While following, sending tail SIGUSR1 makes it print a status line with the
+files it follows, their offsets and how many lines it has printed to stderr.
+SIGUSR2 makes it print the last lines of each file again, as it did when it
+started, e.g. after the scrollback was lost, and then carry on following.
+SIGINT (Ctrl-C) and SIGTERM make it stop between lines and exit normally.
+
 With --follow (-f), tail defaults to following the file descriptor, which
 means that even if a tail'ed file is renamed, tail will continue to track
 its end.  This default behavior is not desirable when you really want to
 track the actual name of the file, not the file descriptor (e.g., log
 rotation).  Use --follow=name in that case.  That causes tail to track the
 named file in a way that accommodates renaming, removal and creation.
+
+SECS may be a fraction, and may end in 's' for seconds (the default), 'm'
+for minutes, 'h' for hours or 'd' for days, e.g. --exit-on-idle=30s.
+
+The exit status is 124 if --exit-on-idle ended following, else 1 if a file
+couldn't be read or the output couldn't be written, else 0.
 "#;
 
 
+/// What to print from each file before following it
+#[derive(Clone)]
+enum DumpMode {
+    /// `-n`: the last NUM lines, or everything after line NUM with `+NUM`
+    Lines(String),
+    /// `--head-tail=K:N`: the first K and the last N lines
+    HeadTail(usize, usize),
+    /// `--byte-range=START-END`: the bytes in `[START, END)`
+    ByteRange(u64, u64),
+    /// `-c`: the last NUM bytes, or everything from byte NUM on with `+NUM`,
+    /// counting from 1
+    Bytes(bool, u64),
+}
+
+/// `--encoding`
+#[derive(Clone, Copy)]
+enum Decoding {
+    Auto,
+    Fixed(Encoding),
+}
+
+/// Options shared by every `DumpMode`
+#[derive(Clone, Copy)]
+struct DumpOptions<'a> {
+    decoding: Option<Decoding>,
+    checksum: bool,
+    /// What ends a line
+    delimiter: u8,
+    /// `--max-scan-bytes`
+    max_scan_bytes: Option<u64>,
+    /// `--strip-bom`
+    strip_bom: bool,
+    /// `--line-numbers`
+    line_numbers: bool,
+    /// `--output=json`
+    json_output: bool,
+    /// `--output-delimiter`, which doesn't apply to JSON records
+    output_delimiter: Option<&'a [u8]>,
+}
+
+impl<'a> DumpOptions<'a> {
+    /// The same options for output that's joined later, as `--threads` does
+    fn without_output_delimiter(self) -> DumpOptions<'static> {
+        DumpOptions {
+            decoding: self.decoding,
+            checksum: self.checksum,
+            delimiter: self.delimiter,
+            max_scan_bytes: self.max_scan_bytes,
+            strip_bom: self.strip_bom,
+            line_numbers: self.line_numbers,
+            json_output: self.json_output,
+            output_delimiter: None,
+        }
+    }
+}
+
+/// Splits a `-n` argument into whether it counts from the start (`+NUM`) and
+/// NUM. `-NUM` is the same as NUM, as in GNU tail.
+fn parse_num_lines(num_lines_str: &str) -> (bool, usize) {
+    let from_start = num_lines_str.starts_with('+');
+    let digits = if from_start { &num_lines_str[1..] } else { num_lines_str.strip_prefix('-').unwrap_or(num_lines_str) };
+    let num_lines = parse_size(digits).and_then(|n| ::std::convert::TryFrom::try_from(n).map_err(|_| SizeError::TooLarge))
+        .unwrap_or_else(|e| {
+            eprintln!("tail: {}", e.describe("lines", num_lines_str));
+            std::process::exit(1);
+        });
+    (from_start, num_lines)
+}
+
+/// Why `parse_size` rejected a size
+#[derive(Debug)]
+enum SizeError {
+    NotANumber,
+    UnknownSuffix(String),
+    TooLarge,
+}
+
+impl SizeError {
+    /// GNU's wording, e.g. `invalid number of bytes: '10Q': unknown suffix 'Q'`
+    fn describe(&self, what: &str, given: &str) -> String {
+        let message = format!("invalid number of {}: '{}'", what, given);
+        match *self {
+            SizeError::NotANumber => message,
+            SizeError::UnknownSuffix(ref suffix) => format!("{}: unknown suffix '{}'", message, suffix),
+            SizeError::TooLarge => format!("{}: value too large", message),
+        }
+    }
+}
+
+/// Multipliers for the suffixes documented in the usage text. Each letter is
+/// a power of 1024 on its own or with `iB`, and a power of 1000 with `B`.
+const SIZE_SUFFIXES: [char; 8] = ['K', 'M', 'G', 'T', 'P', 'E', 'Z', 'Y'];
+
+/// Parses a count such as `-n` or `-c` takes, e.g. `512`, `1K` or `2MB`
+fn parse_size(s: &str) -> Result<u64, SizeError> {
+    let digits_end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (digits, suffix) = s.split_at(digits_end);
+    if digits.is_empty() {
+        return Err(SizeError::NotANumber);
+    }
+    let multiplier = match suffix {
+        "" => 1,
+        "b" => 512,
+        "kB" => 1000,
+        _ => {
+            let mut chars = suffix.chars();
+            let power = chars.next().and_then(|c| SIZE_SUFFIXES.iter().position(|s| *s == c))
+                .ok_or_else(|| SizeError::UnknownSuffix(suffix.to_string()))?;
+            let base: u64 = match chars.as_str() {
+                "" | "iB" => 1024,
+                "B" => 1000,
+                _ => return Err(SizeError::UnknownSuffix(suffix.to_string())),
+            };
+            base.checked_pow(power as u32 + 1).ok_or(SizeError::TooLarge)?
+        },
+    };
+    digits.parse::<u64>().ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .ok_or(SizeError::TooLarge)
+}
+
+/// Splits a `-c` argument into whether it counts from the start (`+NUM`) and
+/// NUM. `+` is the only sign allowed.
+fn parse_num_bytes(s: &str) -> Result<(bool, u64), String> {
+    let from_start = s.starts_with('+');
+    let digits = if from_start { &s[1..] } else { s };
+    let num_bytes = parse_size(digits).map_err(|e| e.describe("bytes", s))?;
+    Ok((from_start, num_bytes))
+}
+
+fn parse_seconds(option: &str, s: &str) -> Result<Duration, String> {
+    let (number, unit) = match s.char_indices().last() {
+        Some((i, 's')) => (&s[..i], 1.0),
+        Some((i, 'm')) => (&s[..i], 60.0),
+        Some((i, 'h')) => (&s[..i], 60.0 * 60.0),
+        Some((i, 'd')) => (&s[..i], 24.0 * 60.0 * 60.0),
+        _ => (s, 1.0),
+    };
+    let secs = number.parse::<f64>().ok().filter(|secs| secs.is_finite() && *secs >= 0.0)
+        .ok_or_else(|| format!("invalid number of seconds for --{}: '{}'", option, s))?;
+    Duration::try_from_secs_f64(secs * unit)
+        .map_err(|_| format!("invalid number of seconds for --{}: '{}': value too large", option, s))
+}
+
+fn parse_byte_range(s: &str) -> Result<(u64, u64), String> {
+    let invalid = || format!("invalid byte range: '{}'", s);
+    let mut parts = s.splitn(2, '-');
+    let start = parts.next().and_then(|p| p.parse::<u64>().ok()).ok_or_else(invalid)?;
+    let end = parts.next().and_then(|p| p.parse::<u64>().ok()).ok_or_else(invalid)?;
+    if start > end {
+        return Err(format!("invalid byte range: '{}': start is past the end", s));
+    }
+    Ok((start, end))
+}
+
+fn parse_head_tail(s: &str) -> Result<DumpMode, String> {
+    let parse = |part: Option<&str>| part.and_then(|p| p.parse::<usize>().ok())
+        .ok_or_else(|| format!("invalid line counts for --head-tail: '{}'; expected K:N", s));
+    let mut parts = s.splitn(2, ':');
+    let head = parse(parts.next())?;
+    let tail = parse(parts.next())?;
+    Ok(DumpMode::HeadTail(head, tail))
+}
+
+/// Whether `-c`/`--bytes` is given after the last `-n`/`--lines` in `args`
+fn bytes_given_last(args: &[String]) -> bool {
+    let mut bytes_last = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        }
+        if !arg.starts_with('-') || arg == "-" {
+            continue;
+        }
+        let value_attached = if let Some(long) = arg.strip_prefix("--") {
+            let name = long.split('=').next().unwrap();
+            match name {
+                "bytes" => bytes_last = true,
+                "lines" => bytes_last = false,
+                _ => continue,
+            }
+            long.contains('=')
+        } else {
+            // A cluster of short flags ends at the first option taking a value
+            match arg[1..].char_indices().find(|&(_, c)| c == 'c' || c == 'n') {
+                Some((i, c)) => {
+                    bytes_last = c == 'c';
+                    i + 2 < arg.len()
+                },
+                None => continue,
+            }
+        };
+        if !value_attached {
+            args.next();
+        }
+    }
+    bytes_last
+}
+
+/// Turns the old `-NUM` shorthand for `-n NUM` into `-n NUM`. As in GNU tail
+/// it's only taken as the first argument, where it can't be mistaken for a
+/// file name or an option's value.
+fn expand_num_lines_shorthand(mut args: Vec<String>) -> Vec<String> {
+    let num = match args.get(1).and_then(|arg| arg.strip_prefix('-')) {
+        Some(num) if !num.is_empty() && num.bytes().all(|b| b.is_ascii_digit()) => num.to_string(),
+        _ => return args,
+    };
+    args.splice(1..2, vec![String::from("-n"), num]);
+    args
+}
+
 fn print_usage() {
     print!("{}", USAGE);
     std::process::exit(0);
 }
 
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
+    signals::restore_sigpipe();
+    let args = expand_num_lines_shorthand(std::env::args().collect());
 
     let mut opts = Options::new();
     opts.optopt("c", "bytes", "output the last NUM bytes", "NUM");
-    opts.optflag("f", "follow", "output appended as the file grows");
-    opts.optflag("F", "", "same as follow with --retry");
+    opts.optopt("", "byte-range", "output the bytes from START up to END", "START-END");
+    opts.optflag("f", "", "output appended data as the file grows");
+    opts.optflagopt("", "follow", "output appended data as the file grows, following the name or descriptor",
+                    "HOW");
+    opts.optflag("F", "", "same as --follow=name --retry");
+    opts.optflag("", "no-follow", "don't follow, even if the config file says to");
+    opts.optopt("s", "sleep-interval", "with --follow, check on --pid and pending files every SECS", "SECS");
+    opts.optflag("", "poll", "with --follow, check the files every --sleep-interval instead of being notified");
+    opts.optflag("", "retry", "keep trying to open a file if it is inaccessible");
+    opts.optflag("", "glob", "expand wildcards in the file names of FILE arguments");
     opts.optopt("n", "lines", "output the last NUM lines, instead of the last 10", "NUM");
+    opts.optflag("", "quiet-initial", "with --follow, only output what's appended from now on");
+    opts.optopt("", "exit-on-idle", "with --follow, exit once no file has grown for SECS", "SECS");
+    opts.optopt("", "timeout", "with --follow, exit after SECS", "SECS");
+    opts.optopt("", "pid", "with --follow, exit once the processes PID have exited", "PID[,PID]...");
+    opts.optopt("", "pid-mode", "with --pid, exit when any or all of them have exited", "MODE");
+    opts.optopt("", "max-unchanged-stats", "with --follow=name, reopen a FILE that hasn't changed for N checks", "N");
+    opts.optopt("", "warn-size", "warn once when a followed file reaches BYTES", "BYTES");
+    opts.optopt("", "max-scan-bytes", "read at most BYTES back from the end to find the last lines", "BYTES");
+    opts.optopt("", "head-tail", "output the first K and the last N lines", "K:N");
+    opts.optopt("", "filter", "output only the lines that match REGEX", "REGEX");
+    opts.optopt("", "prefix", "put STR in front of every line that's output", "STR");
+    opts.optopt("", "encoding", "decode the initial lines from ENC, or guess with 'auto'", "ENC");
+    opts.optflag("", "timestamps", "with --follow, prefix each appended line with the time it was read");
+    opts.optflag("", "line-numbers", "prefix each line with its number in its file");
+    opts.optflag("", "global-number", "with --follow, number appended lines across all files");
+    opts.optflag("", "json-complete", "with --follow, only output JSON values once they're complete");
+    opts.optflag("q", "quiet", "never output headers giving file names");
+    opts.optflag("v", "verbose", "always output headers giving file names");
+    opts.optflag("", "header-once", "with --follow, print each file's header only the first time");
+    opts.optflag("", "strip-bom", "leave out a UTF-8 byte order mark at the start of a file");
+    opts.optflag("", "binary", "print files with NUL bytes in them even to a terminal");
+    opts.optopt("", "threads", "read the initial output of up to N files at once", "N");
+    opts.optflag("", "reverse-files", "output the files in reverse command-line order");
+    opts.optflagopt("", "error-report", "on exit, summarize problems with the files, to stderr or PATH", "PATH");
+    opts.optflagopt("", "end-marker", "print a line once each file's output is over", "FORMAT");
+    opts.optopt("", "output-delimiter", "write STR between lines instead of the line delimiter", "STR");
+    opts.optopt("", "output", "write lines as they are, or as JSON records", "FORMAT");
+    opts.optflag("", "checksum", "print a checksum of each file's initial output to stderr");
+    opts.optmulti("", "route", "with --follow, append lines matching REGEX to PATH", "REGEX:PATH");
+    opts.optopt("", "route-default", "with --route, append unmatched lines to PATH", "PATH");
+    opts.optopt("", "state-file", "resume each file from where the last run stopped, saved in PATH", "PATH");
+    opts.optopt("", "config", "read files and per-file options from PATH", "PATH");
+    opts.optflag("z", "zero-terminated", "line delimiter is NUL, not newline");
     opts.optflag("h", "help", "print this help menu");
     opts.optflag("V", "version", "version of program");
 
     let matches = match opts.parse(&args[1..]) {
         Ok(m) => m,
-        Err(f) => { panic!(f.to_string()) }
+        Err(f) => {
+            // e.g. -NUM and -n given together, which would be two counts
+            eprintln!("tail: {}", f);
+            std::process::exit(1);
+        },
     };
 
     if matches.opt_present("h") {
@@ -72,85 +512,2562 @@ fn main() {
         return;
     }
 
-    if matches.free.is_empty() {
-        eprintln!("Error: Must have at least one file in arguments");
-        print_usage();
-    }
+    let config = match matches.opt_str("config") {
+        Some(path) => config::load(&path).unwrap_or_else(|e| {
+            eprintln!("tail: {}", e);
+            std::process::exit(1);
+        }),
+        None => config::Config::default(),
+    };
 
 
-    let follow_opt = matches.opt_present("f");
-    let num_of_lines = matches.opt_str("n").unwrap_or(String::from("10"));
-    let file_names: Vec<String> = matches.free;
+    let follow_opt = config.follows(matches.opt_present("f") || matches.opt_present("F") || matches.opt_present("follow"),
+                                    matches.opt_present("no-follow"));
+    let follow_mode = match matches.opt_str("follow") {
+        None if matches.opt_present("F") => FollowMode::Name,
+        None => FollowMode::Descriptor,
+        Some(how) => FollowMode::from_name(&how).unwrap_or_else(|| {
+            eprintln!("tail: invalid argument '{}' for '--follow'; expected 'name' or 'descriptor'", how);
+            std::process::exit(1);
+        }),
+    };
+    let retry = matches.opt_present("retry") || matches.opt_present("F");
+    if retry && !follow_opt {
+        eprintln!("tail: warning: --retry ignored; --retry is useful only when following");
+    }
+    let byte_range = matches.opt_str("byte-range").map(|s| {
+        parse_byte_range(&s).unwrap_or_else(|e| {
+            eprintln!("tail: {}", e);
+            std::process::exit(1);
+        })
+    });
+    let num_bytes = matches.opt_str("c").map(|s| {
+        // -c START-END is the same as --byte-range
+        let parsed = if s.get(1..).is_some_and(|rest| rest.contains('-')) {
+            parse_byte_range(&s).map(|(start, end)| DumpMode::ByteRange(start, end))
+        } else {
+            parse_num_bytes(&s).map(|(from_start, num_bytes)| DumpMode::Bytes(from_start, num_bytes))
+        };
+        parsed.unwrap_or_else(|e| {
+            eprintln!("tail: {}", e);
+            std::process::exit(1);
+        })
+    });
+    // As in GNU tail, whichever of -c and -n comes last wins
+    let bytes_win = bytes_given_last(&args[1..]);
+    let cli_options = config::FileOptions {
+        lines: matches.opt_str("n"),
+        head_tail: matches.opt_str("head-tail"),
+        filter: matches.opt_str("filter"),
+        prefix: matches.opt_str("prefix"),
+    };
+    let dump_mode_for = |file_options: &config::FileOptions| {
+        // Command line options win over the file's section, which wins over
+        // the config file's defaults
+        if let Some((start, end)) = byte_range {
+            return DumpMode::ByteRange(start, end);
+        }
+        if let Some(ref bytes_mode) = num_bytes {
+            if bytes_win {
+                return bytes_mode.clone();
+            }
+        }
+        let merged = config::FileOptions::merged(&[&cli_options, file_options, &config.defaults]);
+        match merged.head_tail {
+            Some(s) => parse_head_tail(&s).unwrap_or_else(|e| {
+                eprintln!("tail: {}", e);
+                std::process::exit(1);
+            }),
+            None => DumpMode::Lines(merged.lines.unwrap_or(String::from("10"))),
+        }
+    };
+    let line_filter_for = |file_options: &config::FileOptions| {
+        let merged = config::FileOptions::merged(&[&cli_options, file_options, &config.defaults]);
+        LineFilter::new(merged.filter.as_deref(), merged.prefix.as_deref()).unwrap_or_else(|e| {
+            eprintln!("tail: {}", e);
+            std::process::exit(1);
+        })
+    };
+    let mut line_filters = LineFilters::new(line_filter_for(&config::FileOptions::default()));
+    for file in &config.files {
+        line_filters.insert(&file.path, line_filter_for(&file.options));
+    }
+    let warn_size = matches.opt_str("warn-size").map(|s| {
+        parse_size(&s).unwrap_or_else(|e| {
+            eprintln!("tail: {}", e.describe("bytes for --warn-size", &s));
+            std::process::exit(1);
+        })
+    });
+    let max_unchanged_stats = matches.opt_str("max-unchanged-stats").map_or(DEFAULT_MAX_UNCHANGED_STATS, |s| {
+        s.parse::<u64>().unwrap_or_else(|_| {
+            eprintln!("tail: invalid number for --max-unchanged-stats: '{}'", s);
+            std::process::exit(1);
+        })
+    });
+    let threads = matches.opt_str("threads").map_or(1, |s| match s.parse::<usize>() {
+        Ok(threads) if threads > 0 => threads,
+        _ => {
+            eprintln!("tail: invalid number of threads: '{}'", s);
+            std::process::exit(1);
+        },
+    });
+    let max_scan_bytes = matches.opt_str("max-scan-bytes").map(|s| {
+        parse_size(&s).unwrap_or_else(|e| {
+            eprintln!("tail: {}", e.describe("bytes for --max-scan-bytes", &s));
+            std::process::exit(1);
+        })
+    });
+    let seconds = |option: &str| matches.opt_str(option).map(|s| {
+        parse_seconds(option, &s).unwrap_or_else(|e| {
+            eprintln!("tail: {}", e);
+            std::process::exit(1);
+        })
+    });
+    let exit_on_idle = seconds("exit-on-idle");
+    let timeout = seconds("timeout");
+    let sleep_interval = seconds("sleep-interval").unwrap_or(DEFAULT_SLEEP_INTERVAL);
+    let pid_mode = matches.opt_str("pid-mode").map_or(PidMode::All, |s| {
+        PidMode::from_name(&s).unwrap_or_else(|| {
+            eprintln!("tail: invalid --pid-mode: '{}'; expected 'any' or 'all'", s);
+            std::process::exit(1);
+        })
+    });
+    let mut pid_watch = matches.opt_str("pid").map(|s| {
+        PidWatch::parse(&s, pid_mode).unwrap_or_else(|e| {
+            eprintln!("tail: {}", e);
+            std::process::exit(1);
+        })
+    });
+    let decoding = matches.opt_str("encoding").map(|s| {
+        if s == "auto" {
+            return Decoding::Auto;
+        }
+        Decoding::Fixed(Encoding::from_name(&s).unwrap_or_else(|| {
+            eprintln!("tail: unsupported encoding: '{}'", s);
+            std::process::exit(1);
+        }))
+    });
+    let json_output = match matches.opt_str("output").as_deref() {
+        None | Some("text") => false,
+        Some("json") => true,
+        Some(format) => {
+            eprintln!("tail: invalid output format: '{}'", format);
+            std::process::exit(1);
+        },
+    };
+    let output_delimiter = matches.opt_str("output-delimiter").filter(|_| !json_output);
+    let dump_options = DumpOptions {
+        decoding,
+        checksum: matches.opt_present("checksum"),
+        delimiter: if matches.opt_present("z") { b'\0' } else { b'\n' },
+        max_scan_bytes,
+        strip_bom: matches.opt_present("strip-bom"),
+        line_numbers: matches.opt_present("line-numbers"),
+        json_output,
+        output_delimiter: output_delimiter.as_ref().map(|s| s.as_bytes()),
+    };
+    let error_report_path = if matches.opt_present("error-report") {
+        Some(matches.opt_str("error-report"))
+    } else {
+        None
+    };
+    let routes = matches.opt_strs("route");
+    let route_default = matches.opt_str("route-default");
+    let router = if routes.is_empty() && route_default.is_none() {
+        None
+    } else {
+        let mut router = Router::default();
+        for spec in &routes {
+            router.add_route(spec).unwrap_or_else(|e| {
+                eprintln!("tail: {}", e);
+                std::process::exit(1);
+            });
+        }
+        if let Some(path) = route_default {
+            router.set_default(path);
+        }
+        Some(router)
+    };
+    let mut state = matches.opt_str("state-file").map(|path| {
+        // Printing as on a first run beats not printing at all, and the
+        // state is saved afresh as the files are read
+        StateFile::load(&path).unwrap_or_else(|e| {
+            eprintln!("tail: {}; starting without saved state", e);
+            StateFile::empty(&path)
+        })
+    });
+    let end_marker = if matches.opt_present("end-marker") {
+        Some(matches.opt_str("end-marker").unwrap_or(String::from(DEFAULT_END_MARKER)))
+    } else {
+        None
+    };
+    let quiet_initial = follow_opt && matches.opt_present("quiet-initial");
+    let skip_binary = !matches.opt_present("binary") && dump_options.delimiter != b'\0'
+        && dump_options.decoding.is_none() && std::io::stdout().is_terminal();
+    let reverse_files = matches.opt_present("reverse-files");
+    let mut file_names: Vec<(String, DumpMode)> = config.files.iter()
+        .map(|file| (file.path.clone(), dump_mode_for(&file.options)))
+        .collect();
+    let no_options = config::FileOptions::default();
+    // --glob with --retry: the patterns to keep matching new files against,
+    // and every file they've matched
+    let mut globs: Vec<Glob> = Vec::new();
+    let mut glob_matched = HashSet::new();
+    let mut report = ErrorReport::default();
+    // Like GNU tail, a file that couldn't be read makes the exit status 1,
+    // once everything else has been printed
+    let mut failed = false;
+    for name in &matches.free {
+        if !(matches.opt_present("glob") && glob::has_wildcards(name)) {
+            file_names.push((name.clone(), dump_mode_for(&no_options)));
+            continue;
+        }
+        let glob = match Glob::new(name) {
+            Ok(glob) => glob,
+            Err(e) => {
+                eprintln!("tail: {}", e);
+                std::process::exit(1);
+            },
+        };
+        let matched = glob.expand().unwrap_or_default();
+        if matched.is_empty() && !(retry && follow_opt) {
+            eprintln!("tail: cannot open '{}' for reading: no files match", name);
+            report.record(name, Problem::Inaccessible, Some(String::from("no files match")));
+            failed = true;
+        } else if matched.is_empty() {
+            eprintln!("tail: no files match '{}' yet; waiting for them", name);
+        }
+        if retry && follow_opt {
+            glob_matched.extend(matched.iter().cloned());
+            globs.push(glob);
+        }
+        file_names.extend(matched.into_iter().map(|path| (path, dump_mode_for(&no_options))));
+    }
+    if file_names.is_empty() && matches.free.is_empty() {
+        file_names.push((String::from(STDIN_NAME), dump_mode_for(&no_options)));
+    }
+    // -v wins over -q. A pattern that new files can match may stand for
+    // several files even if it matches one now.
+    let several = file_names.len() > 1 || !globs.is_empty();
+    let print_headers = (matches.opt_present("v") || (several && !matches.opt_present("q"))) && !json_output;
+    let mut headers = Headers::new(print_headers, matches.opt_present("header-once"));
+    if reverse_files {
+        file_names.reverse();
+    }
 
-    let mut watcher = Inotify::init().expect("Inotify failed to initialize");
+    // Renames and deletions are when a name may have moved on to another file
+    let mut watcher = watch::new_watcher(follow_mode == FollowMode::Name, sleep_interval, matches.opt_present("poll"));
     let mut files = HashMap::new();
-    for file_name in file_names {
-        let mut wd = watcher.add_watch(Path::new(&file_name), WatchMask::MODIFY)
-            .unwrap_or_else(|_| panic!("Failed to attach watcher to file: {}", &file_name));
-        let mut fd = File::open(&file_name)
-            .unwrap_or_else(|_| panic!("Failed to open file handle for: {}", &file_name));
-        let mut sf = StatefulFile::new(fd, file_name);
-        initial_print(&mut sf, &num_of_lines);
-        sf.update_cursor();
-        files.insert(wd, sf);
+    let mut gzip_files = HashSet::new();
+    // --retry: the files that couldn't be opened yet
+    let mut pending: Vec<String> = Vec::new();
+    // --line-numbers: where each file's numbering is up to
+    let mut line_numbers = HashMap::new();
+    // What to print again on SIGUSR2, for the files given a mode of their own
+    let mut dump_modes = HashMap::new();
+    // With --follow, the named pipes, read as data comes in
+    let mut fifos = Vec::new();
+    for glob in &globs {
+        // Without the watch, new files are still found every --sleep-interval
+        if let Err(e) = watcher.watch_dir(glob.dir()) {
+            eprintln!("tail: cannot watch '{}' for new files: {}", glob.dir().display(), io_error_message(&e));
+        }
+    }
+    let mut prefetched = if threads > 1 && !quiet_initial {
+        Some(prefetch(&file_names, threads, dump_options))
+    } else {
+        None
+    };
+    for (index, (file_name, dump_mode)) in file_names.into_iter().enumerate() {
+        // A pipe can't be watched or seeked, so it's read to the end up front.
+        // Standard input redirected from a file is followed like any other.
+        let path = if file_name == STDIN_NAME { Path::new(STDIN_PATH) } else { Path::new(&file_name) };
+        if file_name == STDIN_NAME && !std::fs::metadata(path).is_ok_and(|m| m.is_file()) {
+            let printed = print_initial_header(&mut headers, STDIN_NAME)
+                .and_then(|_| print_stdin(&dump_mode, dump_options, line_filters.get(STDIN_NAME)));
+            if let Err(e) = printed {
+                eprintln!("tail: {}: {}", STDIN_NAME, e);
+                report.record(STDIN_NAME, Problem::Error, Some(e));
+                failed = true;
+                continue;
+            }
+            print_end_marker(&end_marker, STDIN_NAME, dump_options.delimiter, &mut failed);
+            continue;
+        }
+        if follow_opt && std::fs::metadata(path).is_ok_and(|m| is_fifo(&m)) {
+            let opened = Fifo::open(&file_name).map_err(|e| io_error_message(&e)).and_then(|fifo| {
+                if !quiet_initial {
+                    print_initial_header(&mut headers, &file_name)?;
+                }
+                Ok(fifo)
+            });
+            match opened {
+                Ok(fifo) => fifos.push(fifo),
+                Err(e) => {
+                    eprintln!("tail: {}: {}; skipping", &file_name, e);
+                    report.record(&file_name, Problem::Error, Some(e));
+                    failed = true;
+                },
+            }
+            continue;
+        }
+        let opened = watcher.watch(path)
+            .and_then(|wd| File::open(path).map(|fd| (wd, fd)));
+        let (wd, fd) = match opened {
+            Ok(opened) => opened,
+            // A deliberately looping symlink shouldn't take down the other files
+            Err(ref e) if is_symlink_loop(e) => {
+                eprintln!("tail: {}: {}; skipping", &file_name, io_error_message(e));
+                report.record(&file_name, Problem::Error, Some(io_error_message(e)));
+                failed = true;
+                continue;
+            },
+            Err(ref e) if retry && follow_opt => {
+                eprintln!("tail: cannot open '{}' for reading: {}", &file_name, io_error_message(e));
+                // Only a failure if it still hasn't been opened at the end
+                report.record(&file_name, Problem::Inaccessible, Some(io_error_message(e)));
+                pending.push(file_name);
+                continue;
+            },
+            // The other files are still printed, and followed
+            Err(e) => {
+                eprintln!("tail: cannot open '{}' for reading: {}", &file_name, io_error_message(&e));
+                report.record(&file_name, Problem::Inaccessible, Some(io_error_message(&e)));
+                failed = true;
+                continue;
+            },
+        };
+        let filter = line_filters.get(&file_name);
+        let mut sf = match StatefulFile::new(fd, file_name.clone()) {
+            Ok(sf) => sf,
+            Err(e) => {
+                eprintln!("tail: {}: {}; skipping", &file_name, io_error_message(&e));
+                report.record(&file_name, Problem::Error, Some(io_error_message(&e)));
+                failed = true;
+                watcher.unwatch(wd);
+                continue;
+            },
+        };
+        let binary = if skip_binary && sf.old_metadata.is_file() { is_binary_file(&mut sf) } else { Ok(false) };
+        let header = binary.map_err(|e| io_error_message(&e)).and_then(|binary| {
+            if !binary && !quiet_initial {
+                print_initial_header(&mut headers, sf.file_name())?;
+            }
+            Ok(binary)
+        });
+        let binary = match header {
+            Ok(binary) => binary,
+            Err(e) => {
+                eprintln!("tail: {}: {}; skipping", sf.file_name(), e);
+                report.record(sf.file_name(), Problem::Error, Some(e));
+                failed = true;
+                if !files.contains_key(&wd) {
+                    watcher.unwatch(wd);
+                }
+                continue;
+            },
+        };
+        if binary {
+            eprintln!("tail: {}: binary file, skipping (use --binary to override)", sf.file_name());
+            report.record(sf.file_name(), Problem::Inaccessible, Some(String::from("binary file")));
+            if !files.contains_key(&wd) {
+                watcher.unwatch(wd);
+            }
+            continue;
+        }
+        if !sf.old_metadata.is_file() {
+            // Pipes and devices can't be seeked, or followed by watching their
+            // size, so just pass through whatever they produce
+            if follow_opt {
+                eprintln!("tail: {}: not a regular file; streaming it instead of following", sf.file_name());
+            }
+            watcher.unwatch(wd);
+            report.record(sf.file_name(), Problem::Inaccessible, Some(String::from("not a regular file")));
+            let mut writer = output_writer(sf.file_name(), filter, dump_options);
+            if let Err(e) = stream_to_eof(&mut sf, &mut writer).and_then(|_| finish_output(&mut writer)) {
+                eprintln!("tail: {}: {}", sf.file_name(), io_error_message(&e));
+                report.record(sf.file_name(), Problem::Error, Some(io_error_message(&e)));
+                failed = true;
+            }
+            if dump_options.checksum {
+                print_checksum(sf.file_name(), output_checksum(&writer));
+            }
+            print_end_marker(&end_marker, sf.file_name(), dump_options.delimiter, &mut failed);
+            continue;
+        }
+        let printed = is_gzip_file(&mut sf).map_err(|e| io_error_message(&e)).and_then(|gzip| {
+            match state.as_ref().and_then(|state| state.resume_offset(sf.file_name(), &sf.old_metadata)) {
+                Some(offset) => print_resumed(&mut sf, offset, gzip, dump_options, filter).map_err(|e| io_error_message(&e))?,
+                None if quiet_initial => skip_to_end(&mut sf, gzip)?,
+                None => {
+                    let prepared = prefetched.as_mut().and_then(|pool| pool.take(index)).and_then(|prepared| prepared);
+                    initial_print(&mut sf, &dump_mode, dump_options, filter, prepared)?
+                },
+            }
+            Ok(gzip)
+        });
+        let gzip = match printed {
+            Ok(gzip) => gzip,
+            Err(e) => {
+                eprintln!("tail: {}: {}; skipping", sf.file_name(), e);
+                if !files.contains_key(&wd) {
+                    watcher.unwatch(wd);
+                }
+                report.record(sf.file_name(), Problem::Error, Some(e));
+                failed = true;
+                continue;
+            },
+        };
+        if gzip {
+            gzip_files.insert(sf.file_name().to_string());
+        }
+        if follow_opt && dump_options.line_numbers {
+            let numbers = sf.fd.stream_position()
+                .and_then(|end| line_numbers_at(&mut sf, end, gzip, dump_options.delimiter));
+            match numbers {
+                Ok(numbers) => {
+                    line_numbers.insert(sf.file_name().to_string(), numbers);
+                },
+                Err(e) => {
+                    eprintln!("tail: {}: {}; skipping", sf.file_name(), io_error_message(&e));
+                    report.record(sf.file_name(), Problem::Error, Some(io_error_message(&e)));
+                    failed = true;
+                    if !files.contains_key(&wd) {
+                        watcher.unwatch(wd);
+                    }
+                    continue;
+                },
+            }
+        }
+        if !follow_opt {
+            print_end_marker(&end_marker, sf.file_name(), dump_options.delimiter, &mut failed);
+        }
+        if let Err(e) = sf.update_cursor() {
+            eprintln!("tail: {}: {}; skipping", sf.file_name(), io_error_message(&e));
+            if !files.contains_key(&wd) {
+                watcher.unwatch(wd);
+            }
+            report.record(sf.file_name(), Problem::Error, Some(io_error_message(&e)));
+            failed = true;
+            continue;
+        }
+        dump_modes.insert(sf.file_name().to_string(), dump_mode);
+        // The same file given twice, or through a link, has the same watch.
+        // Its initial output is printed for each name, as GNU tail does, but
+        // it's followed once, under the first name.
+        files.entry(wd).or_insert(sf);
+    }
+
+    // --exit-on-idle: whether following stopped because nothing grew
+    let mut idled_out = false;
+    if follow_opt && (!files.is_empty() || !pending.is_empty() || !globs.is_empty() || !fifos.is_empty()) {
+        signals::install_status_handler();
+        signals::install_reprint_handler();
+        signals::install_stop_handler();
+        let default_dump_mode = dump_mode_for(&no_options);
+        // Reprinting doesn't make the output any different from what was
+        // printed at the start
+        let reprint_options = DumpOptions { checksum: false, ..dump_options };
+        let mut follower = Follower {
+            warn_size,
+            max_unchanged_stats,
+            unchanged_stats: HashMap::new(),
+            router,
+            line_filters,
+            lines_emitted: 0,
+            decoders: HashMap::new(),
+            global_number: matches.opt_present("global-number"),
+            json_pending: if matches.opt_present("json-complete") { Some(HashMap::new()) } else { None },
+            headers,
+            gzip_files,
+            write_error: None,
+            write_failed: false,
+            delimiter: dump_options.delimiter,
+            writer: BufWriter::new(Box::new(Stdout::new())),
+            line_numbers: if dump_options.line_numbers { Some(line_numbers) } else { None },
+            timestamps: matches.opt_present("timestamps"),
+            json_output,
+            output_delimiter: output_delimiter.clone().map(String::into_bytes),
+            delimiter_held: false,
+        };
+        let clock = SystemClock;
+        let mut follow_loop = FollowLoop {
+            watcher: &mut *watcher,
+            timers: Timers::new(&clock, timeout, exit_on_idle, sleep_interval),
+            follow_mode,
+            sleep_interval,
+            files: &mut files,
+            fifos: &mut fifos,
+            pending: &mut pending,
+            globs: &globs,
+            glob_matched: &mut glob_matched,
+            pid_watch: pid_watch.as_mut(),
+            report: &mut report,
+            state: &mut state,
+            dump_modes: &dump_modes,
+            default_dump_mode: &default_dump_mode,
+            reprint_options,
+            inaccessible: HashSet::new(),
+        };
+        idled_out = follow_loop.run(&mut follower, &mut failed);
+        follow_loop.finish(&mut follower, &mut failed);
+
+        let mut followed: Vec<&StatefulFile> = files.values().collect();
+        followed.sort_by(|a, b| a.file_name().cmp(b.file_name()));
+        for sf in followed {
+            print_end_marker(&end_marker, sf.file_name(), dump_options.delimiter, &mut failed);
+        }
+        for fifo in &fifos {
+            print_end_marker(&end_marker, &fifo.name, dump_options.delimiter, &mut failed);
+        }
+    }
+    if !pending.is_empty() {
+        failed = true;
+    }
+    save_state(&mut state, &files);
+
+    match error_report_path {
+        Some(Some(path)) => {
+            File::create(&path).and_then(|mut fd| report.write_to(&mut fd))
+                .unwrap_or_else(|e| eprintln!("tail: cannot write error report to '{}': {}", path, io_error_message(&e)));
+        },
+        Some(None) if report.write_to(&mut std::io::stderr()).is_err() => failed = true,
+        Some(None) | None => {},
     }
+    if idled_out {
+        std::process::exit(124);
+    }
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+const DEFAULT_END_MARKER: &str = "#eof file={file}";
+
+/// The file name that means standard input, as in GNU tail
+const STDIN_NAME: &str = "-";
+/// Where standard input can be opened by name, to watch and follow it
+const STDIN_PATH: &str = "/dev/stdin";
+
+/// How much of a file `is_binary_file` looks at
+const BINARY_SNIFF_LEN: usize = 4096;
+
+/// `--sleep-interval`: how often `--pid` checks whether the processes are
+/// still running, `--retry` tries the files that couldn't be opened again and
+/// `--follow=name` checks whether a name has moved on to another file when no
+/// events have come in to prompt it
+const DEFAULT_SLEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// `--max-unchanged-stats`, as in GNU tail
+const DEFAULT_MAX_UNCHANGED_STATS: u64 = 5000;
 
-    if follow_opt {
-        let mut buffer = [0u8; 4096];
+/// Everything following works on besides the `Follower`: what's followed,
+/// what it's waited on with, and what decides when to stop
+struct FollowLoop<'a> {
+    watcher: &'a mut dyn FileWatcher,
+    timers: Timers<'a>,
+    follow_mode: FollowMode,
+    sleep_interval: Duration,
+    files: &'a mut HashMap<WatchId, StatefulFile>,
+    fifos: &'a mut Vec<Fifo>,
+    /// `--retry`: the files that can't be opened yet
+    pending: &'a mut Vec<String>,
+    globs: &'a [Glob],
+    glob_matched: &'a mut HashSet<String>,
+    pid_watch: Option<&'a mut PidWatch>,
+    report: &'a mut ErrorReport,
+    state: &'a mut Option<StateFile>,
+    /// For SIGUSR2, what was printed from each file at the start
+    dump_modes: &'a HashMap<String, DumpMode>,
+    default_dump_mode: &'a DumpMode,
+    reprint_options: DumpOptions<'a>,
+    /// With --follow=name, the files whose name currently leads nowhere
+    inaccessible: HashSet<String>,
+}
+
+impl<'a> FollowLoop<'a> {
+    /// Follows until a signal, `--pid`, `--timeout` or `--exit-on-idle` stops
+    /// it, or the output or the watcher fails, which sets `failed`. Returns
+    /// whether it was `--exit-on-idle`.
+    fn run(&mut self, follower: &mut Follower, failed: &mut bool) -> bool {
         loop {
-            let events = watcher.read_events_blocking(&mut buffer)
-                .expect("Failed to read inotify events");
+            signals::drain_wakeups();
+            if signals::stop_requested() || follower.write_failed(failed) {
+                return false;
+            }
+            let mut fifo_grew = false;
+            for fifo in self.fifos.iter_mut() {
+                fifo_grew |= follower.read_fifo(fifo);
+            }
+            if fifo_grew {
+                self.timers.grew();
+            }
+            if signals::take_status_request() {
+                follower.flush();
+                follower.print_status(self.files);
+            }
+            if signals::take_reprint_request() {
+                follower.reprint(self.files, self.dump_modes, self.default_dump_mode, self.reprint_options);
+            }
+            if self.timers.take_pid_check() && self.pid_watch.as_mut().is_some_and(|pid_watch| pid_watch.should_exit()) {
+                // Pick up whatever was written just before the writers exited
+                for sf in self.files.values_mut() {
+                    follower.follow(sf);
+                }
+                return false;
+            }
+            // Wait for whichever of the idle and overall deadlines comes first,
+            // waking up every --sleep-interval for anything that has to be
+            // checked rather than waited on
+            let until_exit = self.timers.until_exit();
+            if until_exit == Some(Duration::from_secs(0)) {
+                return self.timers.idled_out();
+            }
+            // Processes with a pidfd wake the wait when they exit, like signals
+            let polling_pids = self.pid_watch.as_ref().is_some_and(|pid_watch| pid_watch.needs_polling());
+            let checking = polling_pids || self.follow_mode == FollowMode::Name || !self.pending.is_empty()
+                || !self.globs.is_empty();
+            let wait = until_exit.into_iter()
+                .chain(Some(self.timers.until_pid_check()).filter(|_| polling_pids))
+                .chain(Some(self.sleep_interval).filter(|_| checking))
+                .min();
+            let wake_fds: Vec<RawFd> = signals::wakeup_fd().into_iter()
+                .chain(self.pid_watch.iter().flat_map(|pid_watch| pid_watch.wake_fds()))
+                .chain(self.fifos.iter().map(Fifo::wake_fd))
+                .collect();
+            // Lines are buffered while a batch is handled, and whatever this
+            // round printed goes out before waiting for the next batch
+            follower.flush();
+            match self.watcher.wait(wait, &wake_fds, self.timers.clock()) {
+                Ok(WaitResult::Ready) => {},
+                Ok(WaitResult::TimedOut) => {
+                    queue_new_matches(self.globs, self.glob_matched, self.pending);
+                    self.check_names(follower);
+                    continue;
+                },
+                Ok(WaitResult::Interrupted) => {
+                    self.timers.check_pids_now();
+                    continue;
+                },
+                Err(e) => {
+                    eprintln!("tail: cannot wait for the files to change: {}", io_error_message(&e));
+                    *failed = true;
+                    return false;
+                },
+            }
+            let events = match self.watcher.next_events() {
+                Ok(events) => events,
+                Err(e) => {
+                    eprintln!("tail: cannot read file change events: {}", io_error_message(&e));
+                    *failed = true;
+                    return false;
+                },
+            };
+            if events.iter().any(|event| event.kind == EventKind::Created) {
+                queue_new_matches(self.globs, self.glob_matched, self.pending);
+            }
 
             for event in events {
-                if event.mask.contains(EventMask::MODIFY) {
-                    let sf = files.get_mut(&event.wd).unwrap();
-                    follow(sf);
+                if event.kind == EventKind::Modified {
+                    // Events can still arrive for a file that was just replaced
+                    let sf = match self.files.get_mut(&event.id) {
+                        Some(sf) => sf,
+                        None => continue,
+                    };
+                    match follower.drain(sf) {
+                        ModificationType::NoChange => {},
+                        ModificationType::Removed => {
+                            self.report.record(sf.file_name(), Problem::Truncated, None);
+                            self.timers.grew();
+                        },
+                        ModificationType::Added => self.timers.grew(),
+                    }
                 }
             }
+            self.check_names(follower);
+            // The saved offsets don't get ahead of what's been written out
+            follower.flush();
+            save_state(self.state, self.files);
+        }
+    }
+
+    /// Starts following the pending files that have appeared, and with
+    /// `--follow=name`, the files that have taken over a followed name
+    fn check_names(&mut self, follower: &mut Follower) {
+        open_pending(self.watcher, self.pending, self.files, follower);
+        if self.follow_mode == FollowMode::Name {
+            follow_renamed(self.watcher, self.files, follower, &mut self.inaccessible, self.report);
+        }
+    }
+
+    /// Prints the rest of each line that was being written, which isn't
+    /// coming now, reporting a failed write to the output
+    fn finish(&mut self, follower: &mut Follower, failed: &mut bool) {
+        let mut followed: Vec<&mut StatefulFile> = self.files.values_mut().collect();
+        followed.sort_by(|a, b| a.file_name().cmp(b.file_name()));
+        for sf in followed {
+            follower.print_partial(sf);
+        }
+        for fifo in self.fifos.iter_mut() {
+            let partial = std::mem::take(&mut fifo.partial);
+            follower.print_unfinished(&fifo.name, partial);
+        }
+        follower.end_joined_line();
+        follower.flush();
+        follower.write_failed(failed);
+    }
+}
+
+/// For `--retry`: starts following each pending file that can now be opened,
+/// printing it from the start, as it's all new
+fn open_pending(watcher: &mut dyn FileWatcher, pending: &mut Vec<String>,
+                files: &mut HashMap<WatchId, StatefulFile>, follower: &mut Follower) {
+    pending.retain(|file_name| {
+        let opened = watcher.watch(Path::new(file_name))
+            .and_then(|wd| File::open(file_name).map(|fd| (wd, fd)));
+        let (wd, mut sf) = match opened.and_then(|(wd, fd)| StatefulFile::new(fd, file_name.clone()).map(|sf| (wd, sf))) {
+            Ok(opened) => opened,
+            Err(_) => return true,
+        };
+        follower.flush();
+        eprintln!("tail: '{}' has appeared;  following new file", file_name);
+        if files.contains_key(&wd) {
+            // Another name for a file that's already followed
+            return false;
+        }
+        follower.drain(&mut sf);
+        files.insert(wd, sf);
+        false
+    });
+}
+
+/// `--glob` with `--retry`: adds the files that have come to match a pattern
+/// to `pending`, for `open_pending` to follow from their start
+fn queue_new_matches(globs: &[Glob], matched: &mut HashSet<String>, pending: &mut Vec<String>) {
+    for glob in globs {
+        for path in glob.expand().unwrap_or_default() {
+            if matched.insert(path.clone()) {
+                pending.push(path);
+            }
         }
     }
 }
 
-fn follow(sf: &mut StatefulFile) {
-    match sf.modification_type() {
-        ModificationType::Added => {}
-        ModificationType::Removed => {
-            sf.reset_cursor();
+/// `--follow`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FollowMode {
+    /// Keep reading the file that was opened, wherever it's moved to
+    Descriptor,
+    /// Keep reading whichever file has the name, reopening it when a file is
+    /// renamed over it or it's deleted and created again
+    Name,
+}
+
+impl FollowMode {
+    fn from_name(name: &str) -> Option<FollowMode> {
+        match name {
+            "descriptor" => Some(FollowMode::Descriptor),
+            "name" => Some(FollowMode::Name),
+            _ => None,
         }
-        ModificationType::NoChange => {}
     }
-    sf.update_metadata();
-    sf.seek_to_cursor();
-    print_from_cursor(sf);
-    sf.update_cursor();
 }
 
-fn initial_print(sf: &mut StatefulFile, num_lines_str: &String) {
-    let mut writer = BufWriter::new(std::io::stdout());
-    if num_lines_str.starts_with("+") {
-        let line_iter = (&mut sf.fd).lines().map(|l| l.unwrap())
-            .skip(num_lines_str.chars().skip(1).collect::<String>().parse::<usize>()
-                .unwrap_or_else(|_| panic!("Incorrect number of lines given: {}", &num_lines_str)));
-        for line in line_iter {
-            writer.write(line.as_bytes()).unwrap();
-            writer.write(b"\n").unwrap();
+/// For `--follow=name`: switches each file whose name now refers to another
+/// file over to that file. What was appended to the old file before it was
+/// replaced is printed first, and then the new file from its start, as GNU
+/// tail does. A name that leads nowhere is reported once and checked again
+/// next time, in case the file is being rotated.
+fn follow_renamed(watcher: &mut dyn FileWatcher, files: &mut HashMap<WatchId, StatefulFile>,
+                  follower: &mut Follower, inaccessible: &mut HashSet<String>, report: &mut ErrorReport) {
+    let replaced: Vec<WatchId> = files.iter()
+        .filter(|&(_, sf)| sf.file_name() != STDIN_NAME)
+        .filter(|&(_, sf)| match std::fs::metadata(sf.file_name()) {
+            Ok(_) => sf.was_rotated() || (follower.unchanged_for_too_long(sf) && is_replaced(sf)),
+            Err(e) => {
+                if inaccessible.insert(sf.file_name().to_string()) {
+                    follower.flush();
+                    eprintln!("tail: '{}' has become inaccessible: {}", sf.file_name(), io_error_message(&e));
+                    report.record(sf.file_name(), Problem::Inaccessible, Some(io_error_message(&e)));
+                }
+                false
+            },
+        })
+        .map(|(wd, _)| *wd)
+        .collect();
+    for wd in replaced {
+        let mut sf = files.remove(&wd).unwrap();
+        follower.drain(&mut sf);
+        follower.print_partial(&mut sf);
+        let new_wd = match watcher.watch(Path::new(sf.file_name())).and_then(|new_wd| {
+            sf.reopen().map(|_| new_wd)
+        }) {
+            Ok(new_wd) => new_wd,
+            Err(e) => {
+                // Gone again already; keep the old file until the next check
+                follower.flush();
+                eprintln!("tail: {}: {}", sf.file_name(), io_error_message(&e));
+                files.insert(wd, sf);
+                continue;
+            },
+        };
+        if new_wd != wd {
+            watcher.unwatch(wd);
         }
-        writer.flush().unwrap();
-        return;
+        if let Some(ref mut json_pending) = follower.json_pending {
+            json_pending.remove(sf.file_name());
+        }
+        if let Some(ref mut line_numbers) = follower.line_numbers {
+            line_numbers.remove(sf.file_name());
+        }
+        follower.flush();
+        if inaccessible.remove(sf.file_name()) {
+            eprintln!("tail: '{}' has appeared;  following new file", sf.file_name());
+        } else {
+            eprintln!("tail: '{}' has been replaced;  following new file", sf.file_name());
+        }
+        follower.drain(&mut sf);
+        files.insert(new_wd, sf);
+    }
+    // A name that comes back to the same file was only moved away for a while
+    for sf in files.values() {
+        if inaccessible.contains(sf.file_name()) && std::fs::metadata(sf.file_name()).is_ok() {
+            inaccessible.remove(sf.file_name());
+        }
+    }
+}
+
+/// Whether opening `sf`'s name gets another file than the one being read.
+/// Unlike `was_rotated`, this also sees a new file that was given the inode
+/// of the old one after it was deleted.
+#[cfg(unix)]
+fn is_replaced(sf: &StatefulFile) -> bool {
+    let named = File::open(sf.file_name()).and_then(|fd| fd.metadata());
+    match (named, sf.fd.get_ref().metadata()) {
+        (Ok(named), Ok(current)) => {
+            named.ino() != current.ino() || named.dev() != current.dev() || current.nlink() == 0
+        },
+        _ => false,
+    }
+}
+
+/// Without inodes, there's nothing to go on but what `was_rotated` checks
+#[cfg(not(unix))]
+fn is_replaced(sf: &StatefulFile) -> bool {
+    sf.was_rotated()
+}
+
+/// Named pipes are only followed on Unix; elsewhere they're read like other
+/// files that aren't regular
+#[cfg(unix)]
+fn is_fifo(metadata: &Metadata) -> bool {
+    metadata.file_type().is_fifo()
+}
+
+#[cfg(not(unix))]
+fn is_fifo(_metadata: &Metadata) -> bool {
+    false
+}
+
+/// A named pipe being followed. Writers come and go, and its data is printed
+/// as it arrives, as there's no end to look back from.
+struct Fifo {
+    name: String,
+    fd: File,
+    /// The start of a line whose end hasn't been written yet
+    partial: Vec<u8>,
+}
+
+impl Fifo {
+    /// Opens the pipe for writing as well as reading, which keeps it open
+    /// between writers, so reads wait for the next writer instead of finding
+    /// the end of the file over and over. It doesn't wait for a writer to open
+    /// it, either way.
+    fn open(name: &str) -> std::io::Result<Fifo> {
+        let mut options = OpenOptions::new();
+        options.read(true).write(true);
+        #[cfg(unix)]
+        options.custom_flags(libc::O_NONBLOCK);
+        Ok(Fifo { name: name.to_string(), fd: options.open(name)?, partial: Vec::new() })
+    }
+
+    /// Readable once there's more to read, for the follow loop to wait on
+    #[cfg(unix)]
+    fn wake_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+
+    /// `is_fifo` never lets a pipe be followed here
+    #[cfg(not(unix))]
+    fn wake_fd(&self) -> RawFd {
+        unreachable!("named pipes are only followed on Unix")
+    }
+}
+
+#[cfg(unix)]
+fn is_symlink_loop(err: &std::io::Error) -> bool {
+    err.raw_os_error() == Some(libc::ELOOP)
+}
+
+/// Only Unix reports a loop as an error of its own
+#[cfg(not(unix))]
+fn is_symlink_loop(_err: &std::io::Error) -> bool {
+    false
+}
+
+/// The `==> name <==` headers that separate the output of different files
+struct Headers {
+    enabled: bool,
+    /// `--header-once`: never print the same file's header twice
+    once: bool,
+    /// The file the last output came from
+    last_printed: Option<String>,
+    printed: HashSet<String>,
+}
+
+impl Headers {
+    fn new(enabled: bool, once: bool) -> Headers {
+        Headers { enabled, once, last_printed: None, printed: HashSet::new() }
+    }
+
+    /// Whether `print` would print `file_name`'s header
+    fn would_print(&self, file_name: &str) -> bool {
+        self.enabled && self.last_printed.as_ref().is_none_or(|last| last != file_name)
+    }
+
+    /// Prints `file_name`'s header, unless the last output was already from
+    /// that file. Every header after the first is preceded by a blank line.
+    fn print<W: Write>(&mut self, file_name: &str, writer: &mut W) -> io::Result<()> {
+        if self.last_printed.as_ref().is_some_and(|last| last == file_name) {
+            return Ok(());
+        }
+        self.print_new(file_name, writer)
+    }
+
+    /// Prints `file_name`'s header even if the last output was from the same
+    /// file, as when a file is named twice on the command line
+    fn print_new<W: Write>(&mut self, file_name: &str, writer: &mut W) -> io::Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+        let separator = if self.last_printed.is_some() { "\n" } else { "" };
+        self.last_printed = Some(file_name.to_string());
+        if self.once && !self.printed.insert(file_name.to_string()) {
+            return Ok(());
+        }
+        let shown = if file_name == STDIN_NAME { "standard input" } else { file_name };
+        writeln!(writer, "{}==> {} <==", separator, shown)
     }
-    let num_lines = num_lines_str.parse::<usize>()
-        .unwrap_or_else(|_| panic!("Incorrect number of lines given: {}", &num_lines_str));
+}
 
-    let mut reader = BackwardsReader::new(num_lines, &mut sf.fd);
-    reader.read_all(&mut writer);
-    writer.flush().unwrap();
+/// Options and state shared by every followed file
+struct Follower {
+    warn_size: Option<u64>,
+    /// `--max-unchanged-stats`
+    max_unchanged_stats: u64,
+    /// Per file, the size at the last check and how many checks in a row
+    /// have found it that size
+    unchanged_stats: HashMap<String, (u64, u64)>,
+    router: Option<Router>,
+    /// `--filter` and `--prefix`
+    line_filters: LineFilters,
+    /// Lines printed or routed since following started
+    lines_emitted: usize,
+    /// Per file, for `--route` matching. Appended data can end part way
+    /// through a character that the next write completes.
+    decoders: HashMap<String, Utf8Decoder>,
+    /// `--global-number`: prefix lines with `lines_emitted`, which counts
+    /// across every file
+    global_number: bool,
+    /// `--json-complete`: per file, what's been read of a JSON value that
+    /// hasn't been completed yet
+    json_pending: Option<HashMap<String, Vec<u8>>>,
+    /// Carried on from the initial output, so following only repeats a
+    /// header once output switches to another file
+    headers: Headers,
+    /// Files made of gzip members, whose cursor is the end of the last
+    /// complete member
+    gzip_files: HashSet<String>,
+    /// The first write to stdout that failed, until it's reported
+    write_error: Option<io::Error>,
+    /// Set once a write to stdout has failed. Nothing more is printed, and
+    /// the follow loop stops at its next round.
+    write_failed: bool,
+    /// `-z`: what lines end with
+    delimiter: u8,
+    /// Flushed once per batch of events rather than per line. Standard
+    /// output, other than in tests.
+    writer: BufWriter<Box<dyn Write>>,
+    /// `--line-numbers`, per file. Files without an entry start from 1.
+    line_numbers: Option<HashMap<String, LineNumbers>>,
+    /// `--timestamps`
+    timestamps: bool,
+    /// `--output=json`
+    json_output: bool,
+    /// `--output-delimiter`
+    output_delimiter: Option<Vec<u8>>,
+    /// Whether the delimiter ending the last line was held back, to be
+    /// replaced by `output_delimiter` if another line follows
+    delimiter_held: bool,
 }
 
-fn print_from_cursor(sf: &mut StatefulFile) {
-    let mut writer = BufWriter::new(std::io::stdout());
-    for line in sf.fd.by_ref().lines().map(|l| l.unwrap()) {
-        writer.write(line.as_bytes()).unwrap();
-        writer.write(b"\n").unwrap();
+impl Follower {
+    /// Prints the part of a line left at the end of `sf` as it is, for when
+    /// the rest isn't coming, e.g. because `sf` was replaced or tail is
+    /// exiting
+    fn print_partial(&mut self, sf: &mut StatefulFile) {
+        let partial = sf.take_partial();
+        self.print_unfinished(sf.file_name(), partial);
+    }
+
+    /// Prints `partial`, the start of a line of `file_name` that the rest of
+    /// isn't coming for
+    fn print_unfinished(&mut self, file_name: &str, mut partial: Vec<u8>) {
+        if partial.is_empty() || self.write_failed {
+            return;
+        }
+        let kept = self.line_filters.get(file_name).is_none_or(|filter| filter.keeps(&partial));
+        self.number_line(file_name, &mut partial);
+        if !kept {
+            return;
+        }
+        if let Some(stamp) = self.timestamp() {
+            partial.splice(0..0, stamp.into_bytes());
+        }
+        if let Some(filter) = self.line_filters.get(file_name) {
+            partial.splice(0..0, filter.prefix().iter().cloned());
+        }
+        let written = self.print_header(file_name).and_then(|_| self.write_line(file_name, &partial));
+        if let Err(e) = written {
+            self.fail_writing(e);
+        }
+    }
+
+    /// Prints whole lines of whatever has been written to `fifo`, returning
+    /// whether anything had been
+    fn read_fifo(&mut self, fifo: &mut Fifo) -> bool {
+        let mut data = Vec::new();
+        let mut buff = [0u8; 4096];
+        loop {
+            match fifo.fd.read(&mut buff) {
+                Ok(0) => break,
+                Ok(bytes_read) => data.extend_from_slice(&buff[..bytes_read]),
+                Err(ref e) if e.kind() == std::io::ErrorKind::Interrupted => {},
+                Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    self.flush();
+                    eprintln!("tail: {}: {}", fifo.name, io_error_message(&e));
+                    break;
+                },
+            }
+        }
+        if data.is_empty() {
+            return false;
+        }
+        self.print_from(&fifo.name, &mut Cursor::new(data), &mut fifo.partial);
+        true
+    }
+
+    fn print_header(&mut self, file_name: &str) -> io::Result<()> {
+        if self.headers.would_print(file_name) {
+            self.end_joined_line();
+        }
+        self.headers.print(file_name, &mut self.writer)
+    }
+
+    /// Writes `line` as it is, or as a JSON record with `--output=json`
+    fn write_line(&mut self, file_name: &str, line: &[u8]) -> io::Result<()> {
+        if self.json_output {
+            return jsonl::write_record(&mut self.writer, file_name, line, self.delimiter);
+        }
+        let separator = match self.output_delimiter {
+            Some(ref separator) => separator,
+            None => return self.writer.write_all(line),
+        };
+        if self.delimiter_held {
+            self.writer.write_all(separator)?;
+        }
+        self.delimiter_held = line.last() == Some(&self.delimiter);
+        let text = if self.delimiter_held { &line[..line.len() - 1] } else { line };
+        self.writer.write_all(text)
+    }
+
+    /// `--output-delimiter`: writes the delimiter held back from the last
+    /// line, for when output from another file or nothing at all comes next
+    fn end_joined_line(&mut self) {
+        if self.delimiter_held && !self.write_failed {
+            self.delimiter_held = false;
+            if let Err(e) = self.writer.write_all(&[self.delimiter]) {
+                self.fail_writing(e);
+            }
+        }
+    }
+
+    /// `--timestamps`: what goes in front of the lines read now
+    fn timestamp(&self) -> Option<String> {
+        if self.timestamps {
+            Some(format!("{} ", timestamp::iso8601(SystemTime::now())))
+        } else {
+            None
+        }
+    }
+
+    /// `--line-numbers`: puts the line's number in its file in front of it
+    fn number_line(&mut self, file_name: &str, line: &mut Vec<u8>) {
+        let delimiter = self.delimiter;
+        if let Some(ref mut line_numbers) = self.line_numbers {
+            line_numbers.entry(file_name.to_string())
+                .or_insert_with(|| LineNumbers::starting_at(1, delimiter))
+                .number(line);
+        }
+    }
+
+    /// `--max-unchanged-stats`: counts another check of `sf`, returning true
+    /// once its size hasn't changed for that many checks in a row, which
+    /// starts the count again
+    fn unchanged_for_too_long(&mut self, sf: &StatefulFile) -> bool {
+        let len = match sf.fd.get_ref().metadata() {
+            Ok(metadata) => metadata.len(),
+            Err(_) => return false,
+        };
+        let stats = self.unchanged_stats.entry(sf.file_name().to_string()).or_insert((len, 0));
+        if stats.0 != len {
+            *stats = (len, 0);
+            return false;
+        }
+        stats.1 += 1;
+        if stats.1 < self.max_unchanged_stats {
+            return false;
+        }
+        stats.1 = 0;
+        true
+    }
+
+    /// Writes out what's been printed since the last flush. Done before
+    /// anything goes to stderr too, so the two stay in order on a terminal.
+    fn flush(&mut self) {
+        if self.write_failed {
+            return;
+        }
+        if let Err(e) = self.writer.flush() {
+            self.fail_writing(e);
+        }
+    }
+
+    /// Prints whatever changed in `sf` since the last call, returning how its
+    /// size changed
+    fn follow(&mut self, sf: &mut StatefulFile) -> ModificationType {
+        let modification = match sf.modification_type().and_then(|modification| sf.update_metadata().map(|_| modification)) {
+            Ok(modification) => modification,
+            Err(e) => {
+                // Try again on the next event or check, e.g. once a rotation
+                // has finished; the other files carry on meanwhile
+                self.flush();
+                eprintln!("tail: {}: {}", sf.file_name(), io_error_message(&e));
+                return ModificationType::NoChange;
+            },
+        };
+        match modification {
+            ModificationType::Added => {}
+            ModificationType::Removed => {
+                // What's left was printed before, so carry on from the new
+                // end, as GNU tail does
+                self.flush();
+                eprintln!("tail: {}: file truncated", sf.file_name());
+                let len = sf.old_metadata.len();
+                sf.set_cursor(len);
+                sf.take_partial();
+                if let Some(ref mut json_pending) = self.json_pending {
+                    json_pending.remove(sf.file_name());
+                }
+                if let Some(ref mut line_numbers) = self.line_numbers {
+                    let gzip = self.gzip_files.contains(sf.file_name());
+                    match line_numbers_at(sf, len, gzip, self.delimiter) {
+                        Ok(numbers) => {
+                            line_numbers.insert(sf.file_name().to_string(), numbers);
+                        },
+                        Err(e) => eprintln!("tail: {}: {}", sf.file_name(), io_error_message(&e)),
+                    }
+                }
+            }
+            ModificationType::NoChange => {}
+        }
+        if let Some(threshold) = self.warn_size {
+            if sf.check_size_threshold(threshold) {
+                self.flush();
+                eprintln!("tail: {}: file has grown to {} bytes (warn size {})",
+                          sf.file_name(), sf.old_metadata.len(), threshold);
+            }
+        }
+        if let Err(e) = sf.seek_to_cursor() {
+            eprintln!("tail: {}: {}", sf.file_name(), io_error_message(&e));
+            return ModificationType::NoChange;
+        }
+        let file_name = sf.file_name().to_string();
+        if self.gzip_files.contains(&file_name) {
+            self.print_gzip_members(sf);
+        } else {
+            let mut partial = sf.take_partial();
+            self.print_from(&file_name, &mut sf.fd, &mut partial);
+            sf.keep_partial(partial);
+        }
+        if let Err(e) = sf.update_cursor() {
+            eprintln!("tail: {}: {}", sf.file_name(), io_error_message(&e));
+        }
+        modification
+    }
+
+    /// Follows `sf` until it has stopped growing, so that data appended while
+    /// it was being read isn't left waiting for another event, which may not
+    /// come if the events were coalesced
+    fn drain(&mut self, sf: &mut StatefulFile) -> ModificationType {
+        let mut modification = self.follow(sf);
+        loop {
+            let cursor = sf.cursor();
+            let len = sf.fd.get_ref().metadata().map(|metadata| metadata.len()).unwrap_or(cursor);
+            if len <= cursor {
+                return modification;
+            }
+            if let ModificationType::Removed = self.follow(sf) {
+                modification = ModificationType::Removed;
+            }
+            // A gzip member that's still being written isn't read until it's whole
+            if sf.cursor() == cursor {
+                return modification;
+            }
+        }
+    }
+
+    /// Decodes and prints the gzip members appended since the cursor. A member
+    /// that's still being written is left for the next call.
+    fn print_gzip_members(&mut self, sf: &mut StatefulFile) {
+        let decoded = match read_gzip_members(sf) {
+            Ok((decoded, _)) => decoded,
+            Err(e) => {
+                // Skip what's there, in case later members are readable
+                self.flush();
+                eprintln!("tail: {}: {}", sf.file_name(), io_error_message(&e));
+                return;
+            },
+        };
+        let file_name = sf.file_name().to_string();
+        let mut partial = sf.take_partial();
+        self.print_from(&file_name, &mut Cursor::new(decoded), &mut partial);
+        sf.keep_partial(partial);
+    }
+
+    /// Prints the lines in `source`, each after the `partial` line left from
+    /// the last call. What's left at the end with no delimiter yet is put in
+    /// `partial`, so a line written in several goes comes out whole, as is
+    /// what was read before a read error.
+    fn print_from<R: BufRead>(&mut self, file_name: &str, source: &mut R, partial: &mut Vec<u8>) {
+        if self.write_failed {
+            return;
+        }
+        // Everything read in one go arrived at about the same time
+        let stamp = self.timestamp();
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            line.append(partial);
+            if let Err(e) = source.read_until(self.delimiter, &mut line) {
+                partial.append(&mut line);
+                eprintln!("tail: {}: {}", file_name, io_error_message(&e));
+                break;
+            }
+            if line.last() != Some(&self.delimiter) {
+                partial.append(&mut line);
+                break;
+            }
+            if let Some(ref mut json_pending) = self.json_pending {
+                // Only whole values go out; the rest waits for the next read
+                let pending = json_pending.entry(file_name.to_string()).or_default();
+                pending.extend_from_slice(&line);
+                let complete = json::complete_len(pending);
+                if complete == 0 {
+                    continue;
+                }
+                line = pending.drain(..complete).collect();
+            }
+            let text = line.strip_suffix(&[self.delimiter]).unwrap_or(&line);
+            if !self.line_filters.get(file_name).is_none_or(|filter| filter.keeps(text)) {
+                // Still counted, so the lines kept keep their numbers
+                self.number_line(file_name, &mut line);
+                continue;
+            }
+            self.lines_emitted += 1;
+            // A character cut off at the end of a partial line is completed
+            // by the next read
+            let text = match self.router {
+                Some(_) => self.decoders.entry(file_name.to_string()).or_default().decode(&line),
+                None => String::new(),
+            };
+            self.number_line(file_name, &mut line);
+            if self.global_number {
+                let number = format!("{:6}\t", self.lines_emitted);
+                line.splice(0..0, number.into_bytes());
+            }
+            if let Some(ref stamp) = stamp {
+                line.splice(0..0, stamp.bytes());
+            }
+            if let Some(filter) = self.line_filters.get(file_name) {
+                line.splice(0..0, filter.prefix().iter().cloned());
+            }
+            if let Some(ref mut router) = self.router {
+                match router.write_line(&line, &text) {
+                    Ok(true) => continue,
+                    Ok(false) => {},
+                    Err(e) => eprintln!("tail: failed to route line: {}", e),
+                }
+            }
+            let written = self.print_header(file_name).and_then(|_| self.write_line(file_name, &line));
+            if let Err(e) = written {
+                self.fail_writing(e);
+                return;
+            }
+        }
+    }
+
+    fn fail_writing(&mut self, e: io::Error) {
+        self.write_error = Some(e);
+        self.write_failed = true;
+    }
+
+    /// Reports the first failed write to stdout, setting `failed`.
+    /// A reader that went away never gets here, as SIGPIPE ends tail first.
+    /// Returns whether writing has failed.
+    fn write_failed(&mut self, failed: &mut bool) -> bool {
+        if let Some(e) = self.write_error.take() {
+            eprintln!("tail: error writing 'standard output': {}", io_error_message(&e));
+            *failed = true;
+        }
+        self.write_failed
+    }
+
+    /// SIGUSR2: prints the end of each file again as at the start, under a
+    /// header of its own, leaving following to carry on from where it was
+    fn reprint<K>(&mut self, files: &mut HashMap<K, StatefulFile>, dump_modes: &HashMap<String, DumpMode>,
+                  default_mode: &DumpMode, options: DumpOptions) {
+        self.end_joined_line();
+        let mut followed: Vec<&mut StatefulFile> = files.values_mut().collect();
+        followed.sort_by(|a, b| a.file_name().cmp(b.file_name()));
+        for sf in followed {
+            let _ = self.headers.print_new(sf.file_name(), &mut self.writer);
+            self.flush();
+            let cursor = sf.cursor();
+            let dump_mode = dump_modes.get(sf.file_name()).unwrap_or(default_mode);
+            if let Err(e) = initial_print(sf, dump_mode, options, self.line_filters.get(sf.file_name()), None) {
+                eprintln!("tail: {}: {}", sf.file_name(), e);
+            }
+            sf.set_cursor(cursor);
+        }
+    }
+
+    /// Prints e.g. `tail: following 2 files: a.log at byte 120, b.log at byte 0; 7 lines emitted`
+    fn print_status<K>(&self, files: &HashMap<K, StatefulFile>) {
+        let mut files: Vec<&StatefulFile> = files.values().collect();
+        files.sort_by(|a, b| a.file_name().cmp(b.file_name()));
+        let offsets: Vec<String> = files.iter()
+            .map(|sf| format!("{} at byte {}", sf.file_name(), sf.cursor()))
+            .collect();
+        eprintln!("tail: following {} file{}: {}; {} lines emitted", files.len(),
+                  if files.len() == 1 { "" } else { "s" }, offsets.join(", "), self.lines_emitted);
+    }
+}
+
+/// How many lines `-n +NUM` skips before line NUM. `+0` and `+1` both mean
+/// the first line.
+fn lines_to_skip(num_lines: usize) -> usize {
+    num_lines.saturating_sub(1)
+}
+
+/// The last byte written to stdout, so an `--end-marker` can start a line of
+/// its own after output that didn't end with one
+static LAST_OUTPUT_BYTE: AtomicU8 = AtomicU8::new(b'\n');
+
+/// Standard output, noting the last byte that's written to it
+struct Stdout(std::io::Stdout);
+
+impl Stdout {
+    fn new() -> Stdout {
+        Stdout(std::io::stdout())
+    }
+}
+
+impl Write for Stdout {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.0.write(buf)?;
+        if let Some(last) = buf[..written].last() {
+            LAST_OUTPUT_BYTE.store(*last, Ordering::Relaxed);
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// Where a file's output goes on its way to stdout
+type Output = FilteredWriter<JoinWriter<RecordWriter<ChecksumWriter<Stdout>>>>;
+
+/// Filtered by `--filter` and `--prefix`, then joined with
+/// `--output-delimiter`, or as JSON records with `--output=json`, and counted
+/// by `--checksum`
+fn output_writer(file_name: &str, filter: Option<&LineFilter>, options: DumpOptions) -> Output {
+    let file = if options.json_output { Some(file_name) } else { None };
+    let records = RecordWriter::new(ChecksumWriter::new(Stdout::new()), file, options.delimiter);
+    // Without a separator the delimiter stands in for itself
+    let delimiter = [options.delimiter];
+    let joined = JoinWriter::new(records, options.delimiter, options.output_delimiter.unwrap_or(&delimiter));
+    FilteredWriter::new(joined, filter, options.delimiter, options.line_numbers)
+}
+
+/// Writes what `writer` held back for the end of the output, and flushes it
+/// so a failure to write that shows up here
+fn finish_output(writer: &mut Output) -> std::io::Result<()> {
+    writer.finish()?;
+    writer.get_mut().finish()?;
+    writer.get_mut().get_mut().finish()?;
+    writer.flush()
+}
+
+fn output_checksum(writer: &Output) -> &ChecksumWriter<Stdout> {
+    writer.get_ref().get_ref().get_ref()
+}
+
+/// Prints the start of `sf`'s output, from what `prepared` read of it if
+/// that's still the file `sf` is and it hasn't been truncated since. A
+/// failure to read it, such as bad gzip data, or to write it out, e.g. to a
+/// full disk, is returned rather than panicked on.
+fn initial_print(sf: &mut StatefulFile, dump_mode: &DumpMode, options: DumpOptions,
+                 filter: Option<&LineFilter>, prepared: Option<Prepared>) -> Result<(), String> {
+    let mut writer = BufWriter::new(output_writer(sf.file_name(), filter, options));
+    match prepared.filter(|prepared| prepared.inode == sf.inode() && prepared.end <= sf.old_metadata.len()) {
+        Some(prepared) => {
+            writer.write_all(&prepared.output).map_err(|e| io_error_message(&e))?;
+            sf.fd.seek(SeekFrom::Start(prepared.end)).map_err(|e| io_error_message(&e))?;
+        },
+        None => dump(sf, dump_mode, options, &mut writer)?,
+    }
+    writer.flush().map_err(|e| io_error_message(&e))?;
+    finish_output(writer.get_mut()).map_err(|e| io_error_message(&e))?;
+    if options.checksum {
+        print_checksum(sf.file_name(), output_checksum(writer.get_ref()));
+    }
+    Ok(())
+}
+
+/// `--threads`: a file's initial output, read ahead of it being printed
+struct Prepared {
+    output: Vec<u8>,
+    /// The file that was read, in case its name has moved on since
+    inode: u64,
+    /// Where following carries on from
+    end: u64,
+}
+
+/// `--threads`: starts reading the initial output of the files on `threads`
+/// threads, for `initial_print` to take in turn
+fn prefetch(file_names: &[(String, DumpMode)], threads: usize, options: DumpOptions) -> OrderedPool<Option<Prepared>> {
+    let options = options.without_output_delimiter();
+    OrderedPool::new(file_names.to_vec(), threads, move |(file_name, dump_mode): (String, DumpMode)| {
+        prepare(&file_name, &dump_mode, options)
+    })
+}
+
+/// Reads `file_name`'s initial output, if it's a regular file. Anything
+/// that goes wrong is left for `initial_print` to run into and report.
+fn prepare(file_name: &str, dump_mode: &DumpMode, options: DumpOptions) -> Option<Prepared> {
+    if file_name == STDIN_NAME {
+        return None;
+    }
+    let mut sf = StatefulFile::new(File::open(file_name).ok()?, file_name.to_string()).ok()?;
+    if !sf.old_metadata.is_file() {
+        return None;
+    }
+    let mut writer = BufWriter::new(Vec::new());
+    dump(&mut sf, dump_mode, options, &mut writer).ok()?;
+    let end = sf.fd.stream_position().ok()?;
+    Some(Prepared { output: writer.into_inner().ok()?, inode: sf.inode(), end })
+}
+
+/// Headers go straight to stdout, so they're not part of `--checksum`
+fn print_initial_header(headers: &mut Headers, file_name: &str) -> Result<(), String> {
+    let mut stdout = Stdout::new();
+    headers.print_new(file_name, &mut stdout)
+        .and_then(|_| stdout.flush())
+        .map_err(|e| io_error_message(&e))
+}
+
+fn print_checksum<W: Write>(file_name: &str, writer: &ChecksumWriter<W>) {
+    eprintln!("tail: {}: checksum {}", file_name, writer.digest());
+}
+
+/// Prints what `dump_mode` selects from standard input when it's a pipe or a
+/// terminal, which can't be seeked. The last lines are kept in a
+/// `RingBuffer` as the input goes past. Every other mode reads the input into
+/// memory in full.
+fn print_stdin(dump_mode: &DumpMode, options: DumpOptions, filter: Option<&LineFilter>) -> Result<(), String> {
+    let stdin = std::io::stdin();
+    let mut input = stdin.lock();
+    let mut writer = BufWriter::new(output_writer(STDIN_NAME, filter, options));
+    match *dump_mode {
+        DumpMode::Lines(ref num_lines_str) if options.decoding.is_none() && !parse_num_lines(num_lines_str).0 => {
+            let (_, num_lines) = parse_num_lines(num_lines_str);
+            let mut last_lines = RingBuffer::new(num_lines);
+            let mut total = 0;
+            loop {
+                let mut line = Vec::new();
+                match input.read_until(options.delimiter, &mut line) {
+                    Ok(0) => break,
+                    Ok(_) => {
+                        last_lines.push(line);
+                        total += 1;
+                    },
+                    Err(ref e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(io_error_message(&e)),
+                }
+            }
+            let first_number = total - last_lines.len() + 1;
+            let mut numbered;
+            let out: &mut dyn Write = if options.line_numbers {
+                numbered = NumberedWriter::new(&mut writer, LineNumbers::starting_at(first_number, options.delimiter));
+                &mut numbered
+            } else {
+                &mut writer
+            };
+            let mut first = true;
+            for line in last_lines.drain() {
+                let skip = if first && options.strip_bom && line.starts_with(encoding::UTF8_BOM) {
+                    encoding::UTF8_BOM.len()
+                } else {
+                    0
+                };
+                first = false;
+                out.write_all(&line[skip..]).map_err(|e| io_error_message(&e))?;
+            }
+        },
+        _ => {
+            let mut contents = Vec::new();
+            input.read_to_end(&mut contents).map_err(|e| io_error_message(&e))?;
+            let text = match options.decoding {
+                Some(Decoding::Auto) => Encoding::sniff(&contents).decode(&contents).into_bytes(),
+                Some(Decoding::Fixed(encoding)) => encoding.decode(&contents).into_bytes(),
+                None => contents,
+            };
+            // Byte ranges are of the input as it is, as they are for files
+            let strip_bom = options.strip_bom && !matches!(*dump_mode, DumpMode::ByteRange(..));
+            let start = if strip_bom && text.starts_with(encoding::UTF8_BOM) { encoding::UTF8_BOM.len() } else { 0 };
+            print_selected(&text[start..], dump_mode, options, &mut writer).map_err(|e| io_error_message(&e))?;
+        },
+    }
+    writer.flush().map_err(|e| io_error_message(&e))?;
+    finish_output(writer.get_mut()).map_err(|e| io_error_message(&e))?;
+    if options.checksum {
+        print_checksum(STDIN_NAME, output_checksum(writer.get_ref()));
+    }
+    Ok(())
+}
+
+fn dump<W: Write>(sf: &mut StatefulFile, dump_mode: &DumpMode, options: DumpOptions,
+                  writer: &mut BufWriter<W>) -> Result<(), String> {
+    if is_gzip_file(sf).map_err(|e| io_error_message(&e))? {
+        return print_gzip(sf, dump_mode, options, writer);
+    }
+    let encoding = match options.decoding {
+        Some(Decoding::Auto) => {
+            let mut sample = Vec::with_capacity(encoding::SNIFF_LEN);
+            sf.fd.by_ref().take(encoding::SNIFF_LEN as u64).read_to_end(&mut sample)
+                .and_then(|_| sf.fd.seek(SeekFrom::Start(0)))
+                .map_err(|e| io_error_message(&e))?;
+            Encoding::sniff(&sample)
+        },
+        Some(Decoding::Fixed(encoding)) => encoding,
+        None => Encoding::Utf8,
+    };
+    // Byte counts are about the file's bytes, so they're never decoded
+    let is_bytes = matches!(*dump_mode, DumpMode::ByteRange(..) | DumpMode::Bytes(..));
+    let printed = if encoding != Encoding::Utf8 && !is_bytes {
+        print_decoded(sf, encoding, dump_mode, options, writer)
+    } else {
+        match *dump_mode {
+            DumpMode::Lines(ref num_lines_str) => print_lines(sf, num_lines_str, options, writer),
+            DumpMode::HeadTail(head, tail) => print_head_tail(sf, head, tail, options, writer),
+            DumpMode::ByteRange(start, end) => print_byte_range(sf, start, end, writer),
+            DumpMode::Bytes(from_start, num_bytes) => print_bytes(sf, from_start, num_bytes, options, writer),
+        }
+    };
+    printed.map_err(|e| io_error_message(&e))
+}
+
+/// Checks for the gzip magic bytes, leaving `sf` at the start of the file
+fn is_gzip_file(sf: &mut StatefulFile) -> io::Result<bool> {
+    let mut magic = Vec::with_capacity(2);
+    sf.fd.by_ref().take(2).read_to_end(&mut magic)?;
+    sf.fd.seek(SeekFrom::Start(0))?;
+    Ok(gzip::is_gzip(&magic))
+}
+
+/// Checks for a NUL byte in the first chunk of a file, as grep does, leaving
+/// `sf` at the start of the file. Compressed files are decompressed before
+/// they're printed, so they don't count.
+fn is_binary_file(sf: &mut StatefulFile) -> io::Result<bool> {
+    let mut chunk = Vec::with_capacity(BINARY_SNIFF_LEN);
+    sf.fd.by_ref().take(BINARY_SNIFF_LEN as u64).read_to_end(&mut chunk)?;
+    sf.fd.seek(SeekFrom::Start(0))?;
+    Ok(!gzip::is_gzip(&chunk) && chunk.contains(&0))
+}
+
+/// Prints from the decompressed contents of a file made of gzip members,
+/// leaving `sf` at the end of the last complete member so following picks up
+/// from there
+fn print_gzip<W: Write>(sf: &mut StatefulFile, dump_mode: &DumpMode, options: DumpOptions,
+                        writer: &mut BufWriter<W>) -> Result<(), String> {
+    let (decoded, consumed) = read_gzip_members(sf).map_err(|e| io_error_message(&e))?;
+    if consumed == 0 {
+        return Err(String::from("truncated gzip stream; only complete gzip members can be read"));
+    }
+    let text = if options.strip_bom { decoded.strip_prefix(encoding::UTF8_BOM).unwrap_or(&decoded) } else { &decoded };
+    print_selected(text, dump_mode, options, writer).map_err(|e| io_error_message(&e))
+}
+
+/// Decodes the complete gzip members from the current position on, leaving
+/// `sf` after the last of them. Returns the decoded bytes and how many bytes
+/// of the file they came from.
+fn read_gzip_members(sf: &mut StatefulFile) -> io::Result<(Vec<u8>, usize)> {
+    let start = sf.fd.stream_position()?;
+    let mut compressed = Vec::new();
+    sf.fd.read_to_end(&mut compressed)?;
+    let (decoded, consumed) = gzip::decode_members(&compressed)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    sf.fd.seek(SeekFrom::Start(start + consumed as u64))?;
+    Ok((decoded, consumed))
+}
+
+/// Prints what was appended to `sf` after `offset`, where a previous run
+/// stopped, instead of the usual initial output
+fn print_resumed(sf: &mut StatefulFile, offset: u64, gzip: bool, options: DumpOptions,
+                 filter: Option<&LineFilter>) -> io::Result<()> {
+    let mut writer = BufWriter::new(output_writer(sf.file_name(), filter, options));
+    sf.fd.seek(SeekFrom::Start(offset))?;
+    if gzip {
+        writer.write_all(&read_gzip_members(sf)?.0)?;
+    } else {
+        let len = sf.old_metadata.len().max(offset);
+        std::io::copy(&mut sf.fd.by_ref().take(len - offset), &mut writer)?;
+    }
+    writer.flush()?;
+    finish_output(writer.get_mut())
+}
+
+/// `--quiet-initial`: leaves `sf` at the end it had when it was opened, for
+/// following to start from
+fn skip_to_end(sf: &mut StatefulFile, gzip: bool) -> Result<(), String> {
+    if gzip {
+        read_gzip_members(sf).map_err(|e| io_error_message(&e))?;
+    } else {
+        sf.fd.seek(SeekFrom::Start(sf.old_metadata.len())).map_err(|e| io_error_message(&e))?;
+    }
+    Ok(())
+}
+
+/// `--end-marker`, printed once a file's output is over, on a line of its own.
+/// A failure to print it is reported, setting `failed`.
+fn print_end_marker(end_marker: &Option<String>, file_name: &str, delimiter: u8, failed: &mut bool) {
+    if let Some(ref format) = *end_marker {
+        let last = LAST_OUTPUT_BYTE.load(Ordering::Relaxed);
+        let start = if last == b'\n' || last == delimiter { "" } else { "\n" };
+        let mut stdout = Stdout::new();
+        let printed = writeln!(stdout, "{}{}", start, format.replace("{file}", file_name)).and_then(|_| stdout.flush());
+        if let Err(e) = printed {
+            eprintln!("tail: {}: {}", file_name, io_error_message(&e));
+            *failed = true;
+        }
+    }
+}
+
+/// Records where each file is up to in the `--state-file`, if there is one
+fn save_state<K>(state: &mut Option<StateFile>, files: &HashMap<K, StatefulFile>) {
+    if let Some(ref mut state) = *state {
+        for sf in files.values() {
+            state.update(sf.file_name(), &sf.old_metadata, sf.cursor());
+        }
+        state.save().unwrap_or_else(|e| eprintln!("tail: cannot save state: {}", io_error_message(&e)));
+    }
+}
+
+/// Line modes for files in other encodings. The lines can't be found by
+/// scanning for newline bytes, so the whole file is decoded first.
+fn print_decoded<W: Write>(sf: &mut StatefulFile, encoding: Encoding, dump_mode: &DumpMode, options: DumpOptions,
+                           writer: &mut BufWriter<W>) -> io::Result<()> {
+    let mut raw = Vec::new();
+    sf.fd.read_to_end(&mut raw)?;
+    let text = encoding.decode(&raw);
+    print_selected(text.as_bytes(), dump_mode, options, writer)
+}
+
+/// Prints the part of `contents` that `dump_mode` selects, for contents that
+/// had to be read into memory in full
+fn print_selected<W: Write>(contents: &[u8], dump_mode: &DumpMode, options: DumpOptions, writer: &mut BufWriter<W>) -> io::Result<()> {
+    let lines: Vec<&[u8]> = contents.split_inclusive(|b| *b == options.delimiter).collect();
+    match *dump_mode {
+        DumpMode::Lines(ref num_lines_str) => {
+            let (from_start, num_lines) = parse_num_lines(num_lines_str);
+            let first = if from_start {
+                lines_to_skip(num_lines).min(lines.len())
+            } else {
+                lines.len().saturating_sub(num_lines)
+            };
+            copy_lines(&mut &contents[offset_of(&lines, first)..], first + 1, options, writer)?;
+        },
+        DumpMode::HeadTail(head, tail) if head + tail < lines.len() => {
+            copy_lines(&mut &contents[..offset_of(&lines, head)], 1, options, writer)?;
+            writeln!(writer, "... {} lines omitted ...", lines.len() - head - tail)?;
+            let first = lines.len() - tail;
+            copy_lines(&mut &contents[offset_of(&lines, first)..], first + 1, options, writer)?;
+        },
+        DumpMode::HeadTail(..) => copy_lines(&mut &contents[..], 1, options, writer)?,
+        DumpMode::ByteRange(start, end) => {
+            let len = contents.len() as u64;
+            writer.write_all(&contents[start.min(len) as usize..end.min(len) as usize])?;
+        },
+        DumpMode::Bytes(from_start, num_bytes) => {
+            let len = contents.len() as u64;
+            let first = bytes_start(from_start, num_bytes, len) as usize;
+            writer.write_all(&contents[first..])?;
+        },
+    }
+    Ok(())
+}
+
+/// `--line-numbers`: where the numbering of `sf`'s lines is up to at byte
+/// `end`, for following to carry on from there. The read position is restored
+/// afterwards.
+fn line_numbers_at(sf: &mut StatefulFile, end: u64, gzip: bool, delimiter: u8) -> io::Result<LineNumbers> {
+    let position = sf.fd.stream_position()?;
+    sf.fd.seek(SeekFrom::Start(0))?;
+    let counted = if gzip {
+        let decoded = read_gzip_members(sf).map(|(decoded, _)| decoded).unwrap_or_default();
+        count_remaining_lines(&mut Cursor::new(decoded), delimiter)
+    } else {
+        count_remaining_lines(&mut sf.fd.by_ref().take(end), delimiter)
+    };
+    sf.fd.seek(SeekFrom::Start(position))?;
+    let (lines, ends_with_delimiter) = counted?;
+    Ok(LineNumbers::after(lines, ends_with_delimiter, delimiter))
+}
+
+/// Where line `n` starts, counting from 0, in the text `lines` were split from
+fn offset_of(lines: &[&[u8]], n: usize) -> usize {
+    lines[..n].iter().map(|line| line.len()).sum()
+}
+
+/// Copies `source`, which starts at line `first`, to `writer`, numbering its
+/// lines with `--line-numbers`
+fn copy_lines<R: Read, W: Write>(source: &mut R, first: usize, options: DumpOptions, writer: &mut W) -> io::Result<()> {
+    if options.line_numbers {
+        let numbers = LineNumbers::starting_at(first, options.delimiter);
+        std::io::copy(source, &mut NumberedWriter::new(writer, numbers))?;
+    } else {
+        std::io::copy(source, writer)?;
+    }
+    Ok(())
+}
+
+fn print_byte_range<W: Write>(sf: &mut StatefulFile, start: u64, end: u64, writer: &mut BufWriter<W>) -> io::Result<()> {
+    sf.fd.seek(SeekFrom::Start(start))?;
+    std::io::copy(&mut sf.fd.by_ref().take(end - start), writer)?;
+    Ok(())
+}
+
+/// Where `-c` output starts in `len` bytes. `+0` and `+1` both mean the first
+/// byte, and offsets past the end leave nothing to print.
+fn bytes_start(from_start: bool, num_bytes: u64, len: u64) -> u64 {
+    if from_start {
+        num_bytes.saturating_sub(1).min(len)
+    } else {
+        len - num_bytes.min(len)
+    }
+}
+
+/// With `--strip-bom`, the bytes are counted from after the byte order mark
+fn print_bytes<W: Write>(sf: &mut StatefulFile, from_start: bool, num_bytes: u64, options: DumpOptions,
+                         writer: &mut BufWriter<W>) -> io::Result<()> {
+    if !from_start && !options.strip_bom {
+        return TailBuilder::new(sf.file_name()).bytes(num_bytes).write_tail(sf, writer).map_err(io::Error::from);
+    }
+    let len = sf.old_metadata.len();
+    let text_start = seek_to_start(sf, options)?.min(len);
+    let start = text_start + bytes_start(from_start, num_bytes, len - text_start);
+    sf.fd.seek(SeekFrom::Start(start))?;
+    std::io::copy(&mut sf.fd.by_ref().take(len - start), writer)?;
+    Ok(())
+}
+
+fn print_head_tail<W: Write>(sf: &mut StatefulFile, head: usize, tail: usize, options: DumpOptions,
+                             writer: &mut BufWriter<W>) -> io::Result<()> {
+    let delimiter = options.delimiter;
+    let total = sf.count_lines(delimiter)?;
+    let start = seek_to_start(sf, options)?;
+    if head + tail >= total {
+        let len = sf.old_metadata.len().max(start);
+        return copy_lines(&mut sf.fd.by_ref().take(len - start), 1, options, writer);
+    }
+
+    let head_end = seek_to_line(&mut sf.fd, head, delimiter)?;
+    sf.fd.seek(SeekFrom::Start(start))?;
+    copy_lines(&mut sf.fd.by_ref().take(head_end - start), 1, options, writer)?;
+    writeln!(writer, "... {} lines omitted ...", total - head - tail)?;
+
+    print_last_lines(tail, sf, options, writer)
+}
+
+fn print_lines<W: Write>(sf: &mut StatefulFile, num_lines_str: &str, options: DumpOptions,
+                         writer: &mut BufWriter<W>) -> io::Result<()> {
+    let (from_start, num_lines) = parse_num_lines(num_lines_str);
+    if from_start {
+        seek_to_start(sf, options)?;
+        let skip = lines_to_skip(num_lines);
+        let start = seek_to_line(&mut sf.fd, skip, options.delimiter)?;
+        // Up to the length the file had when it was opened, as following
+        // carries on from there
+        let len = sf.old_metadata.len().max(start);
+        return copy_lines(&mut sf.fd.by_ref().take(len - start), skip + 1, options, writer);
+    }
+
+    print_last_lines(num_lines, sf, options, writer)
+}
+
+fn print_last_lines<W: Write>(num_lines: usize, sf: &mut StatefulFile, options: DumpOptions,
+                              writer: &mut BufWriter<W>) -> io::Result<()> {
+    let mut tail = TailBuilder::new(sf.file_name()).lines(num_lines).delimiter(options.delimiter);
+    if let Some(max_bytes) = options.max_scan_bytes {
+        tail = tail.max_scan_bytes(max_bytes);
+    }
+    if options.strip_bom {
+        tail = tail.skip_bom();
+    }
+    if !options.line_numbers {
+        return tail.write_tail(sf, writer).map_err(io::Error::from);
+    }
+    // The numbers count back from the last line, so the lines are gathered
+    // first to see how many there are
+    let total = sf.count_lines(options.delimiter)?;
+    let mut text = Vec::new();
+    tail.write_tail(sf, &mut text)?;
+    let lines = text.split_inclusive(|b| *b == options.delimiter).count();
+    copy_lines(&mut text.as_slice(), total + 1 - lines, options, writer)
+}
+
+/// Seeks to where the file's text starts, which is after its byte order mark
+/// with `--strip-bom`, and returns that offset
+fn seek_to_start(sf: &mut StatefulFile, options: DumpOptions) -> io::Result<u64> {
+    sf.fd.seek(SeekFrom::Start(0))?;
+    if options.strip_bom {
+        let mut start = Vec::with_capacity(encoding::UTF8_BOM.len());
+        sf.fd.by_ref().take(encoding::UTF8_BOM.len() as u64).read_to_end(&mut start)?;
+        if start == encoding::UTF8_BOM {
+            return Ok(start.len() as u64);
+        }
+        sf.fd.seek(SeekFrom::Start(0))?;
+    }
+    Ok(0)
+}
+
+/// Copies everything from `sf` to `writer` as it arrives, until its writers are gone
+fn stream_to_eof<W: Write>(sf: &mut StatefulFile, writer: &mut W) -> io::Result<()> {
+    let mut buff = [0u8; 4096];
+    loop {
+        match sf.fd.read(&mut buff) {
+            Ok(0) => return Ok(()),
+            Ok(bytes_read) => {
+                writer.write_all(&buff[..bytes_read])?;
+                writer.flush()?;
+            },
+            Err(ref e) if e.kind() == std::io::ErrorKind::Interrupted => {},
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process::{Command, Stdio};
+    use std::rc::Rc;
+    use std::time::Instant;
+    use tail::clock::{Clock, MockClock};
+    use watch::{Event, PollWatcher};
+
+    /// What's been written, shared between the follower writing it and the
+    /// test looking at it
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Shared {
+        fn bytes(&self) -> Vec<u8> {
+            self.0.borrow().clone()
+        }
+
+        fn text(&self) -> String {
+            String::from_utf8_lossy(&self.0.borrow()).into_owned()
+        }
+    }
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Polls by the test's clock, making the next of `changes` before each
+    /// wait, so each change is seen on a round of its own
+    struct Scripted {
+        poll: PollWatcher,
+        changes: VecDeque<Box<dyn FnOnce()>>,
+        output: Shared,
+        /// What had been written out when each change was made
+        seen: Vec<String>,
+    }
+
+    impl FileWatcher for Scripted {
+        fn watch(&mut self, path: &Path) -> io::Result<WatchId> {
+            self.poll.watch(path)
+        }
+
+        fn watch_dir(&mut self, dir: &Path) -> io::Result<WatchId> {
+            self.poll.watch_dir(dir)
+        }
+
+        fn unwatch(&mut self, id: WatchId) {
+            self.poll.unwatch(id)
+        }
+
+        fn wait(&mut self, timeout: Option<Duration>, _wake_fds: &[RawFd], clock: &dyn Clock) -> io::Result<WaitResult> {
+            if let Some(change) = self.changes.pop_front() {
+                self.seen.push(self.output.text());
+                change();
+            }
+            // No signals come in tests, and `--pid` is checked every interval
+            // anyway, so there's only the clock to wait on
+            self.poll.wait(timeout, &[], clock)
+        }
+
+        fn next_events(&mut self) -> io::Result<Vec<Event>> {
+            self.poll.next_events()
+        }
+    }
+
+    /// Follows files in a directory of the test's own, with time told by a
+    /// `MockClock`, so none of it is spent really waiting
+    struct Following {
+        dir: PathBuf,
+        clock: MockClock,
+        started: Instant,
+        watcher: Scripted,
+        follower: Follower,
+        files: HashMap<WatchId, StatefulFile>,
+        pending: Vec<String>,
+        pid_watch: Option<PidWatch>,
+        report: ErrorReport,
+        state: Option<StateFile>,
+        follow_mode: FollowMode,
+        timeout: Duration,
+        exit_on_idle: Option<Duration>,
+        failed: bool,
+    }
+
+    impl Following {
+        fn new(test: &str) -> Following {
+            let dir = std::env::temp_dir().join(format!("tail-follow-{}-{}", std::process::id(), test));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            let clock = MockClock::new();
+            let output = Shared::default();
+            Following {
+                dir,
+                started: clock.now(),
+                clock,
+                watcher: Scripted {
+                    poll: PollWatcher::new(DEFAULT_SLEEP_INTERVAL),
+                    changes: VecDeque::new(),
+                    output: output.clone(),
+                    seen: Vec::new(),
+                },
+                follower: Follower {
+                    warn_size: None,
+                    max_unchanged_stats: DEFAULT_MAX_UNCHANGED_STATS,
+                    unchanged_stats: HashMap::new(),
+                    router: None,
+                    lines_emitted: 0,
+                    decoders: HashMap::new(),
+                    global_number: false,
+                    json_pending: None,
+                    headers: Headers::new(false, false),
+                    gzip_files: HashSet::new(),
+                    write_error: None,
+                    write_failed: false,
+                    delimiter: b'\n',
+                    writer: BufWriter::new(Box::new(output)),
+                    line_numbers: None,
+                    timestamps: false,
+                    json_output: false,
+                    output_delimiter: None,
+                    line_filters: LineFilters::new(LineFilter::new(None, None).unwrap()),
+                    delimiter_held: false,
+                },
+                files: HashMap::new(),
+                pending: Vec::new(),
+                pid_watch: None,
+                report: ErrorReport::default(),
+                state: None,
+                follow_mode: FollowMode::Descriptor,
+                timeout: Duration::from_secs(10),
+                exit_on_idle: None,
+                failed: false,
+            }
+        }
+
+        fn path(&self, name: &str) -> String {
+            self.dir.join(name).to_str().unwrap().to_string()
+        }
+
+        fn write(&self, name: &str, contents: &[u8]) -> String {
+            let path = self.path(name);
+            fs::write(&path, contents).unwrap();
+            path
+        }
+
+        /// Follows `file` from its end, as after its initial output
+        fn follow(&mut self, file: &str) {
+            let wd = self.watcher.watch(Path::new(file)).unwrap();
+            let mut sf = StatefulFile::new(File::open(file).unwrap(), file.to_string()).unwrap();
+            sf.fd.seek(SeekFrom::End(0)).unwrap();
+            sf.update_cursor().unwrap();
+            self.files.entry(wd).or_insert(sf);
+        }
+
+        /// As if `files`' initial output had been printed under headers, in
+        /// that order
+        fn headers_printed(&mut self, files: &[&str]) {
+            for file in files {
+                self.follower.headers.print(file, &mut io::sink()).unwrap();
+            }
+        }
+
+        /// Makes `change` on the next round of following
+        fn then<F: FnOnce() + 'static>(&mut self, change: F) {
+            self.watcher.changes.push_back(Box::new(change));
+        }
+
+        /// Follows until something stops it, returning whether it idled out
+        fn run(&mut self) -> bool {
+            let mut fifos = Vec::new();
+            let mut glob_matched = HashSet::new();
+            let dump_modes = HashMap::new();
+            let default_dump_mode = DumpMode::Lines(String::from("10"));
+            let mut follow_loop = FollowLoop {
+                watcher: &mut self.watcher,
+                timers: Timers::new(&self.clock, Some(self.timeout), self.exit_on_idle, DEFAULT_SLEEP_INTERVAL),
+                follow_mode: self.follow_mode,
+                sleep_interval: DEFAULT_SLEEP_INTERVAL,
+                files: &mut self.files,
+                fifos: &mut fifos,
+                pending: &mut self.pending,
+                globs: &[],
+                glob_matched: &mut glob_matched,
+                pid_watch: self.pid_watch.as_mut(),
+                report: &mut self.report,
+                state: &mut self.state,
+                dump_modes: &dump_modes,
+                default_dump_mode: &default_dump_mode,
+                reprint_options: DumpOptions {
+                    decoding: None,
+                    checksum: false,
+                    delimiter: b'\n',
+                    max_scan_bytes: None,
+                    strip_bom: false,
+                    line_numbers: false,
+                    json_output: false,
+                    output_delimiter: None,
+                },
+                inaccessible: HashSet::new(),
+            };
+            let idled_out = follow_loop.run(&mut self.follower, &mut self.failed);
+            follow_loop.finish(&mut self.follower, &mut self.failed);
+            idled_out
+        }
+
+        fn output(&self) -> String {
+            self.watcher.output.text()
+        }
+
+        /// How long following took by the clock
+        fn elapsed(&self) -> Duration {
+            self.clock.now() - self.started
+        }
+
+        fn report(&self) -> String {
+            let mut report = Vec::new();
+            self.report.write_to(&mut report).unwrap();
+            String::from_utf8(report).unwrap()
+        }
+    }
+
+    fn append(file: &str, data: &[u8]) {
+        OpenOptions::new().append(true).create(true).open(file).unwrap().write_all(data).unwrap();
+    }
+
+    /// The PID of a process that has exited and been reaped
+    fn exited_pid() -> u32 {
+        let mut child = Command::new("true").spawn().unwrap();
+        child.wait().unwrap();
+        child.id()
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut child = Command::new("gzip").args(["-c", "-n"])
+            .stdin(Stdio::piped()).stdout(Stdio::piped()).spawn().unwrap();
+        child.stdin.take().unwrap().write_all(data).unwrap();
+        child.wait_with_output().unwrap().stdout
+    }
+
+    #[test]
+    fn a_burst_of_appends_comes_out_whole() {
+        let mut following = Following::new("burst");
+        let file = following.write("f.log", b"");
+        following.follow(&file);
+        let expected: String = (0..20_000).map(|i| format!("line {}\n", i)).collect();
+        let burst = expected.clone();
+        following.then(move || append(&file, burst.as_bytes()));
+        following.run();
+        assert!(following.output() == expected, "{} of {} bytes", following.output().len(), expected.len());
+        assert!(!following.failed);
+    }
+
+    #[test]
+    fn each_batch_is_flushed_before_the_next_wait() {
+        let mut following = Following::new("flush");
+        let file = following.write("f.log", b"");
+        following.follow(&file);
+        for &data in &[&b"one\n"[..], b"two\n", b""] {
+            let file = file.clone();
+            following.then(move || append(&file, data));
+        }
+        following.run();
+        assert_eq!(following.watcher.seen, ["", "one\n", "one\ntwo\n"]);
+    }
+
+    #[test]
+    fn a_line_written_in_pieces_is_printed_whole() {
+        let mut following = Following::new("pieces");
+        following.follower.headers = Headers::new(true, false);
+        let a = following.write("a.log", b"");
+        let b = following.write("b.log", b"");
+        following.follow(&a);
+        following.follow(&b);
+        following.headers_printed(&[&a, &b]);
+        for &(file, data) in &[(&a, &b"hel"[..]), (&b, b"x\n"), (&a, b"lo\n")] {
+            let file = file.clone();
+            following.then(move || append(&file, data));
+        }
+        following.run();
+        assert_eq!(following.output(), format!("x\n\n==> {} <==\nhello\n", a));
+
+        // What's left of a line when following stops is still printed
+        let mut following = Following::new("unfinished");
+        let file = following.write("f.log", b"");
+        following.follow(&file);
+        for &data in &[&b"hel"[..], b"lo\nunfinished"] {
+            let file = file.clone();
+            following.then(move || append(&file, data));
+        }
+        following.run();
+        assert_eq!(following.output(), "hello\nunfinished");
+    }
+
+    #[test]
+    fn a_read_error_keeps_what_was_read_as_the_partial_line() {
+        /// Reads `data`, and then fails
+        struct Failing(Cursor<&'static [u8]>);
+
+        impl Read for Failing {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                match self.0.read(buf)? {
+                    0 => Err(io::Error::other("device gone")),
+                    read => Ok(read),
+                }
+            }
+        }
+
+        let mut following = Following::new("read-error");
+        let mut partial = Vec::new();
+        let mut failing = io::BufReader::new(Failing(Cursor::new(b"whole\npar")));
+        following.follower.print_from("f.log", &mut failing, &mut partial);
+        assert_eq!(partial, b"par");
+        following.follower.print_from("f.log", &mut Cursor::new(b"tial\n"), &mut partial);
+        following.follower.flush();
+        assert_eq!(following.output(), "whole\npartial\n");
+    }
+
+    #[test]
+    fn a_header_is_printed_only_when_the_output_switches_files() {
+        for &quiet in &[false, true] {
+            let mut following = Following::new(if quiet { "switch-quiet" } else { "switch" });
+            following.follower.headers = Headers::new(!quiet, false);
+            let a = following.write("a.log", b"a0\n");
+            let b = following.write("b.log", b"b0\n");
+            following.follow(&a);
+            following.follow(&b);
+            following.headers_printed(&[&a, &b]);
+            for &(file, data) in &[(&b, &b"b1\n"[..]), (&b, b"b2\n"), (&a, b"a1\n")] {
+                let file = file.clone();
+                following.then(move || append(&file, data));
+            }
+            following.run();
+            let expected = if quiet { String::from("b1\nb2\na1\n") } else { format!("b1\nb2\n\n==> {} <==\na1\n", a) };
+            assert_eq!(following.output(), expected, "-q: {}", quiet);
+        }
+    }
+
+    #[test]
+    fn header_once_keeps_headers_from_coming_back_after_a_rotation() {
+        for &once in &[false, true] {
+            let mut following = Following::new(if once { "header-once" } else { "header-every" });
+            following.follower.headers = Headers::new(true, once);
+            following.follow_mode = FollowMode::Name;
+            let a = following.write("a.log", b"a0\n");
+            let b = following.write("b.log", b"b0\n");
+            following.follow(&a);
+            following.follow(&b);
+            following.headers_printed(&[&a, &b]);
+            for &(file, data) in &[(&a, &b"a1\n"[..]), (&b, b"b1\n")] {
+                let file = file.clone();
+                following.then(move || append(&file, data));
+            }
+            let (rotated, new) = (a.clone(), a.clone());
+            following.then(move || {
+                fs::rename(&rotated, format!("{}.1", rotated)).unwrap();
+                fs::write(&new, b"a2\n").unwrap();
+            });
+            let later = b.clone();
+            following.then(move || append(&later, b"b2\n"));
+            following.run();
+            let expected = if once {
+                String::from("a1\nb1\na2\nb2\n")
+            } else {
+                format!("\n==> {a} <==\na1\n\n==> {b} <==\nb1\n\n==> {a} <==\na2\n\n==> {b} <==\nb2\n", a = a, b = b)
+            };
+            assert_eq!(following.output(), expected, "--header-once: {}", once);
+        }
+    }
+
+    #[test]
+    fn following_carries_on_the_line_numbers() {
+        let mut following = Following::new("numbers");
+        let file = following.write("f.log", b"a\nb");
+        following.follow(&file);
+        let mut line_numbers = HashMap::new();
+        line_numbers.insert(file.clone(), LineNumbers::after(2, false, b'\n'));
+        following.follower.line_numbers = Some(line_numbers);
+        let appended = file.clone();
+        following.then(move || append(&appended, b"c\nd\n"));
+        following.run();
+        assert_eq!(following.output(), "c\n     3\td\n");
+    }
+
+    #[test]
+    fn global_number_counts_up_across_the_followed_files() {
+        let mut following = Following::new("global-number");
+        following.follower.global_number = true;
+        let a = following.write("a.log", b"a0\n");
+        let b = following.write("b.log", b"b0\n");
+        following.follow(&a);
+        following.follow(&b);
+        for &(file, data) in &[(&a, &b"a1\na2\n"[..]), (&b, b"b1\n"), (&a, b"a3\n"), (&b, b"b2\nb3\n")] {
+            let file = file.clone();
+            following.then(move || append(&file, data));
+        }
+        following.run();
+        assert_eq!(following.output(), "     1\ta1\n     2\ta2\n     3\tb1\n     4\ta3\n     5\tb2\n     6\tb3\n");
+    }
+
+    #[test]
+    fn invalid_utf8_is_followed_byte_for_byte() {
+        let mut following = Following::new("invalid-utf8");
+        let file = following.write("binary.log", b"text\n");
+        following.follow(&file);
+        let appended = file.clone();
+        following.then(move || append(&appended, b"\xc3(\x80\n"));
+        following.run();
+        assert_eq!(following.watcher.output.bytes(), b"\xc3(\x80\n");
+    }
+
+    #[test]
+    fn json_complete_prints_a_value_written_in_two_flushes_once_whole() {
+        let mut following = Following::new("json-complete");
+        following.follower.json_pending = Some(HashMap::new());
+        let file = following.write("f.jsonl", b"");
+        following.follow(&file);
+        for &data in &[&b"{\"msg\": \"first\"}\n{\"msg\": \"a\nb {\", "[..], b"\"n\": [1, 2]}\n"] {
+            let file = file.clone();
+            following.then(move || append(&file, data));
+        }
+        following.run();
+        assert_eq!(following.watcher.seen[1], "{\"msg\": \"first\"}\n");
+        assert_eq!(following.output(), "{\"msg\": \"first\"}\n{\"msg\": \"a\nb {\", \"n\": [1, 2]}\n");
+    }
+
+    #[test]
+    fn route_splits_followed_lines_between_files() {
+        let mut following = Following::new("route");
+        let file = following.write("f.log", b"ERROR before following\n");
+        let (errors, access) = (following.path("errors.log"), following.path("access.log"));
+        let mut router = Router::default();
+        router.add_route(&format!("^ERROR:{}", errors)).unwrap();
+        router.add_route(&format!("^ACCESS:{}", access)).unwrap();
+        following.follower.router = Some(router);
+        following.follow(&file);
+        let appended = file.clone();
+        following.then(move || append(&appended, b"ACCESS 1\nERROR 1\nother\nACCESS 2\nERROR 2\n"));
+        following.run();
+        assert_eq!(following.output(), "other\n");
+        assert_eq!(fs::read_to_string(&errors).unwrap(), "ERROR 1\nERROR 2\n");
+        assert_eq!(fs::read_to_string(&access).unwrap(), "ACCESS 1\nACCESS 2\n");
+    }
+
+    #[test]
+    fn a_character_split_between_appends_is_still_matched() {
+        let mut following = Following::new("split-character");
+        let file = following.write("f.log", b"");
+        let routed = following.path("routed.log");
+        following.follower.line_filters = LineFilters::new(LineFilter::new(Some("caf\u{e9}"), None).unwrap());
+        let mut router = Router::default();
+        router.add_route(&format!("^caf\u{e9} 2:{}", routed)).unwrap();
+        following.follower.router = Some(router);
+        following.follow(&file);
+        // "é" is 0xC3 0xA9
+        for &data in &[&b"cafe\ncaf\xC3"[..], b"\xA9 1\ncaf\xC3", b"\xA9 2\n"] {
+            let file = file.clone();
+            following.then(move || append(&file, data));
+        }
+        following.run();
+        assert_eq!(following.output(), "caf\u{e9} 1\n");
+        assert_eq!(fs::read_to_string(&routed).unwrap(), "caf\u{e9} 2\n");
+    }
+
+    #[test]
+    fn following_a_gzip_file_prints_each_member_appended_once_it_is_whole() {
+        let mut following = Following::new("gzip");
+        let file = following.write("f.log.gz", &gzip(b"one\ntwo\nthree\n"));
+        following.follower.gzip_files.insert(file.clone());
+        following.follow(&file);
+        let member = gzip(b"five\nsix\n");
+        let (first_half, second_half) = member.split_at(member.len() / 2);
+        for data in [gzip(b"four\n"), first_half.to_vec(), second_half.to_vec()] {
+            let file = file.clone();
+            following.then(move || append(&file, &data));
+        }
+        following.run();
+        // Nothing of the second member until it's whole
+        assert_eq!(following.watcher.seen[2], "four\n");
+        assert_eq!(following.output(), "four\nfive\nsix\n");
+    }
+
+    #[test]
+    fn truncation_carries_on_from_the_new_end() {
+        let mut following = Following::new("truncated");
+        let lines: Vec<String> = (1..=100).map(|i| format!("line {}\n", i)).collect();
+        let file = following.write("t.log", lines.concat().as_bytes());
+        following.follow(&file);
+        let first_ten = lines[..10].concat().len() as u64;
+        let truncated = file.clone();
+        following.then(move || OpenOptions::new().write(true).open(&truncated).unwrap().set_len(first_ten).unwrap());
+        let appended: String = (1..=5).map(|i| format!("appended {}\n", i)).collect();
+        let (file, data) = (file.clone(), appended.clone());
+        following.then(move || append(&file, data.as_bytes()));
+        following.run();
+        assert_eq!(following.output(), appended);
+    }
+
+    #[test]
+    fn follow_survives_copy_truncate_without_redumping() {
+        let mut following = Following::new("copy-truncate");
+        let old: String = (1..=20).map(|i| format!("old {}\n", i)).collect();
+        let file = following.write("app.log", old.as_bytes());
+        following.follow(&file);
+        // As logrotate's copytruncate does
+        let rotated = file.clone();
+        following.then(move || {
+            fs::copy(&rotated, format!("{}.1", rotated)).unwrap();
+            OpenOptions::new().write(true).open(&rotated).unwrap().set_len(0).unwrap();
+        });
+        let appended = file.clone();
+        following.then(move || append(&appended, b"new 1\n"));
+        following.run();
+        assert_eq!(following.output(), "new 1\n");
+        assert!(following.report().contains(&format!("{}: truncated 1 time", file)), "{}", following.report());
+    }
+
+    #[test]
+    fn truncated_and_inaccessible_files_are_reported() {
+        let mut following = Following::new("error-report");
+        following.follow_mode = FollowMode::Name;
+        let shrinking = following.write("shrinking.log", b"a long first line\n");
+        let vanishing = following.write("vanishing.log", b"here\n");
+        following.follow(&shrinking);
+        following.follow(&vanishing);
+        let (shrunk, vanished) = (shrinking.clone(), vanishing.clone());
+        following.then(move || {
+            fs::write(&shrunk, b"short\n").unwrap();
+            fs::remove_file(&vanished).unwrap();
+        });
+        following.run();
+        let report = following.report();
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines.len(), 3, "{}", report);
+        assert_eq!(lines[0], "tail: error report: 2 problem(s)");
+        assert!(lines[1].starts_with(&format!("{}: truncated 1 time, first ", shrinking)), "{}", report);
+        assert!(lines[2].starts_with(&format!("{}: inaccessible 1 time, first ", vanishing)), "{}", report);
+        assert!(lines[2].ends_with(": No such file or directory"), "{}", report);
+    }
+
+    #[test]
+    fn a_followed_name_that_becomes_a_symlink_loop_is_picked_up_again() {
+        let mut following = Following::new("symlink-loop");
+        following.follow_mode = FollowMode::Name;
+        let file = following.write("f.log", b"old\n");
+        following.follow(&file);
+        let looped = file.clone();
+        following.then(move || {
+            fs::remove_file(&looped).unwrap();
+            std::os::unix::fs::symlink(&looped, &looped).unwrap();
+        });
+        let back = file.clone();
+        following.then(move || {
+            fs::remove_file(&back).unwrap();
+            fs::write(&back, b"new\n").unwrap();
+        });
+        following.run();
+        assert_eq!(following.output(), "new\n");
+        assert!(following.report().contains(&format!("{}: inaccessible 1 time", file)), "{}", following.report());
+    }
+
+    #[test]
+    fn capital_f_follows_the_name_through_a_logrotate_cycle_and_retries() {
+        let mut following = Following::new("capital-f");
+        following.follow_mode = FollowMode::Name;
+        let log = following.write("app.log", b"old 1\n");
+        let missing = following.path("later.log");
+        following.follow(&log);
+        following.pending.push(missing.clone());
+        // logrotate moves the file aside, the program writes its last lines to
+        // it, and then starts a new one under the name
+        let (rotated, rotated_to) = (log.clone(), following.path("app.log.1"));
+        following.then(move || {
+            fs::rename(&rotated, &rotated_to).unwrap();
+            append(&rotated_to, b"old 2\n");
+        });
+        let new = log.clone();
+        following.then(move || append(&new, b"new 1\n"));
+        let (new, later) = (log.clone(), missing.clone());
+        following.then(move || {
+            append(&new, b"new 2\n");
+            append(&later, b"later 1\n");
+        });
+        following.run();
+        assert_eq!(following.output(), "old 2\nnew 1\nnew 2\nlater 1\n");
+        assert!(following.pending.is_empty());
+    }
+
+    #[test]
+    fn a_file_named_twice_is_followed_once() {
+        let mut following = Following::new("named-twice");
+        let file = following.write("a.log", b"a\n");
+        let link = following.path("link.log");
+        std::os::unix::fs::symlink(&file, &link).unwrap();
+        following.follow(&file);
+        following.follow(&file);
+        following.follow(&link);
+        assert_eq!(following.files.len(), 1);
+        let appended = file.clone();
+        following.then(move || append(&appended, b"b\n"));
+        following.run();
+        assert_eq!(following.output(), "b\n");
+    }
+
+    #[test]
+    fn following_saves_where_it_is_up_to_in_the_state_file() {
+        let mut following = Following::new("state-file");
+        let state = following.path("tail.state");
+        following.state = Some(StateFile::empty(&state));
+        let file = following.write("f.log", b"one\n");
+        following.follow(&file);
+        let appended = file.clone();
+        following.then(move || append(&appended, b"six\n"));
+        following.run();
+        assert_eq!(following.output(), "six\n");
+        let saved = StateFile::load(&state).unwrap();
+        assert_eq!(saved.resume_offset(&file, &fs::metadata(&file).unwrap()), Some(8));
+    }
+
+    #[test]
+    fn timeout_ends_following_however_busy_the_file_is() {
+        let mut following = Following::new("timeout");
+        following.exit_on_idle = Some(Duration::from_secs(2));
+        let file = following.write("busy.log", b"");
+        following.follow(&file);
+        // --exit-on-idle never fires, as the file grows on every round
+        for _ in 0..20 {
+            let file = file.clone();
+            following.then(move || append(&file, b"busy\n"));
+        }
+        assert!(!following.run());
+        assert_eq!(following.elapsed(), Duration::from_secs(10));
+        assert_eq!(following.output(), "busy\n".repeat(10));
+
+        // Whichever comes first ends it: nothing grows here, so idling out does
+        let mut following = Following::new("idle");
+        following.exit_on_idle = Some(Duration::from_secs(2));
+        let file = following.write("idle.log", b"");
+        following.follow(&file);
+        assert!(following.run());
+        assert_eq!(following.elapsed(), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn pid_ends_following_once_the_writer_exits_with_its_last_lines_printed() {
+        let mut following = Following::new("pid");
+        let file = following.write("out.log", b"first\n");
+        following.follow(&file);
+        // Written just before the writer exited, with no round to see it yet
+        append(&file, b"last\n");
+        following.pid_watch = Some(PidWatch::parse(&exited_pid().to_string(), PidMode::Any).unwrap());
+        assert!(!following.run());
+        assert_eq!(following.elapsed(), Duration::from_secs(0));
+        assert_eq!(following.output(), "last\n");
+    }
+
+    #[test]
+    fn pid_mode_picks_whether_any_or_all_of_the_pids_end_following() {
+        let pids = format!("{},{}", exited_pid(), std::process::id());
+        for &(mode, elapsed) in &[(PidMode::Any, 0), (PidMode::All, 10)] {
+            let mut following = Following::new("pid-mode");
+            let file = following.write("f.log", b"");
+            following.follow(&file);
+            following.pid_watch = Some(PidWatch::parse(&pids, mode).unwrap());
+            following.run();
+            assert_eq!(following.elapsed(), Duration::from_secs(elapsed), "{:?}", mode);
+        }
     }
-    writer.flush().unwrap();
 }
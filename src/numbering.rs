@@ -0,0 +1,84 @@
+//! `--line-numbers`: each line's number within its file, in front of it.
+
+use std::io::{self, Write};
+
+/// Where numbering is up to in a file
+#[derive(Debug, Clone, Copy)]
+pub struct LineNumbers {
+    next: usize,
+    /// Whether the next byte written starts a line, rather than carrying on
+    /// with a line that was cut off
+    at_line_start: bool,
+    delimiter: u8,
+}
+
+impl LineNumbers {
+    pub fn starting_at(first: usize, delimiter: u8) -> LineNumbers {
+        LineNumbers { next: first, at_line_start: true, delimiter }
+    }
+
+    /// Numbering for what's appended after `lines` lines, the last of which
+    /// may not have its delimiter yet
+    pub fn after(lines: usize, ends_with_delimiter: bool, delimiter: u8) -> LineNumbers {
+        if ends_with_delimiter || lines == 0 {
+            LineNumbers::starting_at(lines + 1, delimiter)
+        } else {
+            LineNumbers { next: lines + 1, at_line_start: false, delimiter }
+        }
+    }
+
+    /// Puts the next number in front of `data`, if it starts a line, and notes
+    /// whether the next data will. `data` holds at most one line.
+    pub fn number(&mut self, data: &mut Vec<u8>) {
+        if data.is_empty() {
+            return;
+        }
+        if self.at_line_start {
+            data.splice(0..0, prefix(self.next));
+            self.next += 1;
+        }
+        self.at_line_start = data.last() == Some(&self.delimiter);
+    }
+
+    fn write_to<W: Write>(&mut self, data: &[u8], writer: &mut W) -> io::Result<()> {
+        let delimiter = self.delimiter;
+        for line in data.split_inclusive(|b| *b == delimiter) {
+            if self.at_line_start {
+                writer.write_all(&prefix(self.next))?;
+                self.next += 1;
+            }
+            writer.write_all(line)?;
+            self.at_line_start = line.last() == Some(&delimiter);
+        }
+        Ok(())
+    }
+}
+
+/// Right aligned in 6 columns like `cat -n`, so numbers line up until they
+/// pass a million
+fn prefix(number: usize) -> Vec<u8> {
+    format!("{:6}\t", number).into_bytes()
+}
+
+/// Numbers the lines written through it
+pub struct NumberedWriter<W: Write> {
+    inner: W,
+    numbers: LineNumbers,
+}
+
+impl<W: Write> NumberedWriter<W> {
+    pub fn new(inner: W, numbers: LineNumbers) -> Self {
+        NumberedWriter { inner, numbers }
+    }
+}
+
+impl<W: Write> Write for NumberedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.numbers.write_to(buf, &mut self.inner)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
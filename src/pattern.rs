@@ -0,0 +1,234 @@
+//! A small backtracking regex engine for matching lines.
+//!
+//! Supports literals, `.`, character classes (`[a-z]`, `[^0-9]`), the escapes
+//! `\d`, `\w`, `\s` (and their negations `\D`, `\W`, `\S`), groups, `|`, the
+//! quantifiers `*`, `+`, `?` and `{m,n}`, and the anchors `^` and `$`. Matches
+//! are unanchored unless `^`/`$` say otherwise.
+
+#[derive(Debug, Clone)]
+enum ClassItem {
+    Range(char, char),
+    Digit(bool),
+    Word(bool),
+    Space(bool),
+}
+
+impl ClassItem {
+    fn matches(&self, c: char) -> bool {
+        match *self {
+            ClassItem::Range(lo, hi) => lo <= c && c <= hi,
+            ClassItem::Digit(negated) => c.is_ascii_digit() != negated,
+            ClassItem::Word(negated) => (c.is_alphanumeric() || c == '_') != negated,
+            ClassItem::Space(negated) => c.is_whitespace() != negated,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Any,
+    Class(Vec<ClassItem>, bool),
+    Start,
+    End,
+    /// Alternatives, each a sequence
+    Group(Vec<Vec<Node>>),
+    Repeat(Box<Node>, usize, Option<usize>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Regex {
+    root: [Node; 1],
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex, String> {
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut parser = Parser { chars: &chars, pos: 0 };
+        let alternatives = parser.parse_alternatives()?;
+        if parser.pos < chars.len() {
+            return Err(format!("invalid regex '{}': unmatched ')'", pattern));
+        }
+        Ok(Regex { root: [Node::Group(alternatives)] })
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        let chars: Vec<char> = text.chars().collect();
+        (0..=chars.len()).any(|start| match_seq(&self.root, &chars, start, &mut |_| true))
+    }
+}
+
+struct Parser<'a> {
+    chars: &'a [char],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).cloned()
+    }
+
+    fn next(&mut self) -> Result<char, String> {
+        let c = self.peek().ok_or_else(|| String::from("invalid regex: unexpected end of pattern"))?;
+        self.pos += 1;
+        Ok(c)
+    }
+
+    fn parse_alternatives(&mut self) -> Result<Vec<Vec<Node>>, String> {
+        let mut alternatives = vec![self.parse_sequence()?];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            alternatives.push(self.parse_sequence()?);
+        }
+        Ok(alternatives)
+    }
+
+    fn parse_sequence(&mut self) -> Result<Vec<Node>, String> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.parse_atom()?;
+            nodes.push(self.parse_quantifier(atom)?);
+        }
+        Ok(nodes)
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, String> {
+        Ok(match self.next()? {
+            '.' => Node::Any,
+            '^' => Node::Start,
+            '$' => Node::End,
+            '(' => {
+                let alternatives = self.parse_alternatives()?;
+                if self.next()? != ')' {
+                    return Err(String::from("invalid regex: unmatched '('"));
+                }
+                Node::Group(alternatives)
+            },
+            '[' => self.parse_class()?,
+            '\\' => Node::Class(vec![self.parse_escape()?], false),
+            c @ '*' | c @ '+' | c @ '?' => return Err(format!("invalid regex: nothing to repeat before '{}'", c)),
+            c => Node::Class(vec![ClassItem::Range(c, c)], false),
+        })
+    }
+
+    fn parse_escape(&mut self) -> Result<ClassItem, String> {
+        Ok(match self.next()? {
+            'd' => ClassItem::Digit(false),
+            'D' => ClassItem::Digit(true),
+            'w' => ClassItem::Word(false),
+            'W' => ClassItem::Word(true),
+            's' => ClassItem::Space(false),
+            'S' => ClassItem::Space(true),
+            't' => ClassItem::Range('\t', '\t'),
+            'n' => ClassItem::Range('\n', '\n'),
+            c => ClassItem::Range(c, c),
+        })
+    }
+
+    fn parse_class(&mut self) -> Result<Node, String> {
+        let negated = self.peek() == Some('^');
+        if negated {
+            self.pos += 1;
+        }
+        let mut items = Vec::new();
+        let mut first = true;
+        loop {
+            let c = self.next()?;
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+            let item = if c == '\\' { self.parse_escape()? } else { ClassItem::Range(c, c) };
+            let item = match item {
+                ClassItem::Range(lo, _) if self.peek() == Some('-')
+                    && self.chars.get(self.pos + 1).is_some_and(|c| *c != ']') => {
+                    self.pos += 1;
+                    let hi = self.next()?;
+                    if hi < lo {
+                        return Err(format!("invalid regex: bad class range {}-{}", lo, hi));
+                    }
+                    ClassItem::Range(lo, hi)
+                },
+                item => item,
+            };
+            items.push(item);
+        }
+        Ok(Node::Class(items, negated))
+    }
+
+    fn parse_quantifier(&mut self, atom: Node) -> Result<Node, String> {
+        let (min, max) = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => return self.parse_braces(atom),
+            _ => return Ok(atom),
+        };
+        self.pos += 1;
+        Ok(Node::Repeat(Box::new(atom), min, max))
+    }
+
+    fn parse_braces(&mut self, atom: Node) -> Result<Node, String> {
+        let close = self.chars[self.pos..].iter().position(|c| *c == '}')
+            .ok_or_else(|| String::from("invalid regex: unmatched '{'"))?;
+        let body: String = self.chars[self.pos + 1..self.pos + close].iter().collect();
+        let invalid = || format!("invalid regex: bad repetition '{{{}}}'", body);
+        let mut bounds = body.splitn(2, ',');
+        let min = bounds.next().unwrap().trim().parse::<usize>().map_err(|_| invalid())?;
+        let max = match bounds.next() {
+            None => Some(min),
+            Some(max) if max.trim().is_empty() => None,
+            Some(max) => Some(max.trim().parse::<usize>().map_err(|_| invalid())?),
+        };
+        if max.is_some_and(|max| max < min) {
+            return Err(invalid());
+        }
+        self.pos += close + 1;
+        Ok(Node::Repeat(Box::new(atom), min, max))
+    }
+}
+
+/// Matches `nodes` against `text` starting at `pos`, calling `cont` with the
+/// end position of each way the sequence can match until it returns true
+fn match_seq(nodes: &[Node], text: &[char], pos: usize, cont: &mut dyn FnMut(usize) -> bool) -> bool {
+    let (node, rest) = match nodes.split_first() {
+        Some(split) => split,
+        None => return cont(pos),
+    };
+    match *node {
+        Node::Any => pos < text.len() && match_seq(rest, text, pos + 1, cont),
+        Node::Class(ref items, negated) => {
+            pos < text.len()
+                && items.iter().any(|item| item.matches(text[pos])) != negated
+                && match_seq(rest, text, pos + 1, cont)
+        },
+        Node::Start => pos == 0 && match_seq(rest, text, pos, cont),
+        Node::End => pos == text.len() && match_seq(rest, text, pos, cont),
+        Node::Group(ref alternatives) => alternatives.iter().any(|alternative| {
+            match_seq(alternative, text, pos, &mut |end| match_seq(rest, text, end, cont))
+        }),
+        Node::Repeat(ref inner, min, max) => match_repeat(inner, min, max, 0, rest, text, pos, cont),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn match_repeat(inner: &Node, min: usize, max: Option<usize>, count: usize, rest: &[Node],
+                text: &[char], pos: usize, cont: &mut dyn FnMut(usize) -> bool) -> bool {
+    // Greedy: try one more repetition first, as long as it makes progress
+    if max.is_none_or(|max| count < max) {
+        let matched = match_seq(::std::slice::from_ref(inner), text, pos, &mut |end| {
+            if end == pos {
+                // An empty repetition can be repeated to satisfy any minimum
+                match_seq(rest, text, end, cont)
+            } else {
+                match_repeat(inner, min, max, count + 1, rest, text, end, cont)
+            }
+        });
+        if matched {
+            return true;
+        }
+    }
+    count >= min && match_seq(rest, text, pos, cont)
+}
@@ -0,0 +1,191 @@
+//! `--pid`: stopping following once the processes writing the files exit.
+//!
+//! On Linux 5.3 and later, each process is opened as a pidfd, which becomes
+//! readable when the process exits, so the follow loop can wait on it along
+//! with the files. Otherwise the processes are checked with `kill(PID, 0)`
+//! on every `--sleep-interval`. Elsewhere there's no way to check on them,
+//! so `--pid` is refused.
+
+use watch::RawFd;
+
+#[cfg(unix)]
+type Pid = libc::pid_t;
+
+#[cfg(not(unix))]
+type Pid = i32;
+
+/// `pidfd_open(2)`, which has the same number on every architecture
+#[cfg(target_os = "linux")]
+const SYS_PIDFD_OPEN: libc::c_long = 434;
+
+#[cfg(target_os = "linux")]
+fn pidfd_open(pid: Pid) -> Option<RawFd> {
+    let fd = unsafe { libc::syscall(SYS_PIDFD_OPEN, pid, 0) };
+    if fd >= 0 { Some(fd as RawFd) } else { None }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pidfd_open(_pid: Pid) -> Option<RawFd> {
+    None
+}
+
+/// Whether `fd`, a pidfd, is readable, which it is once its process has
+/// exited
+#[cfg(target_os = "linux")]
+fn pidfd_readable(fd: RawFd) -> bool {
+    let mut pfd = libc::pollfd { fd, events: libc::POLLIN, revents: 0 };
+    unsafe { libc::poll(&mut pfd, 1, 0) > 0 }
+}
+
+#[cfg(target_os = "linux")]
+fn close_pidfd(fd: RawFd) {
+    unsafe { libc::close(fd) };
+}
+
+/// `pidfd_open` never gives out a pidfd here
+#[cfg(not(target_os = "linux"))]
+fn pidfd_readable(_fd: RawFd) -> bool {
+    false
+}
+
+#[cfg(not(target_os = "linux"))]
+fn close_pidfd(_fd: RawFd) {}
+
+/// `--pid-mode`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PidMode {
+    /// Exit as soon as one of the processes has exited
+    Any,
+    /// Exit once every process has exited
+    All,
+}
+
+impl PidMode {
+    pub fn from_name(name: &str) -> Option<PidMode> {
+        match name {
+            "any" => Some(PidMode::Any),
+            "all" => Some(PidMode::All),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Process {
+    pid: Pid,
+    /// Closed once the process has exited
+    pidfd: Option<RawFd>,
+    exited: bool,
+}
+
+impl Process {
+    fn open(pid: Pid) -> Process {
+        Process { pid, pidfd: pidfd_open(pid), exited: false }
+    }
+
+    /// Notes the exit if the pidfd says so, as `kill` keeps succeeding until
+    /// the parent has reaped the process
+    fn has_exited(&mut self) -> bool {
+        if let Some(fd) = self.pidfd {
+            if pidfd_readable(fd) {
+                close_pidfd(fd);
+                self.pidfd = None;
+                self.exited = true;
+            }
+        }
+        self.exited || !is_alive(self.pid)
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        if let Some(fd) = self.pidfd {
+            close_pidfd(fd);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PidWatch {
+    processes: Vec<Process>,
+    mode: PidMode,
+}
+
+impl PidWatch {
+    /// Parses a `--pid` argument, a comma separated list of process IDs
+    pub fn parse(list: &str, mode: PidMode) -> Result<PidWatch, String> {
+        if cfg!(not(unix)) {
+            return Err(String::from("--pid is only supported on Unix"));
+        }
+        let pids = list.split(',')
+            .map(|pid| match pid.trim().parse::<Pid>() {
+                Ok(pid) if pid > 0 => Ok(pid),
+                _ => Err(format!("invalid PID: '{}'", pid)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(PidWatch { processes: pids.into_iter().map(Process::open).collect(), mode })
+    }
+
+    /// Whether the processes are gone, according to the mode
+    pub fn should_exit(&mut self) -> bool {
+        // Every process is checked, so no exited process's pidfd is left
+        // readable to wake the loop again
+        let exited: Vec<bool> = self.processes.iter_mut().map(Process::has_exited).collect();
+        match self.mode {
+            PidMode::Any => exited.iter().any(|exited| *exited),
+            PidMode::All => exited.iter().all(|exited| *exited),
+        }
+    }
+
+    /// The pidfds of the processes still running, which become readable when
+    /// they exit
+    pub fn wake_fds(&self) -> Vec<RawFd> {
+        self.processes.iter().filter_map(|process| process.pidfd).collect()
+    }
+
+    /// Whether some process has no pidfd, so it has to be checked on every
+    /// `--sleep-interval` instead
+    pub fn needs_polling(&self) -> bool {
+        self.processes.iter().any(|process| process.pidfd.is_none() && !process.exited)
+    }
+}
+
+/// Signal 0 only checks whether `pid` could be signalled. `EPERM` means the
+/// process exists but belongs to someone else.
+#[cfg(unix)]
+fn is_alive(pid: Pid) -> bool {
+    let signalled = unsafe { libc::kill(pid, 0) } == 0;
+    signalled || ::std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// `parse` refuses every list here
+#[cfg(not(unix))]
+fn is_alive(_pid: Pid) -> bool {
+    true
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    #[test]
+    fn parses_lists_of_pids() {
+        assert_eq!(PidWatch::parse("1, 2,3", PidMode::Any).unwrap().processes.len(), 3);
+        assert_eq!(PidWatch::parse("0", PidMode::Any).unwrap_err(), "invalid PID: '0'");
+        assert_eq!(PidWatch::parse("1,x", PidMode::Any).unwrap_err(), "invalid PID: 'x'");
+    }
+
+    #[test]
+    fn notices_a_process_exit() {
+        let mut child = Command::new("true").spawn().unwrap();
+        let running = ::std::process::id().to_string();
+        let list = format!("{},{}", running, child.id());
+        let mut any = PidWatch::parse(&list, PidMode::Any).unwrap();
+        let mut all = PidWatch::parse(&list, PidMode::All).unwrap();
+        child.wait().unwrap();
+        assert!(any.should_exit());
+        assert!(!all.should_exit());
+        assert!(!PidWatch::parse(&running, PidMode::Any).unwrap().should_exit());
+    }
+}
@@ -0,0 +1,56 @@
+//! `--threads`: worker threads that prepare each file's initial output, so
+//! slow reads of many files overlap while it's still written one file at a
+//! time, in order.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+/// Runs `work` on each job on a few threads, handing the results back by
+/// the index of their job
+pub struct OrderedPool<R> {
+    results: mpsc::Receiver<(usize, R)>,
+    /// Results that came in before they were asked for
+    ready: HashMap<usize, R>,
+}
+
+impl<R: Send + 'static> OrderedPool<R> {
+    pub fn new<T, F>(jobs: Vec<T>, threads: usize, work: F) -> OrderedPool<R>
+        where T: Send + 'static, F: Fn(T) -> R + Send + Sync + 'static
+    {
+        let queue = Arc::new(Mutex::new(jobs.into_iter().enumerate().collect::<VecDeque<_>>()));
+        let work = Arc::new(work);
+        let (sender, results) = mpsc::channel();
+        for _ in 0..threads {
+            let queue = Arc::clone(&queue);
+            let work = Arc::clone(&work);
+            let sender = sender.clone();
+            thread::spawn(move || loop {
+                let job = queue.lock().unwrap().pop_front();
+                let (index, job) = match job {
+                    Some(job) => job,
+                    None => return,
+                };
+                if sender.send((index, work(job))).is_err() {
+                    return;
+                }
+            });
+        }
+        OrderedPool { results, ready: HashMap::new() }
+    }
+
+    /// Waits for the result of job `index`. There's none if the job was
+    /// already taken, or the thread running it panicked.
+    pub fn take(&mut self, index: usize) -> Option<R> {
+        if let Some(result) = self.ready.remove(&index) {
+            return Some(result);
+        }
+        for (done, result) in self.results.iter() {
+            if done == index {
+                return Some(result);
+            }
+            self.ready.insert(done, result);
+        }
+        None
+    }
+}
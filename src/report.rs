@@ -0,0 +1,107 @@
+//! The `--error-report` summary of problems seen while tailing.
+
+use std::io::{self, Write};
+use std::time::SystemTime;
+use timestamp;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Problem {
+    /// The file couldn't be opened or read
+    Error,
+    /// The file shrank while it was being followed
+    Truncated,
+    /// The file was skipped because it can't be tailed the normal way
+    Inaccessible,
+}
+
+impl Problem {
+    fn describe(self) -> &'static str {
+        match self {
+            Problem::Error => "error",
+            Problem::Truncated => "truncated",
+            Problem::Inaccessible => "inaccessible",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    file_name: String,
+    problem: Problem,
+    count: usize,
+    first_seen: SystemTime,
+    last_seen: SystemTime,
+    last_detail: Option<String>,
+}
+
+/// Problems per file, in the order they were first seen
+#[derive(Debug, Default)]
+pub struct ErrorReport {
+    entries: Vec<Entry>,
+}
+
+impl ErrorReport {
+    pub fn record(&mut self, file_name: &str, problem: Problem, detail: Option<String>) {
+        let now = SystemTime::now();
+        let existing = self.entries.iter_mut()
+            .find(|e| e.file_name == file_name && e.problem == problem);
+        match existing {
+            Some(entry) => {
+                entry.count += 1;
+                entry.last_seen = now;
+                if detail.is_some() {
+                    entry.last_detail = detail;
+                }
+            },
+            None => self.entries.push(Entry {
+                file_name: file_name.to_string(),
+                problem,
+                count: 1,
+                first_seen: now,
+                last_seen: now,
+                last_detail: detail,
+            }),
+        }
+    }
+
+    /// Writes one line per file and problem, e.g.
+    /// `app.log: truncated 2 times, first 2024-01-01T12:00:00Z, last 2024-01-01T12:05:00Z`
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "tail: error report: {} problem(s)", self.entries.len())?;
+        for entry in &self.entries {
+            write!(writer, "{}: {} {} time{}, first {}, last {}",
+                   entry.file_name, entry.problem.describe(), entry.count,
+                   if entry.count == 1 { "" } else { "s" },
+                   timestamp::iso8601(entry.first_seen), timestamp::iso8601(entry.last_seen))?;
+            match entry.last_detail {
+                Some(ref detail) => writeln!(writer, ": {}", detail)?,
+                None => writeln!(writer)?,
+            }
+        }
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_each_problem_per_file_and_keeps_the_last_detail() {
+        let mut report = ErrorReport::default();
+        report.record("a.log", Problem::Truncated, None);
+        report.record("b.log", Problem::Error, Some(String::from("Permission denied")));
+        report.record("a.log", Problem::Truncated, None);
+        report.record("b.log", Problem::Error, None);
+        let mut out = Vec::new();
+        report.write_to(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 3, "{}", out);
+        assert_eq!(lines[0], "tail: error report: 2 problem(s)");
+        assert!(lines[1].starts_with("a.log: truncated 2 times, first "), "{}", out);
+        assert!(!lines[1].ends_with(": "), "{}", out);
+        assert!(lines[2].starts_with("b.log: error 2 times, first "), "{}", out);
+        assert!(lines[2].ends_with(": Permission denied"), "{}", out);
+    }
+}
@@ -0,0 +1,108 @@
+//! `--route`: sending followed lines to different files by regex.
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use pattern::Regex;
+use tail::io_error_message;
+
+#[derive(Debug)]
+struct Route {
+    regex: Regex,
+    path: String,
+}
+
+/// Routes are tried in the order they were given, and the first regex that
+/// matches a line picks its file. Files are opened for appending the first
+/// time a line is sent to them.
+#[derive(Debug, Default)]
+pub struct Router {
+    routes: Vec<Route>,
+    default_path: Option<String>,
+    sinks: HashMap<String, File>,
+}
+
+impl Router {
+    /// Adds a route from a `REGEX:PATH` argument. The last `:` separates the
+    /// two, so the regex itself may contain colons.
+    pub fn add_route(&mut self, spec: &str) -> Result<(), String> {
+        let split = spec.rfind(':')
+            .ok_or_else(|| format!("invalid route '{}': expected REGEX:PATH", spec))?;
+        let (regex, path) = (&spec[..split], &spec[split + 1..]);
+        if path.is_empty() {
+            return Err(format!("invalid route '{}': missing file name", spec));
+        }
+        let regex = Regex::new(regex).map_err(|e| format!("invalid route '{}': {}", spec, e))?;
+        self.routes.push(Route { regex, path: path.to_string() });
+        Ok(())
+    }
+
+    /// Where lines that match no route go, instead of stdout
+    pub fn set_default(&mut self, path: String) {
+        self.default_path = Some(path);
+    }
+
+    /// Writes `line` to the file picked by matching `text`, its decoded form.
+    /// Returns false if the line matched no route and there's no default, so
+    /// the caller should print it itself.
+    pub fn write_line(&mut self, line: &[u8], text: &str) -> io::Result<bool> {
+        let text = text.trim_end_matches(['\n', '\r']);
+        let path = match self.routes.iter().find(|route| route.regex.is_match(text)) {
+            Some(route) => &route.path,
+            None => match self.default_path {
+                Some(ref path) => path,
+                None => return Ok(false),
+            },
+        };
+        let with_path = |e: io::Error| io::Error::new(e.kind(), format!("{}: {}", path, io_error_message(&e)));
+        if !self.sinks.contains_key(path) {
+            let sink = OpenOptions::new().create(true).append(true).open(path).map_err(with_path)?;
+            self.sinks.insert(path.clone(), sink);
+        }
+        self.sinks.get_mut(path).unwrap().write_all(line).map_err(with_path)?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn route(router: &mut Router, line: &str) -> bool {
+        router.write_line(line.as_bytes(), line).unwrap()
+    }
+
+    #[test]
+    fn the_first_matching_route_wins_and_the_rest_fall_through() {
+        let dir = std::env::temp_dir().join(format!("tail-route-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let errors = dir.join("errors.log").to_str().unwrap().to_string();
+        let all = dir.join("all.log").to_str().unwrap().to_string();
+        let other = dir.join("other.log").to_str().unwrap().to_string();
+
+        let mut router = Router::default();
+        router.add_route(&format!("^ERROR:{}", errors)).unwrap();
+        router.add_route(&format!(".:{}", all)).unwrap();
+        assert!(route(&mut router, "ERROR one\n"));
+        assert!(route(&mut router, "info\n"));
+        assert!(!route(&mut router, "\n"));
+        router.set_default(other.clone());
+        assert!(route(&mut router, "\n"));
+        assert_eq!(fs::read_to_string(&errors).unwrap(), "ERROR one\n");
+        assert_eq!(fs::read_to_string(&all).unwrap(), "info\n");
+        assert_eq!(fs::read_to_string(&other).unwrap(), "\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn the_last_colon_separates_the_path() {
+        let mut router = Router::default();
+        router.add_route("a:b:out.log").unwrap();
+        assert_eq!(router.routes[0].path, "out.log");
+        assert!(router.add_route("no-path:").is_err());
+        assert!(router.add_route("no-colon").is_err());
+        assert!(router.add_route("(:out.log").is_err());
+    }
+}
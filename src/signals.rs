@@ -0,0 +1,185 @@
+//! Signal handlers that only set flags for the follow loop to act on.
+//!
+//! Each handler also writes a byte to a pipe that the loop waits on along
+//! with the files, so a signal that arrives just before the loop starts
+//! waiting still wakes it, rather than waiting for the next event.
+//!
+//! Outside Unix, none are handled: the handlers aren't installed, and the
+//! signals do what they do by default there.
+
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+#[cfg(unix)]
+use libc;
+use watch::RawFd;
+
+static STATUS_REQUESTED: AtomicBool = AtomicBool::new(false);
+static STOP_REQUESTED: AtomicBool = AtomicBool::new(false);
+static REPRINT_REQUESTED: AtomicBool = AtomicBool::new(false);
+/// The ends of the wakeup pipe, or -1 before it's opened
+static WAKEUP_READ: AtomicI32 = AtomicI32::new(-1);
+#[cfg(unix)]
+static WAKEUP_WRITE: AtomicI32 = AtomicI32::new(-1);
+
+#[cfg(unix)]
+extern "C" fn request_status(_: libc::c_int) {
+    STATUS_REQUESTED.store(true, Ordering::SeqCst);
+    wake();
+}
+
+#[cfg(unix)]
+extern "C" fn request_reprint(_: libc::c_int) {
+    REPRINT_REQUESTED.store(true, Ordering::SeqCst);
+    wake();
+}
+
+#[cfg(unix)]
+extern "C" fn request_stop(_: libc::c_int) {
+    STOP_REQUESTED.store(true, Ordering::SeqCst);
+    wake();
+}
+
+/// Only does what's safe in a signal handler. If the pipe is full, the loop
+/// has plenty of wakeups waiting already.
+#[cfg(unix)]
+fn wake() {
+    let fd = WAKEUP_WRITE.load(Ordering::SeqCst);
+    if fd >= 0 {
+        unsafe {
+            libc::write(fd, b"!".as_ptr() as *const libc::c_void, 1);
+        }
+    }
+}
+
+/// Opens the wakeup pipe, if it isn't open yet. Without it, signals still
+/// interrupt the wait, just not one that hasn't started yet.
+#[cfg(unix)]
+fn open_wakeup_pipe() {
+    if WAKEUP_READ.load(Ordering::SeqCst) >= 0 {
+        return;
+    }
+    if let Some([read, write]) = nonblocking_pipe() {
+        WAKEUP_READ.store(read, Ordering::SeqCst);
+        WAKEUP_WRITE.store(write, Ordering::SeqCst);
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn nonblocking_pipe() -> Option<[RawFd; 2]> {
+    let mut fds = [-1; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) } == 0 {
+        Some(fds)
+    } else {
+        None
+    }
+}
+
+/// Without `pipe2`, the flags are set one at a time. Nothing else runs yet
+/// that could inherit the descriptors in between.
+#[cfg(all(unix, not(target_os = "linux")))]
+fn nonblocking_pipe() -> Option<[RawFd; 2]> {
+    let mut fds = [-1; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        return None;
+    }
+    for &fd in &fds {
+        let set = unsafe {
+            libc::fcntl(fd, libc::F_SETFL, libc::fcntl(fd, libc::F_GETFL) | libc::O_NONBLOCK) == 0
+                && libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) == 0
+        };
+        if !set {
+            unsafe {
+                libc::close(fds[0]);
+                libc::close(fds[1]);
+            }
+            return None;
+        }
+    }
+    Some(fds)
+}
+
+/// What becomes readable when a handled signal arrives, for the follow loop
+/// to wait on
+pub fn wakeup_fd() -> Option<RawFd> {
+    let fd = WAKEUP_READ.load(Ordering::SeqCst);
+    if fd >= 0 { Some(fd) } else { None }
+}
+
+/// Empties the wakeup pipe. Done before the flags are checked, so a signal
+/// that comes after the check leaves the pipe readable for the next wait.
+#[cfg(unix)]
+pub fn drain_wakeups() {
+    if let Some(fd) = wakeup_fd() {
+        let mut buff = [0u8; 64];
+        while unsafe { libc::read(fd, buff.as_mut_ptr() as *mut libc::c_void, buff.len()) } > 0 {}
+    }
+}
+
+/// Lets SIGPIPE end the process quietly, as it does for other command line
+/// tools, when whatever reads the output goes away, e.g. `tail big.log | head`.
+/// Rust ignores it by default, which turns every later write into an error.
+#[cfg(unix)]
+pub fn restore_sigpipe() {
+    unsafe {
+        libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+    }
+}
+
+/// Makes SIGUSR1 request a status line instead of killing the process
+#[cfg(unix)]
+pub fn install_status_handler() {
+    open_wakeup_pipe();
+    unsafe {
+        libc::signal(libc::SIGUSR1, request_status as extern "C" fn(libc::c_int) as libc::sighandler_t);
+    }
+}
+
+/// Returns whether SIGUSR1 arrived since the last call
+pub fn take_status_request() -> bool {
+    STATUS_REQUESTED.swap(false, Ordering::SeqCst)
+}
+
+/// Makes SIGUSR2 request the last lines of each file again, SIGUSR1 being
+/// taken by the status line
+#[cfg(unix)]
+pub fn install_reprint_handler() {
+    open_wakeup_pipe();
+    unsafe {
+        libc::signal(libc::SIGUSR2, request_reprint as extern "C" fn(libc::c_int) as libc::sighandler_t);
+    }
+}
+
+/// Returns whether SIGUSR2 arrived since the last call
+pub fn take_reprint_request() -> bool {
+    REPRINT_REQUESTED.swap(false, Ordering::SeqCst)
+}
+
+/// Makes SIGINT and SIGTERM ask the follow loop to stop, so it can finish the
+/// line it's writing and exit cleanly
+#[cfg(unix)]
+pub fn install_stop_handler() {
+    open_wakeup_pipe();
+    unsafe {
+        libc::signal(libc::SIGINT, request_stop as extern "C" fn(libc::c_int) as libc::sighandler_t);
+        libc::signal(libc::SIGTERM, request_stop as extern "C" fn(libc::c_int) as libc::sighandler_t);
+    }
+}
+
+/// Returns whether SIGINT or SIGTERM has arrived
+pub fn stop_requested() -> bool {
+    STOP_REQUESTED.load(Ordering::SeqCst)
+}
+
+#[cfg(not(unix))]
+pub fn drain_wakeups() {}
+
+#[cfg(not(unix))]
+pub fn restore_sigpipe() {}
+
+#[cfg(not(unix))]
+pub fn install_status_handler() {}
+
+#[cfg(not(unix))]
+pub fn install_reprint_handler() {}
+
+#[cfg(not(unix))]
+pub fn install_stop_handler() {}
@@ -0,0 +1,119 @@
+//! `--state-file`: remembering how far each file was read, so a restarted
+//! tail carries on where the last one stopped.
+//!
+//! Each line of the file is `INODE OFFSET PATH`. The path comes last so it may
+//! contain spaces. Where files have no inodes, the inode is always 0.
+
+use std::collections::HashMap;
+use std::fs::{self, File, Metadata};
+use std::io::{self, Read, Write};
+use tail::inode_of;
+use tail::io_error_message;
+
+#[derive(Debug)]
+pub struct StateFile {
+    path: String,
+    /// Inode and offset by file name
+    offsets: HashMap<String, (u64, u64)>,
+}
+
+impl StateFile {
+    /// No saved state, to be saved in `path`
+    pub fn empty(path: &str) -> StateFile {
+        StateFile { path: path.to_string(), offsets: HashMap::new() }
+    }
+
+    /// Loads the state saved in `path`. A missing file is an empty state, as
+    /// on the first run.
+    pub fn load(path: &str) -> Result<StateFile, String> {
+        let mut state = StateFile::empty(path);
+        let mut contents = String::new();
+        match File::open(path).and_then(|mut fd| fd.read_to_string(&mut contents)) {
+            Ok(_) => {},
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(state),
+            Err(e) => return Err(format!("cannot read state file '{}': {}", path, io_error_message(&e))),
+        }
+        for (i, line) in contents.lines().enumerate() {
+            let mut fields = line.splitn(3, ' ');
+            let parsed = match (fields.next(), fields.next(), fields.next()) {
+                (Some(inode), Some(offset), Some(name)) => inode.parse::<u64>().ok()
+                    .and_then(|inode| offset.parse::<u64>().ok().map(|offset| (name, inode, offset))),
+                _ => None,
+            };
+            let (name, inode, offset) = parsed
+                .ok_or_else(|| format!("{}:{}: expected 'INODE OFFSET PATH', found '{}'", path, i + 1, line))?;
+            state.offsets.insert(name.to_string(), (inode, offset));
+        }
+        Ok(state)
+    }
+
+    /// Where to resume `file_name` from, if it's still the file that was
+    /// saved and hasn't shrunk below the saved offset since
+    pub fn resume_offset(&self, file_name: &str, metadata: &Metadata) -> Option<u64> {
+        match self.offsets.get(file_name) {
+            Some(&(inode, offset)) if inode == inode_of(metadata) && offset <= metadata.len() => Some(offset),
+            _ => None,
+        }
+    }
+
+    pub fn update(&mut self, file_name: &str, metadata: &Metadata, offset: u64) {
+        self.offsets.insert(file_name.to_string(), (inode_of(metadata), offset));
+    }
+
+    /// Writes the state out. It's written to a temporary file that's renamed
+    /// over the old one, so a crash part way leaves the previous state intact.
+    pub fn save(&self) -> io::Result<()> {
+        let mut names: Vec<&String> = self.offsets.keys().collect();
+        names.sort();
+        let temp_path = format!("{}.tmp", self.path);
+        let mut temp = File::create(&temp_path)?;
+        for name in names {
+            let (inode, offset) = self.offsets[name];
+            writeln!(temp, "{} {} {}", inode, offset, name)?;
+        }
+        fs::rename(&temp_path, &self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir().join(format!("tail-state-{}-{}", std::process::id(), name)).to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn saved_offsets_are_resumed_while_the_file_is_the_same() {
+        let log = temp_path("a log.txt");
+        fs::write(&log, b"0123456789").unwrap();
+        let path = temp_path("round-trip.state");
+        let mut state = StateFile::empty(&path);
+        state.update(&log, &fs::metadata(&log).unwrap(), 4);
+        state.save().unwrap();
+
+        let state = StateFile::load(&path).unwrap();
+        assert_eq!(state.resume_offset(&log, &fs::metadata(&log).unwrap()), Some(4));
+        assert_eq!(state.resume_offset("other.log", &fs::metadata(&log).unwrap()), None);
+        // Truncated below the offset
+        fs::write(&log, b"012").unwrap();
+        assert_eq!(state.resume_offset(&log, &fs::metadata(&log).unwrap()), None);
+        // Replaced by another file
+        let replacement = temp_path("replacement.txt");
+        fs::write(&replacement, b"0123456789").unwrap();
+        fs::rename(&replacement, &log).unwrap();
+        assert_eq!(state.resume_offset(&log, &fs::metadata(&log).unwrap()), None);
+        fs::remove_file(&log).unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn a_missing_state_file_is_empty_and_a_malformed_one_is_an_error() {
+        let path = temp_path("missing.state");
+        assert!(StateFile::load(&path).unwrap().offsets.is_empty());
+        fs::write(&path, b"1 2 ok.log\n12 x bad.log\n").unwrap();
+        assert_eq!(StateFile::load(&path).unwrap_err(),
+                   format!("{}:2: expected 'INODE OFFSET PATH', found '12 x bad.log'", path));
+        fs::remove_file(&path).unwrap();
+    }
+}
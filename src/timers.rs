@@ -0,0 +1,142 @@
+//! The deadlines of the follow loop: `--timeout`, `--exit-on-idle`, and the
+//! `--pid` checks every `--sleep-interval`, all told by one `Clock`.
+
+use std::time::{Duration, Instant};
+use tail::clock::Clock;
+
+pub struct Timers<'a> {
+    clock: &'a dyn Clock,
+    /// `--exit-on-idle`
+    exit_on_idle: Option<Duration>,
+    /// When `--timeout` runs out
+    deadline: Option<Instant>,
+    last_growth: Instant,
+    sleep_interval: Duration,
+    next_pid_check: Instant,
+}
+
+impl<'a> Timers<'a> {
+    pub fn new(clock: &'a dyn Clock, timeout: Option<Duration>, exit_on_idle: Option<Duration>,
+               sleep_interval: Duration) -> Timers<'a> {
+        let now = clock.now();
+        Timers {
+            clock,
+            exit_on_idle,
+            deadline: timeout.map(|timeout| now + timeout),
+            last_growth: now,
+            sleep_interval,
+            // The first check is straight away, so a PID that never existed
+            // doesn't keep tail following
+            next_pid_check: now,
+        }
+    }
+
+    pub fn clock(&self) -> &'a dyn Clock {
+        self.clock
+    }
+
+    /// Something grew, so the idle time starts over
+    pub fn grew(&mut self) {
+        self.last_growth = self.clock.now();
+    }
+
+    /// Whether the PIDs are due a check, and if so, sets the next one for
+    /// an interval from now
+    pub fn take_pid_check(&mut self) -> bool {
+        let now = self.clock.now();
+        let due = now >= self.next_pid_check;
+        if due {
+            self.next_pid_check = now + self.sleep_interval;
+        }
+        due
+    }
+
+    /// Checks the PIDs on the next round, e.g. once a signal has come in
+    pub fn check_pids_now(&mut self) {
+        self.next_pid_check = self.clock.now();
+    }
+
+    pub fn until_pid_check(&self) -> Duration {
+        self.next_pid_check.saturating_duration_since(self.clock.now())
+    }
+
+    /// How long until whichever of the idle and overall deadlines comes
+    /// first, if there are any
+    pub fn until_exit(&self) -> Option<Duration> {
+        let now = self.clock.now();
+        self.idle_deadline().into_iter().chain(self.deadline)
+            .map(|until| until.saturating_duration_since(now))
+            .min()
+    }
+
+    /// Whether following should stop because nothing grew for
+    /// `--exit-on-idle`
+    pub fn idled_out(&self) -> bool {
+        self.idle_deadline().is_some_and(|until| self.clock.now() >= until)
+    }
+
+    fn idle_deadline(&self) -> Option<Instant> {
+        self.exit_on_idle.map(|idle| self.last_growth + idle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tail::clock::MockClock;
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn growth_puts_off_idling_out() {
+        let clock = MockClock::new();
+        let mut timers = Timers::new(&clock, None, Some(secs(5)), secs(1));
+        clock.advance(secs(3));
+        assert_eq!(timers.until_exit(), Some(secs(2)));
+        timers.grew();
+        clock.advance(secs(3));
+        assert!(!timers.idled_out());
+        assert_eq!(timers.until_exit(), Some(secs(2)));
+        clock.advance(secs(2));
+        assert!(timers.idled_out());
+        assert_eq!(timers.until_exit(), Some(secs(0)));
+    }
+
+    #[test]
+    fn timeout_comes_regardless_of_growth() {
+        let clock = MockClock::new();
+        let mut timers = Timers::new(&clock, Some(secs(4)), Some(secs(10)), secs(1));
+        clock.advance(secs(3));
+        timers.grew();
+        assert_eq!(timers.until_exit(), Some(secs(1)));
+        clock.sleep(secs(1));
+        assert_eq!(timers.until_exit(), Some(secs(0)));
+        assert!(!timers.idled_out());
+    }
+
+    #[test]
+    fn no_deadlines_without_timeout_or_idle() {
+        let clock = MockClock::new();
+        let timers = Timers::new(&clock, None, None, secs(1));
+        clock.advance(secs(1000));
+        assert_eq!(timers.until_exit(), None);
+        assert!(!timers.idled_out());
+    }
+
+    #[test]
+    fn pids_are_checked_every_interval() {
+        let clock = MockClock::new();
+        let mut timers = Timers::new(&clock, None, None, secs(2));
+        assert!(timers.take_pid_check());
+        assert!(!timers.take_pid_check());
+        clock.advance(secs(1));
+        assert_eq!(timers.until_pid_check(), secs(1));
+        assert!(!timers.take_pid_check());
+        clock.advance(secs(1));
+        assert!(timers.take_pid_check());
+        timers.check_pids_now();
+        assert!(timers.take_pid_check());
+    }
+}
@@ -0,0 +1,860 @@
+//! Waiting for followed files to change.
+//!
+//! The follow loop only talks to a `FileWatcher`, so how changes are noticed
+//! depends on the platform. On Linux that's inotify, and on macOS, FreeBSD
+//! and NetBSD it's kqueue. Elsewhere, with `--poll`, or for files the native
+//! watcher can't watch (e.g. on some network and FUSE file systems), the
+//! files are polled, checking their size, modification time and inode every
+//! interval.
+
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::fs::{self, File, Metadata};
+use std::io;
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+#[cfg(unix)]
+use libc;
+use tail::clock::Clock;
+
+#[cfg(unix)]
+pub use std::os::unix::io::RawFd;
+
+/// Outside Unix there are no descriptors to wait on besides the files, so the
+/// lists of them are always empty
+#[cfg(not(unix))]
+pub type RawFd = i32;
+
+/// Identifies a watch, so the events for it can be matched to its file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WatchId(u64);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    /// The file was written to or truncated
+    Modified,
+    /// The file was renamed, deleted or had its attributes changed, so its
+    /// name may lead to another file now
+    NameChanged,
+    /// A file was created in or moved to a directory watched with
+    /// `watch_dir`
+    Created,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Event {
+    pub id: WatchId,
+    pub kind: EventKind,
+}
+
+pub enum WaitResult {
+    Ready,
+    TimedOut,
+    /// A signal arrived, or one of the other descriptors waited on became
+    /// readable, so whatever they stand for should be checked
+    Interrupted,
+}
+
+pub trait FileWatcher {
+    /// Starts watching `path`. Watching the same file twice gives the same
+    /// ID.
+    fn watch(&mut self, path: &Path) -> io::Result<WatchId>;
+
+    /// Starts watching the directory `dir` for files created in it or moved
+    /// to it
+    fn watch_dir(&mut self, dir: &Path) -> io::Result<WatchId>;
+
+    /// Stops watching. The watch may already be gone, e.g. because its file
+    /// was deleted, which isn't an error.
+    fn unwatch(&mut self, id: WatchId);
+
+    /// Waits until there are events to read, until `timeout` passes, until
+    /// a signal arrives, or until one of `wake_fds` is readable, e.g. the
+    /// pipe signal handlers write to. A watcher that only has to sleep
+    /// sleeps by `clock`.
+    fn wait(&mut self, timeout: Option<Duration>, wake_fds: &[RawFd], clock: &dyn Clock) -> io::Result<WaitResult>;
+
+    /// The events that have come in since the last call, which may be none
+    fn next_events(&mut self) -> io::Result<Vec<Event>>;
+}
+
+/// The best watcher for this platform, or a `PollWatcher` checking every
+/// `interval` if `poll` is set. `name_changes` asks for
+/// `EventKind::NameChanged` events as well, for `--follow=name`.
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "freebsd", target_os = "netbsd"))]
+pub fn new_watcher(name_changes: bool, interval: Duration, poll: bool) -> Box<dyn FileWatcher> {
+    if poll {
+        return Box::new(PollWatcher::new(interval));
+    }
+    match NativeWatcher::new(name_changes) {
+        Ok(native) => Box::new(Fallback::new(native, interval)),
+        Err(_) => {
+            note_polling();
+            Box::new(PollWatcher::new(interval))
+        },
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "freebsd", target_os = "netbsd")))]
+pub fn new_watcher(_name_changes: bool, interval: Duration, _poll: bool) -> Box<dyn FileWatcher> {
+    Box::new(PollWatcher::new(interval))
+}
+
+/// Waits until `fd` or one of `wake_fds` is readable, or until `timeout`
+/// passes. Only `fd` being readable is `Ready`; the rest interrupt the wait.
+#[cfg(unix)]
+fn wait_readable(fd: Option<RawFd>, wake_fds: &[RawFd], timeout: Option<Duration>) -> io::Result<WaitResult> {
+    let mut pfds: Vec<libc::pollfd> = fd.into_iter().chain(wake_fds.iter().cloned())
+        .map(|fd| libc::pollfd { fd, events: libc::POLLIN, revents: 0 })
+        .collect();
+    // Round up, so the wait never ends just short of a deadline
+    let timeout_ms = timeout
+        .map(|d| d.as_nanos().div_ceil(1_000_000).min(libc::c_int::MAX as u128) as libc::c_int)
+        .unwrap_or(-1);
+    match unsafe { libc::poll(pfds.as_mut_ptr(), pfds.len() as libc::nfds_t, timeout_ms) } {
+        -1 => {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted { Ok(WaitResult::Interrupted) } else { Err(err) }
+        },
+        0 => Ok(WaitResult::TimedOut),
+        _ => {
+            let woken = pfds[fd.iter().count()..].iter().any(|pfd| pfd.revents != 0);
+            Ok(if woken { WaitResult::Interrupted } else { WaitResult::Ready })
+        },
+    }
+}
+
+/// Without poll(2), and with no descriptors handed out to wait on, there's
+/// only the timeout to wait for
+#[cfg(not(unix))]
+fn wait_readable(_fd: Option<RawFd>, _wake_fds: &[RawFd], timeout: Option<Duration>) -> io::Result<WaitResult> {
+    if let Some(timeout) = timeout {
+        ::std::thread::sleep(timeout);
+    }
+    Ok(WaitResult::TimedOut)
+}
+
+/// Tells files apart, so a file watched under two names is watched once and
+/// a name that leads to another file is noticed
+#[cfg(unix)]
+type FileId = (u64, u64);
+
+#[cfg(unix)]
+fn file_id(_path: &Path, metadata: &Metadata) -> FileId {
+    (metadata.dev(), metadata.ino())
+}
+
+/// Without inodes, files are told apart by their canonical path. A file
+/// replaced under the same name is then only noticed once the name leads
+/// nowhere for a check.
+#[cfg(not(unix))]
+type FileId = PathBuf;
+
+#[cfg(not(unix))]
+fn file_id(path: &Path, _metadata: &Metadata) -> FileId {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(target_os = "linux")]
+type NativeWatcher = InotifyWatcher;
+
+#[cfg(any(target_os = "macos", target_os = "freebsd", target_os = "netbsd"))]
+type NativeWatcher = KqueueWatcher;
+
+#[cfg(target_os = "linux")]
+const NATIVE_NAME: &str = "inotify";
+
+#[cfg(any(target_os = "macos", target_os = "freebsd", target_os = "netbsd"))]
+const NATIVE_NAME: &str = "kqueue";
+
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "freebsd", target_os = "netbsd"))]
+fn note_polling() {
+    eprintln!("tail: {} cannot be used, reverting to polling", NATIVE_NAME);
+}
+
+/// Watches with `primary` where it can, and polls the files it can't watch
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "freebsd", target_os = "netbsd"))]
+struct Fallback<W> {
+    primary: W,
+    poll: PollWatcher,
+    /// Which watcher each ID handed out belongs to, and its own ID there
+    ids: HashMap<WatchId, (bool, WatchId)>,
+    next_id: u64,
+    noted: bool,
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "freebsd", target_os = "netbsd"))]
+impl<W: FileWatcher> Fallback<W> {
+    fn new(primary: W, interval: Duration) -> Fallback<W> {
+        Fallback { primary, poll: PollWatcher::new(interval), ids: HashMap::new(), next_id: 0, noted: false }
+    }
+
+    fn id_for(&mut self, polled: bool, inner: WatchId) -> WatchId {
+        if let Some((id, _)) = self.ids.iter().find(|&(_, watch)| *watch == (polled, inner)) {
+            return *id;
+        }
+        self.next_id += 1;
+        let id = WatchId(self.next_id);
+        self.ids.insert(id, (polled, inner));
+        id
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "freebsd", target_os = "netbsd"))]
+impl<W: FileWatcher> FileWatcher for Fallback<W> {
+    fn watch(&mut self, path: &Path) -> io::Result<WatchId> {
+        if let Ok(inner) = self.primary.watch(path) {
+            return Ok(self.id_for(false, inner));
+        }
+        // If polling can't open it either, that error is the one that
+        // explains why
+        let inner = self.poll.watch(path)?;
+        if !self.noted {
+            self.noted = true;
+            note_polling();
+        }
+        Ok(self.id_for(true, inner))
+    }
+
+    fn watch_dir(&mut self, dir: &Path) -> io::Result<WatchId> {
+        if let Ok(inner) = self.primary.watch_dir(dir) {
+            return Ok(self.id_for(false, inner));
+        }
+        let inner = self.poll.watch_dir(dir)?;
+        if !self.noted {
+            self.noted = true;
+            note_polling();
+        }
+        Ok(self.id_for(true, inner))
+    }
+
+    fn unwatch(&mut self, id: WatchId) {
+        match self.ids.remove(&id) {
+            Some((false, inner)) => self.primary.unwatch(inner),
+            Some((true, inner)) => self.poll.unwatch(inner),
+            None => {},
+        }
+    }
+
+    fn wait(&mut self, timeout: Option<Duration>, wake_fds: &[RawFd], clock: &dyn Clock) -> io::Result<WaitResult> {
+        if self.poll.is_empty() {
+            return self.primary.wait(timeout, wake_fds, clock);
+        }
+        // Wake up in time to poll
+        let interval = self.poll.interval;
+        let result = self.primary.wait(Some(timeout.map_or(interval, |timeout| timeout.min(interval))), wake_fds, clock)?;
+        self.poll.check();
+        Ok(match result {
+            WaitResult::TimedOut if !self.poll.events.is_empty() => WaitResult::Ready,
+            result => result,
+        })
+    }
+
+    fn next_events(&mut self) -> io::Result<Vec<Event>> {
+        let mut events: Vec<(bool, Event)> = self.primary.next_events()?.into_iter().map(|event| (false, event))
+            .chain(self.poll.next_events()?.into_iter().map(|event| (true, event)))
+            .collect();
+        events.retain(|&(polled, event)| self.ids.values().any(|watch| *watch == (polled, event.id)));
+        Ok(events.into_iter()
+            .map(|(polled, event)| Event { id: self.id_for(polled, event.id), kind: event.kind })
+            .collect())
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub use self::linux::InotifyWatcher;
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::collections::HashMap;
+    use std::ffi::OsString;
+    use std::io;
+    use std::os::unix::io::{AsRawFd, RawFd};
+    use std::path::Path;
+    use std::time::Duration;
+    use inotify::{Inotify, WatchDescriptor, WatchMask, EventMask};
+    use tail::clock::Clock;
+    use super::{wait_readable, Event, EventKind, FileWatcher, WaitResult, WatchId};
+
+    pub struct InotifyWatcher {
+        inotify: Inotify,
+        mask: WatchMask,
+        ids: HashMap<WatchDescriptor, WatchId>,
+        /// With `name_changes`, the directories the files are in, and which
+        /// of their names are watched, to hear when a new file takes a
+        /// watched name straight away
+        dirs: HashMap<WatchDescriptor, Vec<(OsString, WatchId)>>,
+        /// The directories watched with `watch_dir`, for any file created
+        created: HashMap<WatchDescriptor, WatchId>,
+        next_id: u64,
+        buffer: [u8; 4096],
+    }
+
+    impl InotifyWatcher {
+        pub fn new(name_changes: bool) -> io::Result<InotifyWatcher> {
+            let mask = if name_changes {
+                WatchMask::MODIFY | WatchMask::MOVE_SELF | WatchMask::DELETE_SELF | WatchMask::ATTRIB
+            } else {
+                WatchMask::MODIFY
+            };
+            Ok(InotifyWatcher {
+                inotify: Inotify::init()?,
+                mask,
+                ids: HashMap::new(),
+                dirs: HashMap::new(),
+                created: HashMap::new(),
+                next_id: 0,
+                buffer: [0; 4096],
+            })
+        }
+
+        fn new_id(&mut self) -> WatchId {
+            self.next_id += 1;
+            WatchId(self.next_id)
+        }
+
+        fn name_changes(&self) -> bool {
+            self.mask.contains(WatchMask::MOVE_SELF)
+        }
+
+        /// Watches the directory `path` is in for files created or moved to
+        /// its name. Without it, a name that a file was moved away from would
+        /// only be checked again every interval.
+        fn watch_name(&mut self, path: &Path, id: WatchId) {
+            let name = match path.file_name() {
+                Some(name) => name.to_os_string(),
+                None => return,
+            };
+            let dir = match path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            };
+            // The file is still watched without it, just not as promptly
+            if let Ok(wd) = self.inotify.add_watch(dir, WatchMask::CREATE | WatchMask::MOVED_TO) {
+                let names = self.dirs.entry(wd).or_default();
+                if !names.iter().any(|&(ref watched, watched_id)| *watched == name && watched_id == id) {
+                    names.push((name, id));
+                }
+            }
+        }
+    }
+
+    impl FileWatcher for InotifyWatcher {
+        fn watch(&mut self, path: &Path) -> io::Result<WatchId> {
+            let wd = self.inotify.add_watch(path, self.mask)?;
+            let next_id = &mut self.next_id;
+            let id = *self.ids.entry(wd).or_insert_with(|| {
+                *next_id += 1;
+                WatchId(*next_id)
+            });
+            if self.name_changes() {
+                self.watch_name(path, id);
+            }
+            Ok(id)
+        }
+
+        fn watch_dir(&mut self, dir: &Path) -> io::Result<WatchId> {
+            let wd = self.inotify.add_watch(dir, WatchMask::CREATE | WatchMask::MOVED_TO | WatchMask::ONLYDIR)?;
+            if let Some(id) = self.created.get(&wd) {
+                return Ok(*id);
+            }
+            let id = self.new_id();
+            self.created.insert(wd, id);
+            Ok(id)
+        }
+
+        fn unwatch(&mut self, id: WatchId) {
+            let wd = self.ids.iter().find(|&(_, watch_id)| *watch_id == id).map(|(wd, _)| wd.clone());
+            if let Some(wd) = wd {
+                self.ids.remove(&wd);
+                let _ = self.inotify.rm_watch(wd);
+            }
+            let wd = self.created.iter().find(|&(_, watch_id)| *watch_id == id).map(|(wd, _)| wd.clone());
+            if let Some(wd) = wd {
+                self.created.remove(&wd);
+                if !self.dirs.contains_key(&wd) {
+                    let _ = self.inotify.rm_watch(wd);
+                }
+            }
+            let mut unused = Vec::new();
+            for (wd, names) in &mut self.dirs {
+                names.retain(|&(_, watched_id)| watched_id != id);
+                if names.is_empty() {
+                    unused.push(wd.clone());
+                }
+            }
+            for wd in unused {
+                self.dirs.remove(&wd);
+                // The same directory may be watched for any file as well
+                if !self.created.contains_key(&wd) {
+                    let _ = self.inotify.rm_watch(wd);
+                }
+            }
+        }
+
+        fn wait(&mut self, timeout: Option<Duration>, wake_fds: &[RawFd], _clock: &dyn Clock) -> io::Result<WaitResult> {
+            wait_readable(Some(self.inotify.as_raw_fd()), wake_fds, timeout)
+        }
+
+        fn next_events(&mut self) -> io::Result<Vec<Event>> {
+            let mut events = Vec::new();
+            let mut overflowed = false;
+            for event in self.inotify.read_events(&mut self.buffer)? {
+                if event.mask.contains(EventMask::Q_OVERFLOW) {
+                    overflowed = true;
+                    continue;
+                }
+                let named = self.dirs.get(&event.wd);
+                let created = self.created.get(&event.wd);
+                if named.is_some() || created.is_some() {
+                    if event.mask.intersects(EventMask::CREATE | EventMask::MOVED_TO) {
+                        // A file was created or moved to a watched name
+                        events.extend(named.into_iter().flatten()
+                            .filter(|&(name, _)| event.name == Some(name.as_os_str()))
+                            .map(|&(_, id)| Event { id, kind: EventKind::NameChanged }));
+                        events.extend(created.map(|id| Event { id: *id, kind: EventKind::Created }));
+                    }
+                    if event.mask.contains(EventMask::IGNORED) {
+                        self.dirs.remove(&event.wd);
+                        self.created.remove(&event.wd);
+                    }
+                    continue;
+                }
+                let kind = if event.mask.contains(EventMask::MODIFY) {
+                    EventKind::Modified
+                } else {
+                    EventKind::NameChanged
+                };
+                let id = if event.mask.contains(EventMask::IGNORED) {
+                    // The watch is gone, e.g. because its file was deleted,
+                    // and its descriptor may be handed out again for another
+                    // file
+                    self.ids.remove(&event.wd)
+                } else {
+                    // Events can still arrive for a watch that was just removed
+                    self.ids.get(&event.wd).cloned()
+                };
+                events.extend(id.map(|id| Event { id, kind }));
+            }
+            if overflowed {
+                // Some events were dropped, so any of the files may have
+                // changed. They're all checked, as if each had been modified.
+                events.extend(self.ids.values().map(|id| Event { id: *id, kind: EventKind::Modified }));
+                events.extend(self.created.values().map(|id| Event { id: *id, kind: EventKind::Created }));
+            }
+            Ok(events)
+        }
+    }
+}
+
+#[cfg(any(target_os = "macos", target_os = "freebsd", target_os = "netbsd"))]
+pub use self::bsd::KqueueWatcher;
+
+/// kqueue(2). Like inotify, each file is watched through a descriptor kept
+/// open for it, so it's still watched after it's renamed. Unlike it, there's
+/// no watch on the directories for a new file taking a watched name; that's
+/// noticed when the names are next checked, every interval.
+#[cfg(any(target_os = "macos", target_os = "freebsd", target_os = "netbsd"))]
+mod bsd {
+    use std::collections::HashMap;
+    use std::fs::File;
+    use std::io;
+    use std::mem;
+    use std::os::unix::fs::MetadataExt;
+    use std::os::unix::io::AsRawFd;
+    use std::path::Path;
+    use std::ptr;
+    use std::time::Duration;
+    use libc;
+    use tail::clock::Clock;
+    use super::{Event, EventKind, FileWatcher, RawFd, WaitResult, WatchId};
+
+    /// What a file's own events are for: its contents changing
+    const MODIFIED_NOTES: u32 = libc::NOTE_WRITE | libc::NOTE_EXTEND | libc::NOTE_ATTRIB;
+    /// And its name leading somewhere else
+    const NAME_NOTES: u32 = libc::NOTE_DELETE | libc::NOTE_RENAME | libc::NOTE_REVOKE;
+
+    struct Watched {
+        id: WatchId,
+        /// Kept open for as long as it's watched; closing it removes the watch
+        fd: File,
+        dev: u64,
+        ino: u64,
+        /// Watched with `watch_dir`, for files created in it
+        dir: bool,
+    }
+
+    pub struct KqueueWatcher {
+        kq: RawFd,
+        name_changes: bool,
+        watches: HashMap<WatchId, Watched>,
+        next_id: u64,
+        /// Events read while waiting, for `next_events`
+        events: Vec<Event>,
+    }
+
+    fn vnode_change(fd: RawFd, fflags: u32) -> libc::kevent {
+        let mut change: libc::kevent = unsafe { mem::zeroed() };
+        change.ident = fd as libc::uintptr_t;
+        change.filter = libc::EVFILT_VNODE;
+        change.flags = libc::EV_ADD | libc::EV_CLEAR;
+        change.fflags = fflags;
+        change
+    }
+
+    /// Only until the first time `fd` is readable, so it's added for each
+    /// wait, and doesn't keep waking later ones once it's been read
+    fn read_change(fd: RawFd) -> libc::kevent {
+        let mut change: libc::kevent = unsafe { mem::zeroed() };
+        change.ident = fd as libc::uintptr_t;
+        change.filter = libc::EVFILT_READ;
+        change.flags = libc::EV_ADD | libc::EV_ONESHOT;
+        change
+    }
+
+    impl KqueueWatcher {
+        pub fn new(name_changes: bool) -> io::Result<KqueueWatcher> {
+            let kq = unsafe { libc::kqueue() };
+            if kq < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(KqueueWatcher { kq, name_changes, watches: HashMap::new(), next_id: 0, events: Vec::new() })
+        }
+
+        fn add(&mut self, fd: File, fflags: u32, dir: bool) -> io::Result<WatchId> {
+            let metadata = fd.metadata()?;
+            let existing = self.watches.values()
+                .find(|watched| watched.dir == dir && watched.dev == metadata.dev() && watched.ino == metadata.ino());
+            if let Some(watched) = existing {
+                return Ok(watched.id);
+            }
+            let change = vnode_change(fd.as_raw_fd(), fflags);
+            if unsafe { libc::kevent(self.kq, &change, 1, ptr::null_mut(), 0, ptr::null()) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            self.next_id += 1;
+            let id = WatchId(self.next_id);
+            self.watches.insert(id, Watched { id, fd, dev: metadata.dev(), ino: metadata.ino(), dir });
+            Ok(id)
+        }
+
+        /// Makes `changes` and reads the events that come in within
+        /// `timeout` into `events`
+        fn read_events(&mut self, changes: &[libc::kevent], timeout: Option<Duration>) -> io::Result<WaitResult> {
+            let timeout = timeout.map(|timeout| libc::timespec {
+                tv_sec: timeout.as_secs() as libc::time_t,
+                tv_nsec: timeout.subsec_nanos() as libc::c_long,
+            });
+            let mut received: [libc::kevent; 64] = unsafe { mem::zeroed() };
+            let count = unsafe {
+                libc::kevent(self.kq, changes.as_ptr(), changes.len() as _, received.as_mut_ptr(), received.len() as _,
+                             timeout.as_ref().map_or(ptr::null(), |timeout| timeout as *const libc::timespec))
+            };
+            if count < 0 {
+                let err = io::Error::last_os_error();
+                return if err.kind() == io::ErrorKind::Interrupted { Ok(WaitResult::Interrupted) } else { Err(err) };
+            }
+            let mut woken = false;
+            for event in &received[..count as usize] {
+                // A descriptor to wake for may have been closed since
+                if event.flags & libc::EV_ERROR != 0 {
+                    continue;
+                }
+                if event.filter == libc::EVFILT_READ {
+                    woken = true;
+                    continue;
+                }
+                let watched = self.watches.values().find(|watched| watched.fd.as_raw_fd() as libc::uintptr_t == event.ident);
+                let watched = match watched {
+                    Some(watched) => watched,
+                    None => continue,
+                };
+                let fflags = event.fflags;
+                if watched.dir {
+                    self.events.push(Event { id: watched.id, kind: EventKind::Created });
+                    continue;
+                }
+                if fflags & MODIFIED_NOTES != 0 {
+                    self.events.push(Event { id: watched.id, kind: EventKind::Modified });
+                }
+                if fflags & NAME_NOTES != 0 {
+                    self.events.push(Event { id: watched.id, kind: EventKind::NameChanged });
+                }
+            }
+            Ok(if woken {
+                WaitResult::Interrupted
+            } else if count == 0 {
+                WaitResult::TimedOut
+            } else {
+                WaitResult::Ready
+            })
+        }
+    }
+
+    impl Drop for KqueueWatcher {
+        fn drop(&mut self) {
+            unsafe { libc::close(self.kq) };
+        }
+    }
+
+    impl FileWatcher for KqueueWatcher {
+        fn watch(&mut self, path: &Path) -> io::Result<WatchId> {
+            let fflags = if self.name_changes { MODIFIED_NOTES | NAME_NOTES } else { MODIFIED_NOTES };
+            self.add(File::open(path)?, fflags, false)
+        }
+
+        fn watch_dir(&mut self, dir: &Path) -> io::Result<WatchId> {
+            let fd = File::open(dir)?;
+            if !fd.metadata()?.is_dir() {
+                return Err(io::Error::from_raw_os_error(libc::ENOTDIR));
+            }
+            // A directory is written to when an entry is added to it
+            self.add(fd, libc::NOTE_WRITE, true)
+        }
+
+        fn unwatch(&mut self, id: WatchId) {
+            self.watches.remove(&id);
+            self.events.retain(|event| event.id != id);
+        }
+
+        fn wait(&mut self, timeout: Option<Duration>, wake_fds: &[RawFd], _clock: &dyn Clock) -> io::Result<WaitResult> {
+            if !self.events.is_empty() {
+                return Ok(WaitResult::Ready);
+            }
+            let changes: Vec<libc::kevent> = wake_fds.iter().map(|&fd| read_change(fd)).collect();
+            self.read_events(&changes, timeout)
+        }
+
+        fn next_events(&mut self) -> io::Result<Vec<Event>> {
+            // Along with whatever else has come in since the wait
+            self.read_events(&[], Some(Duration::from_secs(0)))?;
+            Ok(self.events.drain(..).collect())
+        }
+    }
+}
+
+/// Notices changes by checking each file every `interval`, for platforms
+/// without a native watcher
+pub struct PollWatcher {
+    interval: Duration,
+    watches: HashMap<WatchId, Polled>,
+    dirs: HashMap<WatchId, PolledDir>,
+    next_id: u64,
+    events: Vec<Event>,
+}
+
+struct Polled {
+    path: PathBuf,
+    /// Kept open so the file is still checked after it's renamed
+    fd: File,
+    id: FileId,
+    last: Metadata,
+    /// The file the path led to when last checked, if it led anywhere
+    path_id: Option<FileId>,
+}
+
+struct PolledDir {
+    path: PathBuf,
+    id: FileId,
+    /// What was in the directory when last checked
+    names: HashSet<OsString>,
+}
+
+fn modified(metadata: &Metadata) -> Option<SystemTime> {
+    metadata.modified().ok()
+}
+
+fn dir_names(dir: &Path) -> io::Result<HashSet<OsString>> {
+    fs::read_dir(dir)?.map(|entry| entry.map(|entry| entry.file_name())).collect()
+}
+
+impl PollWatcher {
+    pub fn new(interval: Duration) -> PollWatcher {
+        PollWatcher { interval, watches: HashMap::new(), dirs: HashMap::new(), next_id: 0, events: Vec::new() }
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "macos", target_os = "freebsd", target_os = "netbsd"))]
+    fn is_empty(&self) -> bool {
+        self.watches.is_empty() && self.dirs.is_empty()
+    }
+
+    fn check(&mut self) {
+        for (id, polled) in &mut self.watches {
+            if let Ok(metadata) = polled.fd.metadata() {
+                if metadata.len() != polled.last.len() || modified(&metadata) != modified(&polled.last) {
+                    self.events.push(Event { id: *id, kind: EventKind::Modified });
+                }
+                polled.last = metadata;
+            }
+            let path_id = fs::metadata(&polled.path).ok().map(|metadata| file_id(&polled.path, &metadata));
+            if path_id != polled.path_id {
+                self.events.push(Event { id: *id, kind: EventKind::NameChanged });
+                polled.path_id = path_id;
+            }
+        }
+        for (id, dir) in &mut self.dirs {
+            if let Ok(names) = dir_names(&dir.path) {
+                if names.iter().any(|name| !dir.names.contains(name)) {
+                    self.events.push(Event { id: *id, kind: EventKind::Created });
+                }
+                dir.names = names;
+            }
+        }
+    }
+}
+
+impl FileWatcher for PollWatcher {
+    fn watch(&mut self, path: &Path) -> io::Result<WatchId> {
+        let fd = File::open(path)?;
+        let last = fd.metadata()?;
+        let file = file_id(path, &last);
+        let existing = self.watches.iter().find(|&(_, polled)| polled.id == file);
+        if let Some((id, _)) = existing {
+            return Ok(*id);
+        }
+        self.next_id += 1;
+        let id = WatchId(self.next_id);
+        let path_id = Some(file_id(path, &last));
+        self.watches.insert(id, Polled { path: path.to_path_buf(), fd, id: file, last, path_id });
+        Ok(id)
+    }
+
+    fn watch_dir(&mut self, dir: &Path) -> io::Result<WatchId> {
+        let metadata = fs::metadata(dir)?;
+        let file = file_id(dir, &metadata);
+        let existing = self.dirs.iter().find(|&(_, polled)| polled.id == file);
+        if let Some((id, _)) = existing {
+            return Ok(*id);
+        }
+        let names = dir_names(dir)?;
+        self.next_id += 1;
+        let id = WatchId(self.next_id);
+        self.dirs.insert(id, PolledDir { path: dir.to_path_buf(), id: file, names });
+        Ok(id)
+    }
+
+    fn unwatch(&mut self, id: WatchId) {
+        self.watches.remove(&id);
+        self.dirs.remove(&id);
+    }
+
+    fn wait(&mut self, timeout: Option<Duration>, wake_fds: &[RawFd], clock: &dyn Clock) -> io::Result<WaitResult> {
+        if self.events.is_empty() {
+            let nap = timeout.map_or(self.interval, |timeout| timeout.min(self.interval));
+            // With nothing that could cut the nap short, it's just a sleep
+            if wake_fds.is_empty() {
+                clock.sleep(nap);
+            } else if let WaitResult::Interrupted = wait_readable(None, wake_fds, Some(nap))? {
+                return Ok(WaitResult::Interrupted);
+            }
+            self.check();
+        }
+        Ok(if self.events.is_empty() { WaitResult::TimedOut } else { WaitResult::Ready })
+    }
+
+    fn next_events(&mut self) -> io::Result<Vec<Event>> {
+        Ok(self.events.drain(..).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tail::clock::MockClock;
+
+    #[test]
+    fn polling_sleeps_by_the_clock() {
+        let dir = ::std::env::temp_dir().join(format!("tail-watch-{}", ::std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("polled.log");
+        fs::write(&path, b"a\n").unwrap();
+
+        let clock = MockClock::new();
+        let start = clock.now();
+        let mut watcher = PollWatcher::new(Duration::from_secs(60));
+        let id = watcher.watch(&path).unwrap();
+        assert!(matches!(watcher.wait(None, &[], &clock).unwrap(), WaitResult::TimedOut));
+        assert_eq!(clock.now() - start, Duration::from_secs(60));
+        assert!(matches!(watcher.wait(Some(Duration::from_secs(5)), &[], &clock).unwrap(), WaitResult::TimedOut));
+        assert_eq!(clock.now() - start, Duration::from_secs(65));
+
+        fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(b"b\n").unwrap();
+        assert!(matches!(watcher.wait(None, &[], &clock).unwrap(), WaitResult::Ready));
+        let events = watcher.next_events().unwrap();
+        assert!(events.iter().any(|event| event.id == id && event.kind == EventKind::Modified));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Watches everything but the files named `refused`, the way inotify
+    /// can't watch some file systems, and never has events of its own
+    #[cfg(any(target_os = "linux", target_os = "macos", target_os = "freebsd", target_os = "netbsd"))]
+    struct Refusing {
+        refused: &'static str,
+        next_id: u64,
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "macos", target_os = "freebsd", target_os = "netbsd"))]
+    impl FileWatcher for Refusing {
+        fn watch(&mut self, path: &Path) -> io::Result<WatchId> {
+            if path.file_name().is_some_and(|name| name == self.refused) {
+                return Err(io::Error::from_raw_os_error(libc::ENOSYS));
+            }
+            self.next_id += 1;
+            Ok(WatchId(self.next_id))
+        }
+
+        fn watch_dir(&mut self, dir: &Path) -> io::Result<WatchId> {
+            self.watch(dir)
+        }
+
+        fn unwatch(&mut self, _id: WatchId) {}
+
+        fn wait(&mut self, _timeout: Option<Duration>, _wake_fds: &[RawFd], _clock: &dyn Clock) -> io::Result<WaitResult> {
+            Ok(WaitResult::TimedOut)
+        }
+
+        fn next_events(&mut self) -> io::Result<Vec<Event>> {
+            Ok(Vec::new())
+        }
+    }
+
+    #[test]
+    #[cfg(any(target_os = "linux", target_os = "macos", target_os = "freebsd", target_os = "netbsd"))]
+    fn files_the_primary_cant_watch_are_polled() {
+        let dir = ::std::env::temp_dir().join(format!("tail-fallback-{}", ::std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let watched = dir.join("local.log");
+        let polled = dir.join("nfs.log");
+        fs::write(&watched, b"a\n").unwrap();
+        fs::write(&polled, b"a\n").unwrap();
+
+        let clock = MockClock::new();
+        let mut watcher = Fallback::new(Refusing { refused: "nfs.log", next_id: 0 }, Duration::from_secs(1));
+        let watched_id = watcher.watch(&watched).unwrap();
+        let polled_id = watcher.watch(&polled).unwrap();
+        assert_ne!(watched_id, polled_id);
+        assert!(watcher.noted);
+        assert_eq!(watcher.watch(&polled).unwrap(), polled_id);
+
+        assert!(matches!(watcher.wait(None, &[], &clock).unwrap(), WaitResult::TimedOut));
+        fs::OpenOptions::new().append(true).open(&polled).unwrap().write_all(b"b\n").unwrap();
+        assert!(matches!(watcher.wait(None, &[], &clock).unwrap(), WaitResult::Ready));
+        let events = watcher.next_events().unwrap();
+        assert!(events.iter().all(|event| event.id == polled_id));
+        assert!(events.iter().any(|event| event.kind == EventKind::Modified));
+
+        // Once unwatched, its events aren't passed on
+        watcher.unwatch(polled_id);
+        fs::OpenOptions::new().append(true).open(&polled).unwrap().write_all(b"c\n").unwrap();
+        watcher.wait(None, &[], &clock).unwrap();
+        assert!(watcher.next_events().unwrap().is_empty());
+
+        // When polling can't open it either, that's the error
+        fs::remove_file(&polled).unwrap();
+        assert_eq!(watcher.watch(&polled).unwrap_err().kind(), io::ErrorKind::NotFound);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
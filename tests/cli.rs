@@ -0,0 +1,1126 @@
+//! Runs the `tail` binary the way it's used from the command line. The tests
+//! lean on Unix files and tools (`mkfifo`, `/dev/full`, symlinks, `kill`), so
+//! they only run there.
+#![cfg(unix)]
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+/// A directory of its own for each test, so they can run at the same time
+fn test_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("tail-cli-{}-{}", std::process::id(), test));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn write_file(dir: &Path, name: &str, contents: &[u8]) -> String {
+    let path = dir.join(name);
+    fs::write(&path, contents).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+fn tail(args: &[&str]) -> Output {
+    tail_with_stdin(args, b"")
+}
+
+fn tail_with_stdin(args: &[&str], input: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_tail"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // tail may exit, e.g. on a bad option, before reading any of it
+    let _ = child.stdin.take().unwrap().write_all(input);
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn plus_num_starts_at_line_num() {
+    let dir = test_dir("plus-num");
+    let file = write_file(&dir, "p.txt", b"p1\np2\np3\n");
+    let cases: &[(&str, &str)] = &[
+        ("+0", "p1\np2\np3\n"),
+        ("+1", "p1\np2\np3\n"),
+        ("+2", "p2\np3\n"),
+        ("+3", "p3\n"),
+        ("+4", ""),
+        ("+100", ""),
+    ];
+    for &(num, expected) in cases {
+        let output = tail(&["-n", num, &file]);
+        assert!(output.status.success(), "-n {}: {}", num, stderr(&output));
+        assert_eq!(stdout(&output), expected, "-n {}", num);
+    }
+}
+
+#[test]
+fn plus_num_from_stdin() {
+    for &(num, expected) in &[("+0", "p1\np2\np3\n"), ("+1", "p1\np2\np3\n"), ("+2", "p2\np3\n"), ("+5", "")] {
+        let output = tail_with_stdin(&["-n", num], b"p1\np2\np3\n");
+        assert_eq!(stdout(&output), expected, "-n {}", num);
+    }
+}
+
+#[test]
+fn plus_num_numbers_lines_from_num() {
+    let dir = test_dir("plus-num-numbers");
+    let file = write_file(&dir, "p.txt", b"p1\np2\np3\n");
+    let output = tail(&["-n", "+2", "--line-numbers", &file]);
+    assert_eq!(stdout(&output), "     2\tp2\n     3\tp3\n");
+    let output = tail_with_stdin(&["-n", "+2", "--line-numbers"], b"p1\np2\np3\n");
+    assert_eq!(stdout(&output), "     2\tp2\n     3\tp3\n");
+}
+
+#[test]
+fn standard_input_is_read_with_no_file_or_dash() {
+    let input: String = (1..=12).map(|i| format!("{}\n", i)).collect();
+    let last_ten: String = (3..=12).map(|i| format!("{}\n", i)).collect();
+    let cases: &[(&[&str], &str)] = &[
+        (&[], &last_ten),
+        (&["-"], &last_ten),
+        (&["-n", "2"], "11\n12\n"),
+        (&["-n", "0"], ""),
+        (&["-c", "3"], "12\n"),
+    ];
+    for &(args, expected) in cases {
+        let output = tail_with_stdin(args, input.as_bytes());
+        assert_eq!(output.status.code(), Some(0), "{:?}: {}", args, stderr(&output));
+        assert_eq!(stdout(&output), expected, "{:?}", args);
+    }
+}
+
+#[test]
+fn standard_input_redirected_from_a_file_is_followed() {
+    use std::io::{BufRead, BufReader};
+
+    let dir = test_dir("stdin-file");
+    let file = write_file(&dir, "in.log", b"one\n");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_tail"))
+        .args(["-f", "--timeout", "30"])
+        .stdin(fs::File::open(&file).unwrap())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut line = String::new();
+    stdout.read_line(&mut line).unwrap();
+    assert_eq!(line, "one\n");
+    fs::OpenOptions::new().append(true).open(&file).unwrap().write_all(b"two\n").unwrap();
+    line.clear();
+    stdout.read_line(&mut line).unwrap();
+    assert_eq!(line, "two\n");
+    send_signal(child.id(), "TERM");
+    assert_eq!(child.wait().unwrap().code(), Some(0));
+}
+
+#[test]
+fn malformed_numbers_are_reported_not_panicked_on() {
+    let dir = test_dir("malformed-numbers");
+    let file = write_file(&dir, "f.txt", b"hi\n");
+    let cases: &[&[&str]] = &[
+        &["-n", "x"],
+        &["-n", "5Q"],
+        &["-n", "99999999999999999999"],
+        &["-c", "x"],
+        &["-c", "+"],
+        &["-c", "-5"],
+        &["-c", ""],
+        &["-c", "99999999999999999999"],
+        &["--byte-range", "x"],
+        &["--byte-range", "5-2"],
+        &["--head-tail", "3"],
+        &["--head-tail", "a:b"],
+        &["--warn-size", "1Q"],
+        &["--warn-size", "x"],
+        &["--max-scan-bytes", "x"],
+        &["--max-scan-bytes", "99999999999999999999"],
+        &["--max-unchanged-stats", "-1"],
+        &["--threads", "x"],
+        &["--threads", "0"],
+        &["--timeout", "x"],
+        &["--timeout", "1e400"],
+        &["--timeout", "1x"],
+        &["--exit-on-idle", "-1"],
+        &["-s", "nan"],
+        &["-s", "inf"],
+        &["--pid", "x"],
+        &["--pid-mode", "x"],
+    ];
+    for args in cases {
+        let output = tail(&[*args, &[&file[..]]].concat());
+        let err = stderr(&output);
+        assert_eq!(output.status.code(), Some(1), "{:?}: {}", args, err);
+        assert!(err.starts_with("tail: "), "{:?}: {}", args, err);
+        assert!(!err.contains("panicked"), "{:?}: {}", args, err);
+        assert_eq!(stdout(&output), "", "{:?}", args);
+    }
+    assert_eq!(stderr(&tail(&["-c", "abc", &file])), "tail: invalid number of bytes: 'abc'\n");
+}
+
+#[test]
+fn bytes_are_counted_from_the_end_or_with_a_plus_from_the_start() {
+    let dir = test_dir("bytes");
+    let file = write_file(&dir, "f.txt", b"0123456789");
+    let cases: &[(&[&str], &str)] = &[
+        (&["-c", "3"], "789"),
+        (&["-c", "0"], ""),
+        (&["-c", "100"], "0123456789"),
+        (&["-c", "+3"], "23456789"),
+        (&["-c", "+1"], "0123456789"),
+        (&["-c", "+0"], "0123456789"),
+        (&["-c", "+100"], ""),
+        // The last of -c and -n wins, and --byte-range wins over both
+        (&["-n", "5", "-c", "2"], "89"),
+        (&["-c", "2", "-n", "+1"], "0123456789"),
+        (&["-c2", "-n+1"], "0123456789"),
+        (&["-n1", "--bytes=2"], "89"),
+        (&["-c", "2", "--byte-range", "0-1"], "0"),
+    ];
+    for &(args, expected) in cases {
+        let output = tail(&[args, &[&file[..]]].concat());
+        assert_eq!(output.status.code(), Some(0), "{:?}: {}", args, stderr(&output));
+        assert_eq!(stdout(&output), expected, "{:?}", args);
+    }
+}
+
+#[test]
+fn sizes_take_the_documented_multiplier_suffixes() {
+    let dir = test_dir("size-suffixes");
+    let file = write_file(&dir, "f.txt", &[b'x'; 3000]);
+    let cases: &[(&str, usize)] = &[("1b", 512), ("1kB", 1000), ("1K", 1024), ("2", 2)];
+    for &(size, expected) in cases {
+        let output = tail(&["-c", size, &file]);
+        assert_eq!(output.status.code(), Some(0), "{}: {}", size, stderr(&output));
+        assert_eq!(output.stdout.len(), expected, "{}", size);
+    }
+    let lines = write_file(&dir, "lines.txt", "x\n".repeat(1500).as_bytes());
+    assert_eq!(stdout(&tail(&["-n", "1K", &lines])).len(), 2 * 1024);
+
+    let errors: &[(&[&str], &str)] = &[
+        (&["-n", "10Q"], "tail: invalid number of lines: '10Q': unknown suffix 'Q'\n"),
+        (&["-c", "1MiBx"], "tail: invalid number of bytes: '1MiBx': unknown suffix 'MiBx'\n"),
+        (&["-c", "99Y"], "tail: invalid number of bytes: '99Y': value too large\n"),
+        (&["--warn-size", "1Q"], "tail: invalid number of bytes for --warn-size: '1Q': unknown suffix 'Q'\n"),
+    ];
+    for &(args, expected) in errors {
+        let output = tail(&[args, &[&file[..]]].concat());
+        assert_eq!(output.status.code(), Some(1), "{:?}", args);
+        assert_eq!(stderr(&output), expected, "{:?}", args);
+    }
+}
+
+#[test]
+fn reverse_files_reverses_the_files_and_their_headers() {
+    let dir = test_dir("reverse-files");
+    let a = write_file(&dir, "a.txt", b"a1\na2\n");
+    let b = write_file(&dir, "b.txt", b"b1\n");
+    let c = write_file(&dir, "c.txt", b"c1\nc2\nc3\n");
+    let output = tail(&["--reverse-files", "-n", "2", &a, &b, &c]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(stdout(&output), format!(
+        "==> {} <==\nc2\nc3\n\n==> {} <==\nb1\n\n==> {} <==\na1\na2\n", c, b, a));
+}
+
+#[test]
+fn symlink_loops_are_skipped() {
+    use std::os::unix::fs::symlink;
+
+    let dir = test_dir("symlink-loop");
+    let good = write_file(&dir, "good.log", b"good\n");
+    let looped = dir.join("loop.log");
+    symlink(&looped, &looped).unwrap();
+    let looped = looped.to_str().unwrap().to_string();
+    let output = tail(&[&looped, &good]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stdout(&output), format!("==> {} <==\ngood\n", good));
+    assert!(stderr(&output).starts_with(&format!("tail: {}: ", looped)), "{}", stderr(&output));
+    assert!(stderr(&output).ends_with("; skipping\n"), "{}", stderr(&output));
+
+    // Devices are passed through to their end, with a note
+    let output = tail(&["-f", "--timeout", "1", "/dev/null"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stderr(&output), "tail: /dev/null: not a regular file; streaming it instead of following\n");
+}
+
+#[test]
+fn headers_name_each_of_several_files_unless_quiet() {
+    let dir = test_dir("headers");
+    let a = write_file(&dir, "a.txt", b"a\n");
+    let b = write_file(&dir, "b.txt", b"b\n");
+    assert_eq!(stdout(&tail(&[&a])), "a\n");
+    assert_eq!(stdout(&tail(&[&a, &b])), format!("==> {} <==\na\n\n==> {} <==\nb\n", a, b));
+    assert_eq!(stdout(&tail(&[&a, "-"])), format!("==> {} <==\na\n\n==> standard input <==\n", a));
+    assert_eq!(stdout(&tail(&["-q", &a, &b])), "a\nb\n");
+    assert_eq!(stdout(&tail(&["--quiet", &a, &b])), "a\nb\n");
+}
+
+#[test]
+fn config_sets_per_file_options_under_the_command_line() {
+    let dir = test_dir("config");
+    let app = write_file(&dir, "app.log", b"ERROR a\ninfo b\nERROR c\n");
+    let db = write_file(&dir, "db.log", b"d1\nd2\nd3\n");
+    let config = write_file(&dir, "tail.conf", format!(
+        "follow = true\nlines = 2\n\n[{}]\nlines = 10\nfilter = ^ERROR\nprefix = app:\n\n[{}]\n", app, db).as_bytes());
+
+    let output = tail(&["--no-follow", "--config", &config]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(stdout(&output), format!("==> {} <==\napp:ERROR a\napp:ERROR c\n\n==> {} <==\nd2\nd3\n", app, db));
+
+    let output = tail(&["--no-follow", "--config", &config, "-n", "1", "--prefix", "> ", "--filter", "a"]);
+    assert_eq!(stdout(&output), format!("==> {} <==\n\n==> {} <==\n", app, db));
+    let output = tail(&["--no-follow", "--config", &config, "-n", "1", "--prefix", "> "]);
+    assert_eq!(stdout(&output), format!("==> {} <==\n> ERROR c\n\n==> {} <==\n> d3\n", app, db));
+}
+
+#[test]
+fn filter_keeps_line_numbers_of_the_file() {
+    let dir = test_dir("filter-numbers");
+    let file = write_file(&dir, "f.log", b"a\nb\na\n");
+    let output = tail(&["--filter", "a", "--line-numbers", &file]);
+    assert_eq!(stdout(&output), "     1\ta\n     3\ta\n");
+    let output = tail(&["--filter", "(", &file]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).starts_with("tail: "), "{}", stderr(&output));
+}
+
+#[test]
+fn byte_range_prints_the_bytes_from_start_up_to_end() {
+    let dir = test_dir("byte-range");
+    let file = write_file(&dir, "f.txt", b"0123456789");
+    let cases: &[(&[&str], &str)] = &[
+        (&["--byte-range", "2-5"], "234"),
+        (&["-c", "2-5"], "234"),
+        (&["--byte-range", "3-3"], ""),
+        (&["--byte-range", "7-100"], "789"),
+        (&["--byte-range", "20-30"], ""),
+    ];
+    for &(args, expected) in cases {
+        let output = tail(&[args, &[&file[..]]].concat());
+        assert_eq!(output.status.code(), Some(0), "{:?}: {}", args, stderr(&output));
+        assert_eq!(stdout(&output), expected, "{:?}", args);
+        let output = tail_with_stdin(args, b"0123456789");
+        assert_eq!(stdout(&output), expected, "{:?} from stdin", args);
+    }
+
+    let output = tail(&["--byte-range", "5-2", &file]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stderr(&output), "tail: invalid byte range: '5-2': start is past the end\n");
+}
+
+#[test]
+fn encoding_auto_outputs_utf8_whatever_the_file_is_in() {
+    let dir = test_dir("encoding-auto");
+    let samples: &[(&str, &[u8])] = &[
+        ("utf8.txt", b"caf\xC3\xA9\nb\n"),
+        ("utf16le.txt", b"\xFF\xFEc\x00a\x00f\x00\xE9\x00\n\x00b\x00\n\x00"),
+        ("latin1.txt", b"caf\xE9\nb\n"),
+    ];
+    for &(name, contents) in samples {
+        let file = write_file(&dir, name, contents);
+        let output = tail(&["--encoding=auto", &file]);
+        assert_eq!(output.status.code(), Some(0), "{}: {}", name, stderr(&output));
+        assert_eq!(stdout(&output), "caf\u{e9}\nb\n", "{}", name);
+    }
+
+    let output = tail_with_stdin(&["--encoding=ebcdic"], b"hi\n");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stderr(&output), "tail: unsupported encoding: 'ebcdic'\n");
+}
+
+#[test]
+fn follow_streams_a_fifo_as_it_is_written() {
+    use std::io::{BufRead, BufReader};
+
+    let dir = test_dir("follow-fifo");
+    let fifo = dir.join("f.pipe");
+    assert!(Command::new("mkfifo").arg(&fifo).status().unwrap().success());
+    let mut child = Command::new(env!("CARGO_BIN_EXE_tail"))
+        .args(["-f", "--timeout", "2", fifo.to_str().unwrap()])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut line = String::new();
+    // Each line comes out while its writer still has the pipe open, and the
+    // pipe carries on past the first writer
+    for text in &["one\n", "two\n"] {
+        let mut writer = fs::OpenOptions::new().write(true).open(&fifo).unwrap();
+        writer.write_all(text.as_bytes()).unwrap();
+        line.clear();
+        stdout.read_line(&mut line).unwrap();
+        assert_eq!(line, *text);
+    }
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert!(!stderr(&output).contains("panicked"), "{}", stderr(&output));
+
+    // Devices are passed through to their end, with a note
+    let output = tail(&["-f", "--timeout", "1", "/dev/null"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stderr(&output), "tail: /dev/null: not a regular file; streaming it instead of following\n");
+}
+
+#[test]
+fn checksum_changes_only_with_the_output() {
+    let dir = test_dir("checksum");
+    let a = write_file(&dir, "a.log", b"old\nsame\nlines\n");
+    let b = write_file(&dir, "b.log", b"new\nsame\nlines\n");
+    let c = write_file(&dir, "c.log", b"same\nlimes\n");
+    let checksum = |args: &[&str]| -> String {
+        let output = tail(args);
+        assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+        stderr(&output).rsplit(" checksum ").next().unwrap().trim_end().to_string()
+    };
+    // Only what's output counts, and not the headers
+    let a_sum = checksum(&["--checksum", "-n", "2", &a]);
+    assert!(a_sum.starts_with("fnv1a64:"), "{}", a_sum);
+    assert_eq!(checksum(&["--checksum", "-n", "2", &b]), a_sum);
+    assert_eq!(checksum(&["--checksum", "-n", "2", "-v", &b]), a_sum);
+    assert_ne!(checksum(&["--checksum", "-n", "3", &a]), a_sum);
+    assert_ne!(checksum(&["--checksum", "-n", "2", &c]), a_sum);
+
+    let output = tail(&["--checksum", "-n", "2", &a, &c]);
+    assert_eq!(stderr(&output).lines().count(), 2, "{}", stderr(&output));
+    assert!(stderr(&output).starts_with(&format!("tail: {}: checksum {}\n", a, a_sum)), "{}", stderr(&output));
+}
+
+/// Standard output for a child that fails every write with ENOSPC
+fn full_stdout() -> Stdio {
+    Stdio::from(fs::OpenOptions::new().write(true).open("/dev/full").unwrap())
+}
+
+#[test]
+fn a_full_stdout_is_reported_not_panicked_on() {
+    let dir = test_dir("full-stdout");
+    let file = write_file(&dir, "a.log", b"one\ntwo\n");
+    for args in [&[][..], &["-c", "5"], &["-f", "--timeout", "5"]] {
+        let output = Command::new(env!("CARGO_BIN_EXE_tail"))
+            .args(args)
+            .arg(&file)
+            .stdout(full_stdout())
+            .stderr(Stdio::piped())
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(1), "{:?}: {}", args, stderr(&output));
+        assert_eq!(stderr(&output), format!("tail: {}: No space left on device; skipping\n", file), "{:?}", args);
+    }
+}
+
+/// Sends `signal`, e.g. "USR1", to the process `pid`
+fn send_signal(pid: u32, signal: &str) {
+    let status = Command::new("kill").arg(format!("-{}", signal)).arg(pid.to_string()).status().unwrap();
+    assert!(status.success());
+}
+
+#[test]
+fn sigusr1_prints_a_status_line_and_following_carries_on() {
+    use std::io::{BufRead, BufReader};
+
+    let dir = test_dir("sigusr1");
+    let file = write_file(&dir, "f.log", b"first\n");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_tail"))
+        .args(["-f", "--timeout", "10", &file])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut stderr = BufReader::new(child.stderr.take().unwrap());
+    let mut line = String::new();
+    stdout.read_line(&mut line).unwrap();
+    assert_eq!(line, "first\n");
+    // Once an appended line comes out, tail is following, and has its
+    // handlers in place
+    let append = |data: &[u8]| fs::OpenOptions::new().append(true).open(&file).unwrap().write_all(data).unwrap();
+    append(b"second\n");
+    line.clear();
+    stdout.read_line(&mut line).unwrap();
+    assert_eq!(line, "second\n");
+
+    send_signal(child.id(), "USR1");
+    line.clear();
+    stderr.read_line(&mut line).unwrap();
+    assert_eq!(line, format!("tail: following 1 file: {} at byte 13; 1 lines emitted\n", file));
+
+    append(b"third\n");
+    line.clear();
+    stdout.read_line(&mut line).unwrap();
+    assert_eq!(line, "third\n");
+    send_signal(child.id(), "USR1");
+    line.clear();
+    stderr.read_line(&mut line).unwrap();
+    assert_eq!(line, format!("tail: following 1 file: {} at byte 19; 2 lines emitted\n", file));
+
+    send_signal(child.id(), "TERM");
+    assert_eq!(child.wait().unwrap().code(), Some(0));
+}
+
+#[test]
+fn sigusr2_prints_the_last_lines_again_and_following_carries_on() {
+    use std::io::{BufRead, BufReader};
+
+    let dir = test_dir("sigusr2");
+    let file = write_file(&dir, "f.log", b"first\n");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_tail"))
+        .args(["-f", "-n", "2", "--timeout", "10", &file])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut line = String::new();
+    stdout.read_line(&mut line).unwrap();
+    assert_eq!(line, "first\n");
+    let append = |data: &[u8]| fs::OpenOptions::new().append(true).open(&file).unwrap().write_all(data).unwrap();
+    append(b"second\nthird\n");
+    let mut lines = String::new();
+    for _ in 0..2 {
+        stdout.read_line(&mut lines).unwrap();
+    }
+    assert_eq!(lines, "second\nthird\n");
+
+    send_signal(child.id(), "USR2");
+    lines.clear();
+    for _ in 0..2 {
+        stdout.read_line(&mut lines).unwrap();
+    }
+    assert_eq!(lines, "second\nthird\n");
+
+    // Only what's new is followed after the reprint
+    append(b"fourth\n");
+    line.clear();
+    stdout.read_line(&mut line).unwrap();
+    assert_eq!(line, "fourth\n");
+
+    send_signal(child.id(), "TERM");
+    assert_eq!(child.wait().unwrap().code(), Some(0));
+}
+
+#[test]
+fn the_exit_status_says_what_ended_following() {
+    use std::time::{Duration, Instant};
+
+    let dir = test_dir("exit-status");
+    let file = write_file(&dir, "f.log", b"");
+    let output = tail(&["-f", "--timeout", "0.5", &file]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    // Nothing grows, so idling out comes first
+    let started = Instant::now();
+    let output = tail(&["-f", "--timeout", "30", "--exit-on-idle", "0.5", &file]);
+    assert_eq!(output.status.code(), Some(124));
+    assert!(started.elapsed() < Duration::from_secs(20));
+    // and a PID that's already gone comes before either
+    let started = Instant::now();
+    let output = tail(&["-f", "--timeout", "30", "--exit-on-idle", "30", "--pid", &exited_pid().to_string(), &file]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(started.elapsed() < Duration::from_secs(20));
+}
+
+/// The PID of a process that has exited and been reaped
+fn exited_pid() -> u32 {
+    let mut child = Command::new("true").spawn().unwrap();
+    child.wait().unwrap();
+    child.id()
+}
+
+fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut child = Command::new("gzip").args(["-c", "-n"])
+        .stdin(Stdio::piped()).stdout(Stdio::piped()).spawn().unwrap();
+    child.stdin.take().unwrap().write_all(data).unwrap();
+    child.wait_with_output().unwrap().stdout
+}
+
+#[test]
+fn a_truncated_gzip_file_is_skipped() {
+    let dir = test_dir("gzip-truncated");
+    let member = gzip(b"one\n");
+    let truncated = write_file(&dir, "truncated.gz", &member[..member.len() - 4]);
+    let output = tail(&[&truncated]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stderr(&output), format!(
+        "tail: {}: truncated gzip stream; only complete gzip members can be read; skipping\n", truncated));
+}
+
+#[test]
+fn strip_bom_leaves_out_the_mark_whenever_the_output_starts_the_file() {
+    let dir = test_dir("strip-bom");
+    let contents = b"\xEF\xBB\xBFa\nb\n";
+    let file = write_file(&dir, "bom.txt", contents);
+    let cases: &[(&[&str], &[u8])] = &[
+        (&[], b"a\nb\n"),
+        (&["-n", "1"], b"b\n"),
+        (&["-n", "+1"], b"a\nb\n"),
+        (&["--head-tail", "1:1"], b"a\nb\n"),
+        (&["-c", "100"], b"a\nb\n"),
+        (&["-c", "3"], b"\nb\n"),
+        (&["-c", "+2"], b"\nb\n"),
+        (&["--line-numbers"], b"     1\ta\n     2\tb\n"),
+    ];
+    for &(args, expected) in cases {
+        let args = [&["--strip-bom"], args].concat();
+        let output = tail(&[&args[..], &[&file[..]]].concat());
+        assert_eq!(output.stdout, expected, "{:?}", args);
+        let output = tail_with_stdin(&args, contents);
+        assert_eq!(output.stdout, expected, "{:?} from stdin", args);
+    }
+    // Without it, and for byte ranges, the file's bytes are as they are
+    assert_eq!(tail(&[&file]).stdout, contents);
+    assert_eq!(tail(&["--strip-bom", "--byte-range", "0-4", &file]).stdout, b"\xEF\xBB\xBFa");
+    assert_eq!(tail_with_stdin(&["--strip-bom", "--byte-range", "0-4"], contents).stdout, b"\xEF\xBB\xBFa");
+}
+
+#[test]
+fn global_number_leaves_the_initial_lines_unnumbered() {
+    let dir = test_dir("global-number");
+    let a = write_file(&dir, "a.log", b"a0\n");
+    let b = write_file(&dir, "b.log", b"b0\n");
+    let output = tail(&["-f", "-q", "--timeout", "0.3", "--global-number", &a, &b]);
+    assert_eq!(stdout(&output), "a0\nb0\n");
+}
+
+#[test]
+fn state_file_resumes_without_repeating_or_losing_lines() {
+    let dir = test_dir("state-file");
+    let file = write_file(&dir, "f.log", b"one\ntwo\nthree\n");
+    let state = format!("--state-file={}", dir.join("tail.state").display());
+    let output = tail(&[&state, "-n", "2", &file]);
+    assert_eq!(stdout(&output), "two\nthree\n");
+
+    fs::OpenOptions::new().append(true).open(&file).unwrap().write_all(b"four\nfive\n").unwrap();
+    let output = tail(&[&state, "-n", "2", &file]);
+    assert_eq!(stdout(&output), "four\nfive\n");
+    let output = tail(&[&state, "-n", "2", &file]);
+    assert_eq!(stdout(&output), "");
+
+    // Following resumes from it and saves it too
+    fs::OpenOptions::new().append(true).open(&file).unwrap().write_all(b"six\n").unwrap();
+    assert_eq!(stdout(&tail(&["-f", "--timeout", "0.3", &state, &file])), "six\n");
+    fs::OpenOptions::new().append(true).open(&file).unwrap().write_all(b"seven\n").unwrap();
+    assert_eq!(stdout(&tail(&[&state, &file])), "seven\n");
+
+    // A new file under the name starts over, even where it's long enough to
+    // have the offset saved for the old one
+    fs::rename(&file, dir.join("f.log.1")).unwrap();
+    write_file(&dir, "f.log", &[&b"new one\n"[..], &[b'x'; 100], b"\nnew two\n"].concat());
+    assert_eq!(stdout(&tail(&[&state, "-n", "1", &file])), "new two\n");
+}
+
+#[test]
+fn quiet_initial_only_skips_the_initial_output_while_following() {
+    let dir = test_dir("quiet-initial");
+    let a = write_file(&dir, "a.log", b"a0\n");
+    let b = write_file(&dir, "b.log", b"b0\n");
+    let output = tail(&["-f", "--quiet-initial", "-n", "5", "--timeout", "0.3", &a, &b]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "");
+
+    // Without following there's nothing to skip for
+    assert_eq!(stdout(&tail(&["--quiet-initial", &a])), "a0\n");
+}
+
+#[test]
+fn a_state_file_that_cant_be_read_is_ignored_and_replaced() {
+    let dir = test_dir("state-file-bad");
+    let path = dir.join("tail.state");
+    fs::write(&path, b"not a state file\n").unwrap();
+    let state = format!("--state-file={}", path.display());
+    let file = write_file(&dir, "f.log", b"one\ntwo\n");
+    let output = tail(&[&state, &file]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "one\ntwo\n");
+    assert_eq!(stderr(&output), format!("tail: {}:1: expected 'INODE OFFSET PATH', found 'not a state file'; \
+                                         starting without saved state\n", path.display()));
+    // The fresh state was saved over it
+    fs::OpenOptions::new().append(true).open(&file).unwrap().write_all(b"three\n").unwrap();
+    let output = tail(&[&state, &file]);
+    assert_eq!(stdout(&output), "three\n");
+    assert_eq!(stderr(&output), "");
+}
+
+#[test]
+fn a_full_stdout_is_reported_when_resuming_from_the_state_file() {
+    let dir = test_dir("full-stdout-resume");
+    let state = format!("--state-file={}", dir.join("tail.state").display());
+    let plain = write_file(&dir, "a.log", b"one\n");
+    let gzipped = write_file(&dir, "a.log.gz", &gzip(b"one\n"));
+    for file in [&plain, &gzipped] {
+        assert_eq!(stdout(&tail(&[&state, file])), "one\n");
+        let mut appended = fs::OpenOptions::new().append(true).open(file).unwrap();
+        if file.ends_with(".gz") {
+            appended.write_all(&gzip(b"two\n")).unwrap();
+        } else {
+            appended.write_all(b"two\n").unwrap();
+        }
+        let output = Command::new(env!("CARGO_BIN_EXE_tail"))
+            .args([&state, file])
+            .stdout(full_stdout())
+            .stderr(Stdio::piped())
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(1), "{}: {}", file, stderr(&output));
+        assert_eq!(stderr(&output), format!("tail: {}: No space left on device; skipping\n", file));
+    }
+}
+
+#[test]
+fn headers_markers_and_unreadable_inputs_on_a_full_stdout_are_reported() {
+    let dir = test_dir("full-stdout-extras");
+    let file = write_file(&dir, "a.log", b"one\n");
+    let unreadable = dir.join("a.dir");
+    fs::create_dir_all(&unreadable).unwrap();
+    let unreadable = unreadable.to_str().unwrap();
+    let cases: [(&[&str], String); 3] = [
+        (&["-v", &file], format!("tail: {}: No space left on device; skipping\n", file)),
+        (&["-c", "0", "--end-marker=END", &file], format!("tail: {}: No space left on device\n", file)),
+        (&[unreadable], format!("tail: {}: Is a directory\n", unreadable)),
+    ];
+    for (args, expected) in &cases {
+        let output = Command::new(env!("CARGO_BIN_EXE_tail"))
+            .args(*args)
+            .stdout(full_stdout())
+            .stderr(Stdio::piped())
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(1), "{:?}: {}", args, stderr(&output));
+        assert_eq!(&stderr(&output), expected, "{:?}", args);
+    }
+
+    // Nor is an error report that can't be written
+    let output = Command::new(env!("CARGO_BIN_EXE_tail"))
+        .args(["--error-report", &file])
+        .stdout(Stdio::null())
+        .stderr(full_stdout())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn end_marker_follows_each_file_once_on_a_line_of_its_own() {
+    use std::io::{BufRead, BufReader, Read};
+
+    let dir = test_dir("end-marker");
+    let a = write_file(&dir, "a.log", b"a0\n");
+    let b = write_file(&dir, "b.log", b"b0");
+    let output = tail(&["--end-marker", &a, &b]);
+    assert_eq!(stdout(&output), format!(
+        "==> {a} <==\na0\n#eof file={a}\n\n==> {b} <==\nb0\n#eof file={b}\n", a = a, b = b));
+    let output = tail_with_stdin(&["--end-marker=done: {file}"], b"in");
+    assert_eq!(stdout(&output), "in\ndone: -\n");
+
+    // While following, once following stops
+    let mut child = Command::new(env!("CARGO_BIN_EXE_tail"))
+        .args(["-f", "-q", "--end-marker", "--timeout", "30", &a, &b])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut initial = [0; 5];
+    stdout.read_exact(&mut initial).unwrap();
+    assert_eq!(&initial, b"a0\nb0");
+    fs::OpenOptions::new().append(true).open(&a).unwrap().write_all(b"a1\n").unwrap();
+    // Without headers, a1 carries on from b0, as with GNU tail
+    let mut line = String::new();
+    stdout.read_line(&mut line).unwrap();
+    assert_eq!(line, "a1\n");
+    send_signal(child.id(), "TERM");
+    let mut rest = String::new();
+    stdout.read_to_string(&mut rest).unwrap();
+    assert_eq!(rest, format!("#eof file={}\n#eof file={}\n", a, b));
+    assert_eq!(child.wait().unwrap().code(), Some(0));
+}
+
+#[test]
+fn last_lines_at_the_top_of_the_file_match_gnu_tail() {
+    let dir = test_dir("top-of-file");
+    let cases: &[(&[u8], &str, &[u8])] = &[
+        // Fewer lines than asked for
+        (b"one\ntwo\n", "5", b"one\ntwo\n"),
+        // Exactly as many
+        (b"one\ntwo\nthree\n", "3", b"one\ntwo\nthree\n"),
+        // No newline at the end
+        (b"one\ntwo\nthree", "2", b"two\nthree"),
+        (b"one\ntwo\nthree", "3", b"one\ntwo\nthree"),
+        // Blank lines at the top are lines too
+        (b"\n\none\n", "3", b"\n\none\n"),
+        (b"\n\none\n", "2", b"\none\n"),
+        (b"", "3", b""),
+    ];
+    for (i, &(contents, num, expected)) in cases.iter().enumerate() {
+        let file = write_file(&dir, &format!("{}.txt", i), contents);
+        assert_eq!(tail(&["-n", num, &file]).stdout, expected, "{:?} -n {}", String::from_utf8_lossy(contents), num);
+        assert_eq!(tail_with_stdin(&["-n", num], contents).stdout, expected,
+                   "{:?} -n {} from stdin", String::from_utf8_lossy(contents), num);
+    }
+}
+
+#[test]
+fn verbose_prints_headers_even_for_one_file_and_beats_quiet() {
+    let dir = test_dir("verbose");
+    let a = write_file(&dir, "a.txt", b"a\n");
+    let b = write_file(&dir, "b.txt", b"b\n");
+    assert_eq!(stdout(&tail(&[&a])), "a\n");
+    assert_eq!(stdout(&tail(&["-v", &a])), format!("==> {} <==\na\n", a));
+    assert_eq!(stdout(&tail(&["--verbose", &a])), format!("==> {} <==\na\n", a));
+    assert_eq!(stdout(&tail_with_stdin(&["-v"], b"in\n")), "==> standard input <==\nin\n");
+    assert_eq!(stdout(&tail(&["-q", &a, &b])), "a\nb\n");
+    let both = format!("==> {} <==\na\n\n==> {} <==\nb\n", a, b);
+    assert_eq!(stdout(&tail(&["-q", "-v", &a, &b])), both);
+    assert_eq!(stdout(&tail(&["-v", "-q", &a, &b])), both);
+}
+
+#[test]
+fn follow_stops_quietly_once_the_reader_goes_away() {
+    use std::io::{BufRead, BufReader, Read};
+    use std::time::{Duration, Instant};
+
+    let dir = test_dir("closed-pipe");
+    let file = write_file(&dir, "f.log", b"first\n");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_tail"))
+        .args(["-f", &file])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut line = String::new();
+    BufReader::new(child.stdout.take().unwrap()).read_line(&mut line).unwrap();
+    assert_eq!(line, "first\n");
+
+    let started = Instant::now();
+    let status = loop {
+        fs::OpenOptions::new().append(true).open(&file).unwrap().write_all(b"more\n").unwrap();
+        if let Some(status) = child.try_wait().unwrap() {
+            break status;
+        }
+        assert!(started.elapsed() < Duration::from_secs(10), "tail kept following");
+        std::thread::sleep(Duration::from_millis(50));
+    };
+    let mut err = String::new();
+    child.stderr.take().unwrap().read_to_string(&mut err).unwrap();
+    assert!(!err.contains("panicked"), "{}", err);
+    assert_ne!(status.code(), Some(101), "{}", err);
+}
+
+#[test]
+fn pid_ends_following_once_the_writer_exits_with_its_last_lines_printed() {
+    use std::time::{Duration, Instant};
+
+    let dir = test_dir("pid");
+    let file = write_file(&dir, "out.log", b"");
+    // As with `cmd >> out.log & tail -f --pid=$! out.log`
+    let mut writer = Command::new("sh")
+        .args(["-c", &format!("echo first >> '{0}'; sleep 0.5; echo last >> '{0}'", file)])
+        .spawn()
+        .unwrap();
+    let pid = format!("--pid={}", writer.id());
+    std::thread::spawn(move || writer.wait());
+    let started = Instant::now();
+    let output = tail(&["-f", &pid, "--timeout", "10", &file]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert!(started.elapsed() < Duration::from_secs(20), "{:?}", started.elapsed());
+    assert_eq!(stdout(&output), "first\nlast\n");
+
+    // A PID that's already gone ends it after the initial output
+    fs::write(&file, b"done\n").unwrap();
+    let started = Instant::now();
+    let output = tail(&["-f", &format!("--pid={}", exited_pid()), &file]);
+    assert_eq!(stdout(&output), "done\n");
+    assert!(started.elapsed() < Duration::from_secs(10), "{:?}", started.elapsed());
+}
+
+#[test]
+fn zero_terminated_records_from_stdin_and_files() {
+    let records = b"a\0b\nstill b\0c\0d\0e\0";
+    let cases: &[(&str, &[u8])] = &[
+        ("3", b"c\0d\0e\0"),
+        ("+2", b"b\nstill b\0c\0d\0e\0"),
+        ("10", records),
+    ];
+    let dir = test_dir("zero-terminated");
+    let file = write_file(&dir, "z.bin", records);
+    for &(num, expected) in cases {
+        let output = tail_with_stdin(&["-z", "-n", num], records);
+        assert_eq!(output.stdout, expected, "-n {} from stdin", num);
+        let output = tail(&["-z", "-n", num, &file]);
+        assert_eq!(output.stdout, expected, "-n {}", num);
+    }
+    // Without a NUL at the end, the last record is still one
+    let output = tail_with_stdin(&["-z", "-n", "2"], b"a\0b\0c");
+    assert_eq!(output.stdout, b"b\0c");
+}
+
+#[test]
+fn invalid_utf8_is_output_byte_for_byte() {
+    let dir = test_dir("invalid-utf8");
+    let file = write_file(&dir, "binary.log", b"text\n\xff\xfe\n");
+    assert_eq!(tail(&["-n", "1", &file]).stdout, b"\xff\xfe\n");
+    assert_eq!(tail(&["-n", "+2", &file]).stdout, b"\xff\xfe\n");
+    assert_eq!(tail_with_stdin(&["-n", "1"], b"text\n\xff\xfe\n").stdout, b"\xff\xfe\n");
+}
+
+/// Runs tail on a terminal of its own, through util-linux's `script`,
+/// returning what came out on it, stdout and stderr together
+#[cfg(target_os = "linux")]
+fn tail_on_a_terminal(args: &[&str]) -> String {
+    let command = [env!("CARGO_BIN_EXE_tail")].iter().chain(args).cloned().collect::<Vec<_>>().join(" ");
+    let output = Command::new("script").args(["-qec", &command, "/dev/null"]).output().unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    stdout(&output).replace("\r\n", "\n")
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn binary_files_are_skipped_on_a_terminal() {
+    let dir = test_dir("binary");
+    let binary = write_file(&dir, "binary.dat", b"one\n\0two\n");
+    let text = write_file(&dir, "text.log", b"text\n");
+    assert_eq!(tail_on_a_terminal(&[&binary, &text]),
+               format!("tail: {}: binary file, skipping (use --binary to override)\n\
+                        ==> {} <==\ntext\n", binary, text));
+    assert_eq!(tail_on_a_terminal(&["--binary", "-n", "1", &binary]), "\0two\n");
+    // Anywhere else the bytes go through as they are
+    assert_eq!(tail(&["-n", "1", &binary]).stdout, b"\0two\n");
+}
+
+#[test]
+fn poll_follows_by_checking_every_interval() {
+    use std::io::{BufRead, BufReader, Read};
+
+    let dir = test_dir("poll");
+    let file = write_file(&dir, "polled.log", b"a\n");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_tail"))
+        .args(["-f", "--poll", "-s", "0.1", "--timeout", "30", &file])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut line = String::new();
+    stdout.read_line(&mut line).unwrap();
+    assert_eq!(line, "a\n");
+    fs::OpenOptions::new().append(true).open(&file).unwrap().write_all(b"b\n").unwrap();
+    line.clear();
+    stdout.read_line(&mut line).unwrap();
+    assert_eq!(line, "b\n");
+    send_signal(child.id(), "TERM");
+    assert_eq!(child.wait().unwrap().code(), Some(0));
+    // Asking to poll isn't a fallback, so there's no note
+    let mut err = String::new();
+    child.stderr.take().unwrap().read_to_string(&mut err).unwrap();
+    assert_eq!(err, "");
+}
+
+#[test]
+fn a_file_named_twice_is_printed_twice() {
+    let dir = test_dir("named-twice");
+    let file = write_file(&dir, "a.log", b"a\n");
+    let link = dir.join("link.log");
+    let _ = fs::remove_file(&link);
+    std::os::unix::fs::symlink(&file, &link).unwrap();
+    let link = link.to_str().unwrap();
+    let output = tail(&[&file, &file, link]);
+    assert_eq!(stdout(&output), format!("==> {file} <==\na\n\n==> {file} <==\na\n\n==> {link} <==\na\n",
+                                        file = file, link = link));
+}
+
+#[test]
+fn output_json_prints_records_without_headers() {
+    let dir = test_dir("output-json");
+    let a = write_file(&dir, "a.log", b"a1\na2\n");
+    let b = write_file(&dir, "b.log", b"b1");
+    let output = tail(&["-n", "1", "--output=json", &a, &b]);
+    let out = stdout(&output);
+    let records: Vec<&str> = out.lines().map(|record| record.split(",\"ts\":").next().unwrap()).collect();
+    assert_eq!(records, [format!("{{\"file\":\"{}\",\"line\":\"a2\"", a), format!("{{\"file\":\"{}\",\"line\":\"b1\"", b)]);
+    let output = tail(&["--output=xml", &a]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stderr(&output), "tail: invalid output format: 'xml'\n");
+}
+
+#[test]
+fn output_delimiter_joins_the_lines_of_each_file() {
+    let dir = test_dir("output-delimiter");
+    let a = write_file(&dir, "a.log", b"a1\na2\n");
+    let b = write_file(&dir, "b.log", b"b1\nb2");
+    let output = tail(&["-n", "2", "--output-delimiter=, ", &a, &b]);
+    assert_eq!(stdout(&output), format!("==> {} <==\na1, a2\n\n==> {} <==\nb1, b2", a, b));
+    let output = tail_with_stdin(&["--output-delimiter=,"], b"x\ny\nz\n");
+    assert_eq!(stdout(&output), "x,y,z\n");
+}
+
+#[test]
+fn a_full_stdout_is_reported_when_the_held_back_end_is_written() {
+    let dir = test_dir("full-stdout-finish");
+    // The last line has no delimiter, so JSON holds its record back until
+    // the output is finished
+    let file = write_file(&dir, "a.log", b"a");
+    for args in [&["--output=json"][..], &["--output-delimiter=,"]] {
+        let output = Command::new(env!("CARGO_BIN_EXE_tail"))
+            .args(args)
+            .arg(&file)
+            .stdout(full_stdout())
+            .stderr(Stdio::piped())
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(1), "{:?}: {}", args, stderr(&output));
+        assert_eq!(stderr(&output), format!("tail: {}: No space left on device; skipping\n", file), "{:?}", args);
+    }
+}
+
+#[test]
+fn a_full_stdout_is_reported_for_output_read_ahead_by_threads() {
+    let dir = test_dir("full-stdout-threads");
+    // Longer than the output buffer, so the write itself fails
+    let big: String = (0..20_000).map(|i| format!("{}\n", i)).collect();
+    let big = write_file(&dir, "big.log", big.as_bytes());
+    let output = Command::new(env!("CARGO_BIN_EXE_tail"))
+        .args(["--threads", "2", "-n", "+1", &big])
+        .stdout(full_stdout())
+        .stderr(Stdio::piped())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1), "{}", stderr(&output));
+    assert_eq!(stderr(&output), format!("tail: {}: No space left on device; skipping\n", big));
+}
+
+#[test]
+fn appends_during_startup_are_printed_once_after_the_initial_lines() {
+    use std::io::{BufRead, BufReader, Read};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    let dir = test_dir("startup-appends");
+    let initial: String = (0..1000).map(|i| format!("{}\n", i)).collect();
+    let file = write_file(&dir, "busy.log", initial.as_bytes());
+    let stop = Arc::new(AtomicBool::new(false));
+    let writer = {
+        let (file, stop) = (file.clone(), stop.clone());
+        std::thread::spawn(move || {
+            let mut log = fs::OpenOptions::new().append(true).open(&file).unwrap();
+            let mut next = 1000;
+            while !stop.load(Ordering::SeqCst) {
+                log.write_all(format!("{}\n", next).as_bytes()).unwrap();
+                next += 1;
+                std::thread::sleep(Duration::from_micros(100));
+            }
+            next
+        })
+    };
+    let mut child = Command::new(env!("CARGO_BIN_EXE_tail"))
+        .args(["-f", "--timeout", "30", "-n", "3", &file])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    // Once the first line is out, tail has started up with the writer going
+    stdout.read_line(&mut line).unwrap();
+    lines.push(line.trim_end().parse::<u64>().unwrap());
+    stop.store(true, Ordering::SeqCst);
+    let written = writer.join().unwrap();
+    while *lines.last().unwrap() < written - 1 {
+        line.clear();
+        assert_ne!(stdout.read_line(&mut line).unwrap(), 0, "{:?}", lines.last());
+        lines.push(line.trim_end().parse().unwrap());
+    }
+    send_signal(child.id(), "TERM");
+    let mut rest = String::new();
+    stdout.read_to_string(&mut rest).unwrap();
+    assert_eq!(rest, "");
+    child.wait().unwrap();
+
+    // However far the writer had got, tail's lines carry on one from the
+    // next up to the last one written, with none missed or repeated
+    assert!(lines.len() >= 3, "{:?}", lines);
+    assert!(lines.windows(2).all(|pair| pair[1] == pair[0] + 1), "a gap or a repeat");
+}
+
+#[test]
+fn missing_file_fails_after_printing_the_others() {
+    let dir = test_dir("missing-file");
+    let present = write_file(&dir, "present.txt", b"a\nb\n");
+    let missing = dir.join("missing.txt").to_str().unwrap().to_string();
+    let output = tail(&[&missing, &present]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stdout(&output), format!("==> {} <==\na\nb\n", present));
+    assert_eq!(stderr(&output), format!("tail: cannot open '{}' for reading: No such file or directory\n", missing));
+
+    let output = tail(&[&present]);
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn a_retried_file_that_appears_is_no_failure() {
+    use std::io::{BufRead, BufReader};
+
+    let dir = test_dir("retry-status");
+    let present = write_file(&dir, "present.log", b"here\n");
+    let missing = dir.join("later.log").to_str().unwrap().to_string();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_tail"))
+        .args(["-F", "-q", "-s", "0.1", "--timeout", "30", &missing, &present])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut line = String::new();
+    // Printed once the missing file has been tried, so it's only found while
+    // following
+    stdout.read_line(&mut line).unwrap();
+    assert_eq!(line, "here\n");
+    fs::write(&missing, b"later\n").unwrap();
+    line.clear();
+    stdout.read_line(&mut line).unwrap();
+    assert_eq!(line, "later\n");
+    send_signal(child.id(), "TERM");
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+
+    // One that never does still is
+    let never = dir.join("never.log").to_str().unwrap().to_string();
+    let output = tail(&["-F", "-s", "0.1", "--timeout", "0.5", &present, &never]);
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn everything_after_double_dash_is_a_file_name() {
+    let dir = test_dir("double-dash");
+    write_file(&dir, "-file.log", b"1\n2\n3\n");
+    write_file(&dir, "-c", b"c\n");
+    let output = Command::new(env!("CARGO_BIN_EXE_tail"))
+        .current_dir(&dir)
+        .args(["-n", "1", "--", "-file.log"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "3\n");
+
+    // Neither is an option there, including the one that decides between
+    // lines and bytes
+    let output = Command::new(env!("CARGO_BIN_EXE_tail"))
+        .current_dir(&dir)
+        .args(["-n", "2", "-q", "--", "-file.log", "-c"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "2\n3\nc\n");
+
+    // Without it, the name is taken for options
+    let output = Command::new(env!("CARGO_BIN_EXE_tail")).current_dir(&dir).arg("-file.log").output().unwrap();
+    assert!(!output.status.success());
+}
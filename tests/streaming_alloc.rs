@@ -0,0 +1,154 @@
+//! Checks how much `read_all_streaming` and `RingBuffer` allocate, by counting
+//! every allocation made on the test's thread.
+
+extern crate tail;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::io::{self, Cursor, Write};
+use tail::{BackwardsReader, RingBuffer};
+
+struct Counting;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    static LIVE: Cell<usize> = const { Cell::new(0) };
+    static PEAK: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        note_alloc(layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        note_dealloc(layout.size());
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        note_dealloc(layout.size());
+        note_alloc(new_size);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+// A thread that's exiting has no counters left, and isn't being measured
+fn note_alloc(size: usize) {
+    let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+    let _ = LIVE.try_with(|live| {
+        live.set(live.get() + size);
+        let _ = PEAK.try_with(|peak| peak.set(peak.get().max(live.get())));
+    });
+}
+
+fn note_dealloc(size: usize) {
+    let _ = LIVE.try_with(|live| live.set(live.get().saturating_sub(size)));
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// What `f` allocated: how many times, and the most it held at once
+fn measure<F: FnOnce()>(f: F) -> (usize, usize) {
+    let allocations = ALLOCATIONS.with(Cell::get);
+    let live = LIVE.with(Cell::get);
+    PEAK.with(|peak| peak.set(live));
+    f();
+    (ALLOCATIONS.with(Cell::get) - allocations, PEAK.with(Cell::get) - live)
+}
+
+/// Throws the output away, having counted it, so the output itself isn't
+/// counted as the reader's memory
+struct CountingSink(usize);
+
+impl Write for CountingSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn lines(count: usize, len: usize) -> Vec<u8> {
+    let mut data = Vec::with_capacity(count * (len + 1));
+    for i in 0..count {
+        data.extend(std::iter::repeat_n(b'a' + (i % 26) as u8, len));
+        data.push(b'\n');
+    }
+    data
+}
+
+fn streamed(data: &[u8], n: usize) -> ((usize, usize), usize) {
+    let mut fd = Cursor::new(data);
+    let mut sink = CountingSink(0);
+    let measured = measure(|| {
+        BackwardsReader::new(n, &mut fd, b'\n').unwrap().read_all_streaming(&mut sink).unwrap();
+    });
+    (measured, sink.0)
+}
+
+fn read_all(data: &[u8], n: usize, streaming: bool) -> Vec<u8> {
+    let mut fd = Cursor::new(data);
+    let mut output = Vec::new();
+    let mut reader = BackwardsReader::new(n, &mut fd, b'\n').unwrap();
+    if streaming {
+        reader.read_all_streaming(&mut output).unwrap();
+    } else {
+        reader.read_all(&mut output).unwrap();
+    }
+    output
+}
+
+#[test]
+fn streaming_allocates_the_same_however_many_lines() {
+    let short = lines(10, 20);
+    let long = lines(10_000, 20);
+    let ((few_allocations, _), _) = streamed(&short, 5);
+    let ((many_allocations, _), written) = streamed(&long, 5_000);
+    assert_eq!(written, 5_000 * 21);
+    assert_eq!(few_allocations, many_allocations);
+}
+
+#[test]
+fn streaming_memory_is_bounded_by_the_buffer_not_the_lines() {
+    // The last 3 lines hold 3 MiB between them
+    let data = lines(3, 1 << 20);
+    let ((_, peak), written) = streamed(&data, 3);
+    assert_eq!(written, data.len());
+    assert!(peak <= 64 * 1024, "peak of {} bytes", peak);
+
+    // Whereas read_all holds the lines
+    let (_, holding) = measure(|| {
+        BackwardsReader::new(3, &mut Cursor::new(&data[..]), b'\n').unwrap()
+            .read_all(&mut CountingSink(0)).unwrap();
+    });
+    assert!(holding >= 3 << 20, "peak of {} bytes", holding);
+
+    // And the output is the same
+    assert_eq!(read_all(&data, 3, true), read_all(&data, 3, false));
+}
+
+#[test]
+fn streaming_writes_what_read_all_does() {
+    let data = lines(1_000, 20);
+    for &n in &[0, 1, 5, 999] {
+        assert_eq!(read_all(&data, n, true), read_all(&data, n, false), "-n {}", n);
+    }
+}
+
+#[test]
+fn ring_buffer_only_allocates_up_front() {
+    let (allocations, _) = measure(|| {
+        let mut last = RingBuffer::new(100);
+        for i in 0..100_000 {
+            last.push(i);
+        }
+        assert_eq!(last.iter().next(), Some(&99_900));
+    });
+    assert_eq!(allocations, 1);
+}